@@ -1,25 +1,75 @@
 use crate::{
-  backend::{GegBackend, GraphicsContext},
+  backend::{GegBackend, GraphicsContext, GraphicsOptions},
+  context::GegContext,
   events::GegEvent,
-  io::{to_geg_keycode, to_geg_mousebtn, ModifiersState},
+  gamepad::{to_geg_event, GamepadBackend, GamepadSource},
+  io::{to_geg_ime, to_geg_keycode, to_geg_mousebtn, to_geg_scroll_delta, Key},
   layer::Layer,
+  layer_stack::{LayerId, LayerStack},
+  replay::{InputRecording, Recorder, Replayer},
+  time::Time,
+  window::GegWindow,
 };
 
-use glam::DVec2;
+use glam::{DVec2, IVec2, UVec2};
+use spdlog::prelude::*;
 
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use winit::{
   event::{DeviceEvent, Event, WindowEvent},
   event_loop::{ControlFlow, EventLoop},
   platform::run_return::EventLoopExtRunReturn,
-  window::{Window, WindowBuilder},
+  window::WindowBuilder,
 };
 
+/// options for running without a window
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+  /// size of the offscreen image that is rendered into
+  pub width: u32,
+  pub height: u32,
+  /// number of frames to run, `None` runs until a layer asks to quit
+  pub max_frames: Option<u64>,
+}
+
+impl Default for HeadlessOptions {
+  fn default() -> Self {
+    Self {
+      width: 800,
+      height: 600,
+      max_frames: None,
+    }
+  }
+}
+
 pub struct GegAppOptions {
   pub name: String,
   pub backend: GegBackend,
+  /// msaa and the stencil attachment
+  pub graphics: GraphicsOptions,
+  /// skip the window and surface creation and render into an offscreen image
+  pub headless: Option<HeadlessOptions>,
+  /// how many times per second `Layer::on_fixed_update` runs
+  pub tick_rate: u32,
+  /// the most fixed updates that can run in a single frame
+  pub max_fixed_steps: u32,
+  /// where gamepad input comes from
+  pub gamepads: GamepadBackend,
+  /// record every event and frame delta time into this file, it's written when the app is
+  /// dropped, also when a layer panics
+  pub record: Option<PathBuf>,
+  /// feed the events and delta times recorded in this file to the layers instead of
+  /// the live input, the app quits when the recording ends
+  /// the live input is used if the file can't be loaded or has another `tick_rate` or
+  /// `max_fixed_steps`
+  pub replay: Option<PathBuf>,
+  /// pressing this key saves the frame to `screenshot-<unix time in ms>.png` in the working
+  /// directory, holding it down takes only one, recorded presses take screenshots while
+  /// replaying too
+  pub screenshot_key: Option<Key>,
 }
 
 impl Default for GegAppOptions {
@@ -27,22 +77,64 @@ impl Default for GegAppOptions {
     Self {
       name: "Geg App".to_string(),
       backend: GegBackend::Vulkano,
+      graphics: GraphicsOptions::default(),
+      headless: None,
+      tick_rate: 60,
+      max_fixed_steps: 5,
+      gamepads: GamepadBackend::default(),
+      record: None,
+      replay: None,
+      screenshot_key: None,
     }
   }
 }
 
 pub struct GegApp {
   name: String,
-  window: Arc<Window>,
   event_loop: Option<EventLoop<()>>,
-  layers: Vec<Box<dyn Layer>>,
+  headless: Option<HeadlessOptions>,
+  layers: LayerStack,
   last_frame_time: Instant,
-  modifier_state: ModifiersState,
-  graphics_context: GraphicsContext,
+  gamepad_source: Option<Box<dyn GamepadSource>>,
+  recorder: Option<Recorder>,
+  replayer: Option<Replayer>,
+  screenshot_key: Option<Key>,
+  context: GegContext,
 }
 
 impl GegApp {
   pub fn new(opts: GegAppOptions) -> Self {
+    let time = Time::new(opts.tick_rate, opts.max_fixed_steps);
+    let gamepad_source = opts.gamepads.into_source();
+    let recorder = opts
+      .record
+      .map(|path| Recorder::new(path, opts.tick_rate, opts.max_fixed_steps));
+    let replayer = opts.replay.and_then(|path| {
+      InputRecording::load(&path)
+        .and_then(|recording| Replayer::new(recording, opts.tick_rate, opts.max_fixed_steps))
+        .map_err(|e| error!("Running without replaying {}: {}", path.display(), e))
+        .ok()
+    });
+
+    if let Some(headless) = opts.headless {
+      let size = UVec2::new(headless.width, headless.height);
+      let window = GegWindow::new(None, opts.name.clone(), size);
+      let graphics_context = GraphicsContext::new(opts.backend, None, size.into(), opts.graphics);
+
+      return GegApp {
+        name: opts.name,
+        event_loop: None,
+        headless: Some(headless),
+        layers: LayerStack::new(),
+        last_frame_time: Instant::now(),
+        gamepad_source,
+        recorder,
+        replayer,
+        screenshot_key: opts.screenshot_key,
+        context: GegContext::new(window, graphics_context, time),
+      };
+    }
+
     let event_loop = EventLoop::new();
     let window = Arc::new(
       WindowBuilder::new()
@@ -50,44 +142,105 @@ impl GegApp {
         .build(&event_loop)
         .unwrap(),
     );
+    let size = window.inner_size();
+    let size = UVec2::new(size.width, size.height);
+    let graphics_context =
+      GraphicsContext::new(opts.backend, Some(window.clone()), size.into(), opts.graphics);
 
     GegApp {
-      name: opts.name,
-      window: window.clone(),
+      name: opts.name.clone(),
       event_loop: Some(event_loop),
-      layers: Vec::new(),
+      headless: None,
+      layers: LayerStack::new(),
       last_frame_time: Instant::now(),
-      modifier_state: ModifiersState::default(),
-      graphics_context: GraphicsContext::new(opts.backend, window),
+      gamepad_source,
+      recorder,
+      replayer,
+      screenshot_key: opts.screenshot_key,
+      context: GegContext::new(
+        GegWindow::new(Some(window), opts.name, size),
+        graphics_context,
+        time,
+      ),
     }
   }
 
   pub fn run(&mut self) {
+    // the time spent setting up isn't part of the first frame
+    self.last_frame_time = Instant::now();
+
+    if let Some(headless) = self.headless.clone() {
+      self.run_headless(headless);
+      return;
+    }
+
     self
       .event_loop
       .take()
       .unwrap()
-      .run_return(move |event, _, control_flow| {
-        let dt = self.last_frame_time.elapsed().as_secs_f32();
-        self.last_frame_time = Instant::now();
-
+      .run_return(|event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
 
         match event {
           Event::WindowEvent { event, .. } => match event {
             WindowEvent::CloseRequested => {
-              *control_flow = ControlFlow::Exit;
+              // a layer handling the event vetoes the close
+              if !self.dispatch_live(GegEvent::CloseRequested) {
+                *control_flow = ControlFlow::Exit;
+              }
             }
 
             WindowEvent::Resized(size) => {
-              // sel
+              self.resize(UVec2::new(size.width, size.height));
+            }
+
+            WindowEvent::Moved(pos) => {
+              self.dispatch_live(GegEvent::Moved(IVec2::new(pos.x, pos.y)));
+            }
+
+            WindowEvent::ScaleFactorChanged {
+              scale_factor,
+              new_inner_size,
+            } => {
+              self.dispatch_live(GegEvent::ScaleFactorChanged(scale_factor));
+              self.resize(UVec2::new(new_inner_size.width, new_inner_size.height));
+            }
+
+            WindowEvent::Focused(focused) => {
+              if focused {
+                self.dispatch_live(GegEvent::FocusGained);
+              } else {
+                self.dispatch_live(GegEvent::FocusLost);
+              }
+            }
+
+            WindowEvent::HoveredFile(path) => {
+              self.dispatch_live(GegEvent::FileHovered(path));
+            }
+
+            WindowEvent::HoveredFileCancelled => {
+              self.dispatch_live(GegEvent::FileHoverCancelled);
+            }
+
+            WindowEvent::DroppedFile(path) => {
+              self.dispatch_live(GegEvent::FileDropped(path));
+            }
+
+            WindowEvent::ReceivedCharacter(c) => {
+              self.dispatch_live(GegEvent::CharReceived(c));
             }
 
-            WindowEvent::ModifiersChanged(modifiers) => {
-              self.modifier_state.ctrl = modifiers.ctrl();
-              self.modifier_state.shift = modifiers.shift();
-              self.modifier_state.alt = modifiers.alt();
-              self.modifier_state.logo = modifiers.logo();
+            WindowEvent::Ime(ime) => {
+              self.dispatch_live(GegEvent::Ime(to_geg_ime(ime)));
+            }
+
+            // the recorded modifiers are used while replaying
+            WindowEvent::ModifiersChanged(modifiers) if self.replayer.is_none() => {
+              let modifier_state = &mut self.context.input.modifiers;
+              modifier_state.ctrl = modifiers.ctrl();
+              modifier_state.shift = modifiers.shift();
+              modifier_state.alt = modifiers.alt();
+              modifier_state.logo = modifiers.logo();
             }
 
             WindowEvent::KeyboardInput { input, .. } => {
@@ -101,17 +254,9 @@ impl GegApp {
               let is_down = input.state == winit::event::ElementState::Pressed;
 
               if is_down {
-                for layer in &mut self.layers {
-                  if layer.on_event(GegEvent::KeyDown(key), self.modifier_state) {
-                    break;
-                  }
-                }
+                self.dispatch_live(GegEvent::KeyDown(key));
               } else {
-                for layer in &mut self.layers {
-                  if layer.on_event(GegEvent::KeyUp(key), self.modifier_state) {
-                    break;
-                  };
-                }
+                self.dispatch_live(GegEvent::KeyUp(key));
               }
             }
 
@@ -119,34 +264,27 @@ impl GegApp {
               let is_down = state == winit::event::ElementState::Pressed;
 
               if is_down {
-                for layer in &mut self.layers {
-                  if layer.on_event(
-                    GegEvent::MouseButtonDown(to_geg_mousebtn(button)),
-                    self.modifier_state,
-                  ) {
-                    break;
-                  }
-                }
+                self.dispatch_live(GegEvent::MouseButtonDown(to_geg_mousebtn(button)));
               } else {
-                for layer in &mut self.layers {
-                  if layer.on_event(
-                    GegEvent::MouseButtonUp(to_geg_mousebtn(button)),
-                    self.modifier_state,
-                  ) {
-                    break;
-                  }
-                }
+                self.dispatch_live(GegEvent::MouseButtonUp(to_geg_mousebtn(button)));
               }
             }
 
             WindowEvent::CursorMoved { position, .. } => {
               let pos = DVec2::new(position.x, position.y);
+              self.dispatch_live(GegEvent::MouseMoved(pos));
+            }
 
-              for layer in &mut self.layers {
-                if layer.on_event(GegEvent::MouseMoved(pos), self.modifier_state) {
-                  break;
-                }
-              }
+            WindowEvent::CursorEntered { .. } => {
+              self.dispatch_live(GegEvent::CursorEntered);
+            }
+
+            WindowEvent::CursorLeft { .. } => {
+              self.dispatch_live(GegEvent::CursorLeft);
+            }
+
+            WindowEvent::MouseWheel { delta, .. } => {
+              self.dispatch_live(GegEvent::MouseWheel(to_geg_scroll_delta(delta)));
             }
 
             _ => (),
@@ -155,33 +293,348 @@ impl GegApp {
           Event::DeviceEvent { event, .. } => match event {
             DeviceEvent::MouseMotion { delta } => {
               let delta = DVec2::new(delta.0 as f64, delta.1 as f64);
-              for layer in &mut self.layers {
-                if layer.on_event(GegEvent::MouseRaw(delta), self.modifier_state) {
-                  break;
-                }
-              }
+              self.dispatch_live(GegEvent::MouseRaw(delta));
             }
             _ => (),
           },
 
           Event::MainEventsCleared => {
-            let layers = &mut self.layers;
-            for layer in layers.iter_mut() {
-              layer.on_update(dt);
-              self.graphics_context.update();
+            self.frame();
+
+            if self.context.exit_requested() {
+              *control_flow = ControlFlow::Exit;
             }
           }
 
           _ => (),
         }
       });
+
+    self.finish();
+  }
+
+  /// runs the layers and the renderer without a window or an event loop
+  fn run_headless(&mut self, opts: HeadlessOptions) {
+    let mut frame = 0;
+
+    loop {
+      if opts.max_frames.map_or(false, |max_frames| frame >= max_frames) {
+        break;
+      }
+
+      self.frame();
+      frame += 1;
+
+      if self.context.exit_requested() {
+        break;
+      }
+    }
+
+    self.finish();
+    debug!("Headless run finished after {} frames", frame);
+  }
+
+  /// runs the fixed updates, the update and renders, once per frame
+  fn frame(&mut self) {
+    self.poll_gamepads();
+
+    let mut dt = self.last_frame_time.elapsed().as_secs_f32();
+    self.last_frame_time = Instant::now();
+
+    if let Some(replayer) = &mut self.replayer {
+      let frame = match replayer.next_frame() {
+        Some(frame) => frame,
+        None => {
+          info!("Replay finished");
+          self.context.request_exit();
+          return;
+        }
+      };
+
+      // the recorded delta time keeps the fixed updates the same as the recorded run
+      dt = frame.dt;
+      for recorded in frame.events {
+        self.context.input.modifiers = recorded.modifiers;
+        self.dispatch(recorded.event);
+      }
+    }
+
+    let fixed_steps = self.context.time.advance(dt);
+    self.context.graphics.debug_draw().advance(dt);
+    let fixed_dt = self.context.time.fixed_delta();
+    for _ in 0..fixed_steps {
+      self.context.time.fixed_tick();
+      self.layers.on_fixed_update(&mut self.context, fixed_dt);
+    }
+
+    self.layers.on_update(&mut self.context, dt);
+    self.context.input.end_frame();
+    self.context.graphics.update();
+
+    if let Some(recorder) = &mut self.recorder {
+      recorder.end_frame(self.context.time.frame(), dt);
+    }
+  }
+
+  /// detaches the layers once the app stops running
+  fn finish(&mut self) {
+    self.layers.clear(&mut self.context);
+  }
+
+  fn poll_gamepads(&mut self) {
+    let mut events = Vec::new();
+    if let Some(source) = &mut self.gamepad_source {
+      source.poll(&mut events);
+    }
+
+    for event in events {
+      self.dispatch_live(to_geg_event(event));
+    }
+  }
+
+  /// passes an event from the window or the gamepads to the layers,
+  /// they are ignored while replaying a recording
+  fn dispatch_live(&mut self, event: GegEvent) -> bool {
+    if self.replayer.is_some() {
+      return false;
+    }
+
+    self.dispatch(event)
+  }
+
+  /// passes an event to the layers, returns `true` if a layer handled it
+  fn dispatch(&mut self, event: GegEvent) -> bool {
+    if let Some(recorder) = &mut self.recorder {
+      recorder.record_event(&event, self.context.input.modifiers);
+    }
+
+    // held keys repeat their key down events, only the first one takes a screenshot
+    let screenshot = matches!(event, GegEvent::KeyDown(key)
+      if Some(key) == self.screenshot_key && !self.context.input.is_key_down(key));
+    self.context.input.process_event(&event);
+
+    if screenshot {
+      self.save_screenshot();
+    }
+
+    let modifiers = self.context.input.modifiers;
+    self.layers.on_event(&mut self.context, event, modifiers)
+  }
+
+  fn save_screenshot(&mut self) {
+    let time = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_millis();
+    self
+      .context
+      .graphics
+      .save_screenshot(format!("screenshot-{}.png", time));
+  }
+
+  fn resize(&mut self, size: UVec2) {
+    self.context.window.set_size(size);
+    self.context.graphics.resize(size.x, size.y);
+    self.dispatch_live(GegEvent::Resized(size));
+  }
+
+  /// Pushes a layer on top of the normal layers, below every overlay.
+  pub fn push_layer(&mut self, layer: Box<dyn Layer>) -> LayerId {
+    self.layers.push_layer(layer, &mut self.context)
   }
 
+  #[deprecated(note = "use `push_layer`, it returns the id to remove the layer with")]
   pub fn add_layer(&mut self, layer: Box<dyn Layer>) {
-    self.layers.push(layer);
+    self.push_layer(layer);
+  }
+
+  /// Pushes an overlay on top of every layer, it gets events first.
+  pub fn push_overlay(&mut self, layer: Box<dyn Layer>) -> LayerId {
+    self.layers.push_overlay(layer, &mut self.context)
+  }
+
+  pub fn pop_layer(&mut self) -> Option<Box<dyn Layer>> {
+    self.layers.pop_layer(&mut self.context)
+  }
+
+  pub fn pop_overlay(&mut self) -> Option<Box<dyn Layer>> {
+    self.layers.pop_overlay(&mut self.context)
+  }
+
+  pub fn remove_layer(&mut self, id: LayerId) -> Option<Box<dyn Layer>> {
+    self.layers.remove(id, &mut self.context)
+  }
+
+  pub fn layers(&self) -> &LayerStack {
+    &self.layers
+  }
+
+  pub fn context(&mut self) -> &mut GegContext {
+    &mut self.context
   }
 
   pub fn name(&self) -> &str {
     &self.name
   }
 }
+
+impl Drop for GegApp {
+  fn drop(&mut self) {
+    // saved here so a run that panics keeps its recording, before the layers are
+    // detached in case that panics again
+    if let Some(recorder) = self.recorder.take() {
+      recorder.save();
+    }
+
+    // layers pushed without ever running still get detached
+    self.layers.clear(&mut self.context);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gamepad::{GamepadAxis, GamepadButton, GamepadId, MockGamepadSource};
+  use crate::traits::PassTarget;
+  use glam::Vec3;
+  use std::sync::Mutex;
+
+  /// drives a gamepad through the mock source while recording and logs what it sees,
+  /// draws a debug line every frame and quits after `frames`
+  struct Probe {
+    source: Option<MockGamepadSource>,
+    frames: u64,
+    log: Arc<Mutex<Vec<String>>>,
+  }
+
+  impl Layer for Probe {
+    fn on_update(&mut self, ctx: &mut GegContext, dt: f32) {
+      let id = GamepadId(0);
+      let frame = ctx.time().frame();
+      if let Some(source) = &self.source {
+        match frame {
+          1 => source.connect(id, "mock"),
+          2 => source.press(id, GamepadButton::A),
+          3 => source.move_axis(id, GamepadAxis::LeftX, 0.5),
+          4 => source.release(id, GamepadButton::A),
+          _ => (),
+        }
+      }
+
+      let pad = ctx.input().gamepad(id);
+      self.log.lock().unwrap().push(format!(
+        "{} {} {} {:?} {:?}",
+        frame,
+        dt,
+        ctx.time().fixed_ticks(),
+        pad.map(|pad| pad.is_button_down(GamepadButton::A)),
+        pad.map(|pad| pad.axis(GamepadAxis::LeftX)),
+      ));
+
+      ctx.debug_draw().line(Vec3::ZERO, Vec3::ONE, Vec3::ONE);
+      if frame == self.frames {
+        ctx.request_exit();
+      }
+    }
+  }
+
+  fn headless_app(
+    gamepads: GamepadBackend,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+  ) -> GegApp {
+    GegApp::new(GegAppOptions {
+      backend: GegBackend::Null,
+      headless: Some(HeadlessOptions {
+        width: 64,
+        height: 64,
+        max_frames: Some(10),
+      }),
+      gamepads,
+      record,
+      replay,
+      ..GegAppOptions::default()
+    })
+  }
+
+  #[test]
+  fn headless_frames_are_submitted() {
+    let mut app = headless_app(GamepadBackend::Disabled, None, None);
+    let log = app.context().graphics().log().unwrap().clone();
+    let probe_log = Arc::new(Mutex::new(Vec::new()));
+    app.push_layer(Box::new(Probe {
+      source: None,
+      frames: 3,
+      log: probe_log.clone(),
+    }));
+    app.run();
+
+    assert_eq!(probe_log.lock().unwrap().len(), 3);
+    assert_eq!(log.frames().len(), 3);
+    let draws = log.last_frame_draws();
+    assert_eq!(draws.len(), 1);
+    assert_eq!(draws[0].target, PassTarget::Screen);
+    assert_eq!(draws[0].elements, 0..2);
+  }
+
+  #[test]
+  fn a_replay_matches_the_recorded_run() {
+    let path = std::env::temp_dir().join(format!("geg-replay-{}.ron", std::process::id()));
+
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    let source = MockGamepadSource::new();
+    let mut app = headless_app(
+      GamepadBackend::Custom(Box::new(source.clone())),
+      Some(path.clone()),
+      None,
+    );
+    app.push_layer(Box::new(Probe {
+      source: Some(source),
+      frames: 6,
+      log: recorded.clone(),
+    }));
+    app.run();
+    drop(app);
+
+    let replayed = Arc::new(Mutex::new(Vec::new()));
+    let mut app = headless_app(GamepadBackend::Disabled, None, Some(path.clone()));
+    app.push_layer(Box::new(Probe {
+      source: None,
+      frames: 6,
+      log: replayed.clone(),
+    }));
+    app.run();
+    let _ = std::fs::remove_file(&path);
+
+    let recorded = recorded.lock().unwrap();
+    assert_eq!(recorded.len(), 6);
+    assert_eq!(*recorded, *replayed.lock().unwrap());
+    assert!(recorded[2].ends_with("Some(true) Some(0.0)"));
+    // the button was released and the stick moved past the deadzone
+    assert!(recorded[4].contains("Some(false)") && !recorded[4].ends_with("Some(0.0)"));
+  }
+
+  struct Panics;
+
+  impl Layer for Panics {
+    fn on_update(&mut self, ctx: &mut GegContext, _dt: f32) {
+      if ctx.time().frame() == 3 {
+        panic!("the layer failed");
+      }
+    }
+  }
+
+  #[test]
+  fn a_run_that_panics_keeps_its_recording() {
+    let path = std::env::temp_dir().join(format!("geg-panic-{}.ron", std::process::id()));
+    let mut app = headless_app(GamepadBackend::Disabled, Some(path.clone()), None);
+    app.push_layer(Box::new(Panics));
+    let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || app.run()));
+    assert!(run.is_err());
+
+    let recording = InputRecording::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(recording.frames.len(), 2);
+    assert_eq!(recording.max_fixed_steps, 5);
+  }
+}
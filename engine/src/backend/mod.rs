@@ -1,37 +1,402 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use winit::window::Window;
 
+use glam::Mat4;
+use image::RgbaImage;
+use spdlog::prelude::*;
+
+use self::null::{GraphicsLog, NullDevice};
 use self::vulkan::{device::GegVkDevice, renderer::GegVkRenderer};
+use crate::camera::{Camera, CameraUniform};
+use crate::debug_draw::DebugDraw;
+use crate::mesh::{Mesh, MeshInstance, Submesh};
+use crate::shader::ShaderWatcher;
+use crate::traits::{
+  BufferDesc, BufferHandle, BufferUsage, CommandEncoder, CommandList, DescriptorSetDesc,
+  DescriptorSetHandle, GraphicsDevice, GraphicsError, PipelineDesc, PipelineHandle,
+};
 
+pub mod null;
 mod vulkan;
+#[cfg(feature = "wgpu")]
+mod webgpu;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GegBackend {
   Vulkano,
+  /// only exists with the `wgpu` cargo feature, `WGPU_BACKEND=gl` forces the gl fallback
+  #[cfg(feature = "wgpu")]
   Wgpu,
+  /// creates no gpu objects and logs every call instead, see `GraphicsContext::log`
+  /// together with `GegAppOptions::headless` the app runs without a gpu or a display
+  Null,
+}
+
+/// how the passes are rendered, fixed when the context is created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphicsOptions {
+  /// samples per pixel, 1 disables msaa
+  /// lowered to the closest count the device supports
+  pub msaa_samples: u32,
+  /// adds a stencil to the depth attachment
+  pub stencil: bool,
+  /// how many frames the cpu can record ahead of the gpu, at least 1
+  /// only used by the vulkan backend, wgpu keeps its own queue of frames
+  pub frames_in_flight: u32,
+}
+
+impl Default for GraphicsOptions {
+  fn default() -> Self {
+    Self {
+      msaa_samples: 1,
+      stencil: false,
+      frames_in_flight: 2,
+    }
+  }
+}
+
+/// what is done with the next submitted frame once it's read back
+enum CaptureRequest {
+  Save(PathBuf),
+  Keep,
 }
 
 pub struct GraphicsContext {
-  device: GegVkDevice,
-  backend_type: GegBackend,
-  renderer: GegVkRenderer,
+  device: Box<dyn GraphicsDevice>,
+  frame: CommandList,
+  frame_count: u64,
+  log: Option<GraphicsLog>,
+  // the transforms of the meshes drawn this frame and the buffer they are uploaded to
+  instances: Vec<MeshInstance>,
+  instance_buffer: Option<(BufferHandle, usize)>,
+  shaders: ShaderWatcher,
+  // written every frame and bound at set 0 by the mesh draws
+  camera: Camera,
+  camera_buffer: BufferHandle,
+  camera_set: DescriptorSetHandle,
+  debug_draw: DebugDraw,
+  capture: Option<CaptureRequest>,
+  captured: Option<RgbaImage>,
 }
 
 impl GraphicsContext {
-  pub fn new(backend_type: GegBackend, win: Arc<Window>) -> Self {
-    let device = GegVkDevice::new(win);
+  /// creates a context that renders to `win`, or to an offscreen image of `dimensions`
+  /// if there is no window (headless)
+  pub fn new(
+    backend_type: GegBackend,
+    win: Option<Arc<Window>>,
+    dimensions: [u32; 2],
+    options: GraphicsOptions,
+  ) -> Self {
+    let mut log = None;
+    let mut device: Box<dyn GraphicsDevice> = match backend_type {
+      GegBackend::Vulkano => create_vulkano(win, dimensions, options),
+      #[cfg(feature = "wgpu")]
+      GegBackend::Wgpu => create_wgpu(win, dimensions, options),
+      GegBackend::Null => {
+        let null_log = GraphicsLog::new();
+        log = Some(null_log.clone());
+        Box::new(NullDevice::new(null_log, dimensions, options))
+      }
+    };
+    info!("Using the {:?} backend", device.backend());
+
+    let mut camera = Camera::default();
+    let [width, height] = device.surface_size();
+    camera.resize(width, height);
+
+    let camera_buffer = device
+      .create_buffer(
+        &BufferDesc {
+          size: std::mem::size_of::<CameraUniform>() as u64,
+          usage: BufferUsage::Uniform,
+        },
+        Some(bytemuck::bytes_of(&camera.uniform())),
+      )
+      .expect("failed to create the camera buffer");
+    let camera_set = device
+      .create_descriptor_set(&DescriptorSetDesc::uniform_buffers(&[camera_buffer]))
+      .expect("failed to create the camera descriptor set");
+
     Self {
-      device: device.clone(),
-      renderer: GegVkRenderer::new(device),
-      backend_type,
+      device,
+      frame: CommandList::new(),
+      frame_count: 0,
+      log,
+      instances: Vec::new(),
+      instance_buffer: None,
+      shaders: ShaderWatcher::new(),
+      camera,
+      camera_buffer,
+      camera_set,
+      debug_draw: DebugDraw::default(),
+      capture: None,
+      captured: None,
+    }
+  }
+
+  /// the calls made to the null backend, `None` for the other backends
+  pub fn log(&self) -> Option<&GraphicsLog> {
+    self.log.as_ref()
+  }
+
+  /// the device used to create and destroy gpu resources
+  pub fn device(&mut self) -> &mut dyn GraphicsDevice {
+    self.device.as_mut()
+  }
+
+  pub fn backend(&self) -> GegBackend {
+    self.device.backend()
+  }
+
+  /// the number of frames submitted so far
+  pub fn frame_count(&self) -> u64 {
+    self.frame_count
+  }
+
+  /// the commands of the current frame, submitted after all the layers are updated
+  pub fn encoder(&mut self) -> &mut CommandList {
+    &mut self.frame
+  }
+
+  /// the camera meshes are drawn with, its aspect ratio follows the screen
+  pub fn camera(&self) -> &Camera {
+    &self.camera
+  }
+
+  pub fn camera_mut(&mut self) -> &mut Camera {
+    &mut self.camera
+  }
+
+  /// replaces the camera, its aspect ratio is set to the screen's
+  pub fn set_camera(&mut self, mut camera: Camera) {
+    let [width, height] = self.device.surface_size();
+    camera.resize(width, height);
+    self.camera = camera;
+  }
+
+  /// the descriptor set holding the `CameraUniform` of this frame
+  pub fn camera_set(&self) -> DescriptorSetHandle {
+    self.camera_set
+  }
+
+  /// shapes drawn over the screen for debugging, they are added when the frame is submitted
+  pub fn debug_draw(&mut self) -> &mut DebugDraw {
+    &mut self.debug_draw
+  }
+
+  /// records the debug lines of this frame into the current pass, so they are hidden behind
+  /// the scene drawn in it when the screen pass only shows a post processed copy of the scene
+  /// the lines queued after it are not drawn this frame
+  pub fn render_debug_lines(&mut self) {
+    let mut debug_draw = std::mem::take(&mut self.debug_draw);
+    debug_draw.render_lines_in_pass(self);
+    self.debug_draw = debug_draw;
+  }
+
+  /// saves the current frame to a png at `path` once it's submitted
+  pub fn save_screenshot(&mut self, path: impl Into<PathBuf>) {
+    self.capture = Some(CaptureRequest::Save(path.into()));
+  }
+
+  /// reads the current frame back once it's submitted, see `take_capture`
+  pub fn capture_frame(&mut self) {
+    self.capture = Some(CaptureRequest::Keep);
+  }
+
+  /// the frame read back after `capture_frame`, `None` until the frame is submitted
+  pub fn take_capture(&mut self) -> Option<RgbaImage> {
+    self.captured.take()
+  }
+
+  /// creates a pipeline on the device, it's rebuilt whenever one of its shader files changes
+  pub fn create_pipeline(&mut self, desc: &PipelineDesc) -> Result<PipelineHandle, GraphicsError> {
+    let pipeline = self.device.create_pipeline(desc)?;
+    self.shaders.watch(pipeline, desc);
+    Ok(pipeline)
+  }
+
+  pub fn destroy_pipeline(&mut self, pipeline: PipelineHandle) {
+    self.shaders.unwatch(pipeline);
+    self.device.destroy_pipeline(pipeline);
+  }
+
+  /// records a draw of every submesh of `mesh` into the current pass
+  /// `pipeline` must take the mesh vertices in slot 0 and a `MeshInstance` in slot 1,
+  /// the camera is bound at set 0
+  pub fn draw_mesh(&mut self, mesh: &Mesh, pipeline: PipelineHandle, transform: Mat4) {
+    self.encode_mesh(mesh, mesh.submeshes(), pipeline, transform);
+  }
+
+  /// like `draw_mesh` but only draws the submesh at `index`
+  pub fn draw_submesh(
+    &mut self,
+    mesh: &Mesh,
+    index: usize,
+    pipeline: PipelineHandle,
+    transform: Mat4,
+  ) {
+    match mesh.submeshes().get(index) {
+      Some(submesh) => self.encode_mesh(mesh, std::slice::from_ref(submesh), pipeline, transform),
+      None => warn!("Drawing a submesh that doesn't exist: {}", index),
     }
   }
 
   pub fn update(&mut self) {
-    self.renderer.render();
+    self.reload_shaders();
+
+    if !self.instances.is_empty() {
+      self.upload_instances();
+    }
+
+    // the debug shapes record into the context they belong to
+    let mut debug_draw = std::mem::take(&mut self.debug_draw);
+    debug_draw.render(self);
+    self.debug_draw = debug_draw;
+
+    let camera = self.camera.uniform();
+    if let Err(e) = self
+      .device
+      .write_buffer(self.camera_buffer, 0, bytemuck::bytes_of(&camera))
+    {
+      error!("Failed to upload the camera: {}", e);
+    }
+
+    match self.capture.take() {
+      Some(request) => self.submit_and_capture(request),
+      None => self.device.submit(&self.frame),
+    }
+    self.frame.clear();
+    self.frame_count += 1;
+    self.instances.clear();
+  }
+
+  fn submit_and_capture(&mut self, request: CaptureRequest) {
+    let image = match self.device.submit_and_capture(&self.frame) {
+      Ok(image) => image,
+      Err(e) => {
+        error!("Failed to capture frame {}: {}", self.frame_count, e);
+        return;
+      }
+    };
+
+    match request {
+      CaptureRequest::Save(path) => match image.save(&path) {
+        Ok(()) => info!("Saved a screenshot to {}", path.display()),
+        Err(e) => error!("Failed to save a screenshot to {}: {}", path.display(), e),
+      },
+      CaptureRequest::Keep => self.captured = Some(image),
+    }
   }
 
   pub fn resize(&mut self, width: u32, height: u32) {
-    // self.device.resize(width, height);
+    self.device.resize(width, height);
+    self.camera.resize(width, height);
+  }
+
+  fn encode_mesh(
+    &mut self,
+    mesh: &Mesh,
+    submeshes: &[Submesh],
+    pipeline: PipelineHandle,
+    transform: Mat4,
+  ) {
+    let instance_buffer = match self.instance_buffer {
+      Some((buffer, _)) => buffer,
+      None => {
+        let capacity = 64;
+        let buffer = create_instance_buffer(self.device.as_mut(), capacity);
+        self.instance_buffer = Some((buffer, capacity));
+        buffer
+      }
+    };
+
+    let instance = self.instances.len() as u32;
+    self.instances.push(MeshInstance::new(transform));
+
+    self.frame.set_pipeline(pipeline);
+    self.frame.set_vertex_buffer(0, mesh.vertex_buffer());
+    self.frame.set_vertex_buffer(1, instance_buffer);
+    self.frame.set_descriptor_set(0, self.camera_set);
+    if let Some(index_buffer) = mesh.index_buffer() {
+      self.frame.set_index_buffer(index_buffer);
+    }
+
+    for submesh in submeshes {
+      if mesh.is_indexed() {
+        self.frame.draw_indexed(
+          submesh.range.clone(),
+          submesh.base_vertex,
+          instance..instance + 1,
+        );
+      } else {
+        self.frame.draw(submesh.range.clone(), instance..instance + 1);
+      }
+    }
   }
+
+  /// rebuilds the pipelines whose shaders changed, broken shaders keep the old pipeline
+  fn reload_shaders(&mut self) {
+    for (pipeline, desc) in self.shaders.changed() {
+      match self.device.recreate_pipeline(pipeline, &desc) {
+        Ok(()) => info!("Reloaded the shaders of {:?}", pipeline),
+        Err(e) => error!("Failed to reload the shaders of {:?}: {}", pipeline, e),
+      }
+    }
+  }
+
+  /// writes the transforms of this frame, the buffer is replaced if they don't fit
+  fn upload_instances(&mut self) {
+    let (mut buffer, capacity) = match self.instance_buffer {
+      Some(instance_buffer) => instance_buffer,
+      None => return,
+    };
+
+    if self.instances.len() > capacity {
+      let capacity = self.instances.len().next_power_of_two();
+      let new_buffer = create_instance_buffer(self.device.as_mut(), capacity);
+      self.frame.replace_buffer(buffer, new_buffer);
+      self.device.destroy_buffer(buffer);
+
+      buffer = new_buffer;
+      self.instance_buffer = Some((buffer, capacity));
+    }
+
+    if let Err(e) = self
+      .device
+      .write_buffer(buffer, 0, bytemuck::cast_slice(&self.instances))
+    {
+      error!("Failed to upload the mesh transforms: {}", e);
+    }
+  }
+}
+
+fn create_instance_buffer(device: &mut dyn GraphicsDevice, capacity: usize) -> BufferHandle {
+  device
+    .create_buffer(
+      &BufferDesc {
+        size: (capacity * std::mem::size_of::<MeshInstance>()) as u64,
+        usage: BufferUsage::Vertex,
+      },
+      None,
+    )
+    .expect("failed to create the mesh instance buffer")
+}
+
+fn create_vulkano(
+  win: Option<Arc<Window>>,
+  dimensions: [u32; 2],
+  options: GraphicsOptions,
+) -> Box<dyn GraphicsDevice> {
+  Box::new(GegVkRenderer::new(GegVkDevice::new(win), dimensions, options))
+}
+
+#[cfg(feature = "wgpu")]
+fn create_wgpu(
+  win: Option<Arc<Window>>,
+  dimensions: [u32; 2],
+  options: GraphicsOptions,
+) -> Box<dyn GraphicsDevice> {
+  Box::new(webgpu::renderer::GegWgpuRenderer::new(win, dimensions, options))
 }
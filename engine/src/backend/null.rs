@@ -0,0 +1,719 @@
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use crate::backend::{GegBackend, GraphicsOptions};
+use crate::traits::{
+  check_texture_write, BufferDesc, BufferHandle, BufferUsage, BuiltinPipeline, CommandList,
+  DescriptorResource, DescriptorSetDesc, DescriptorSetHandle, GraphicsDevice, GraphicsError,
+  PassTarget, PipelineDesc, PipelineHandle, RenderCommand, RenderTargetDesc,
+  RenderTargetHandle, SamplerDesc, SamplerHandle, TextureDesc, TextureFormat, TextureHandle,
+};
+
+/// a call made to the null backend
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphicsCall {
+  Resize {
+    width: u32,
+    height: u32,
+  },
+  CreateBuffer {
+    handle: BufferHandle,
+    desc: BufferDesc,
+  },
+  WriteBuffer {
+    handle: BufferHandle,
+    offset: u64,
+    size: u64,
+    /// the written bytes, so tests can check what was uploaded
+    data: Vec<u8>,
+  },
+  DestroyBuffer(BufferHandle),
+  CreateTexture {
+    handle: TextureHandle,
+    desc: TextureDesc,
+  },
+  WriteTexture {
+    handle: TextureHandle,
+    origin: [u32; 2],
+    size: [u32; 2],
+  },
+  DestroyTexture(TextureHandle),
+  CreateSampler {
+    handle: SamplerHandle,
+    desc: SamplerDesc,
+  },
+  DestroySampler(SamplerHandle),
+  CreatePipeline {
+    handle: PipelineHandle,
+    desc: PipelineDesc,
+  },
+  /// only logged the first time a builtin pipeline is asked for
+  CreateBuiltinPipeline {
+    handle: PipelineHandle,
+    pipeline: BuiltinPipeline,
+  },
+  RecreatePipeline {
+    handle: PipelineHandle,
+    desc: PipelineDesc,
+  },
+  DestroyPipeline(PipelineHandle),
+  CreateRenderTarget {
+    handle: RenderTargetHandle,
+    desc: RenderTargetDesc,
+  },
+  DestroyRenderTarget(RenderTargetHandle),
+  CreateDescriptorSet {
+    handle: DescriptorSetHandle,
+    desc: DescriptorSetDesc,
+  },
+  DestroyDescriptorSet(DescriptorSetHandle),
+  /// a frame, `index` counts the submitted frames from 0
+  Submit {
+    index: u64,
+    commands: Vec<RenderCommand>,
+  },
+}
+
+/// a draw with the state bound when it was issued
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawCall {
+  pub target: PassTarget,
+  pub pipeline: Option<PipelineHandle>,
+  /// the vertex buffers by slot
+  pub vertex_buffers: Vec<(u32, BufferHandle)>,
+  /// `None` for non indexed draws
+  pub index_buffer: Option<BufferHandle>,
+  /// the descriptor sets by index
+  pub descriptor_sets: Vec<(u32, DescriptorSetHandle)>,
+  /// vertices for `Draw`, indices for `DrawIndexed`
+  pub elements: Range<u32>,
+  pub instances: Range<u32>,
+}
+
+/// the calls made to a null device
+/// clones share the same log, so a test can keep one while the app owns the device
+#[derive(Debug, Clone, Default)]
+pub struct GraphicsLog {
+  calls: Arc<Mutex<Vec<GraphicsCall>>>,
+}
+
+impl GraphicsLog {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// every call made since the device was created or the log was cleared
+  pub fn calls(&self) -> Vec<GraphicsCall> {
+    self.calls.lock().unwrap().clone()
+  }
+
+  pub fn clear(&self) {
+    self.calls.lock().unwrap().clear();
+  }
+
+  /// the commands of every submitted frame, oldest first
+  pub fn frames(&self) -> Vec<Vec<RenderCommand>> {
+    self
+      .calls
+      .lock()
+      .unwrap()
+      .iter()
+      .filter_map(|call| match call {
+        GraphicsCall::Submit { commands, .. } => Some(commands.clone()),
+        _ => None,
+      })
+      .collect()
+  }
+
+  pub fn last_frame(&self) -> Option<Vec<RenderCommand>> {
+    self.frames().pop()
+  }
+
+  /// the draws of the last submitted frame
+  pub fn last_frame_draws(&self) -> Vec<DrawCall> {
+    self
+      .last_frame()
+      .map(|commands| Self::draws(&commands))
+      .unwrap_or_default()
+  }
+
+  /// replays `commands` and collects their draws
+  pub fn draws(commands: &[RenderCommand]) -> Vec<DrawCall> {
+    let mut draws = Vec::new();
+    let mut target = PassTarget::Screen;
+    let mut pipeline = None;
+    let mut vertex_buffers: Vec<(u32, BufferHandle)> = Vec::new();
+    let mut index_buffer = None;
+    let mut descriptor_sets: Vec<(u32, DescriptorSetHandle)> = Vec::new();
+
+    for command in commands {
+      match command {
+        RenderCommand::BeginPass { target: pass, .. } => {
+          // nothing stays bound between passes
+          target = *pass;
+          pipeline = None;
+          vertex_buffers.clear();
+          index_buffer = None;
+          descriptor_sets.clear();
+        }
+        RenderCommand::EndPass => (),
+        RenderCommand::SetPipeline(handle) => pipeline = Some(*handle),
+        RenderCommand::SetVertexBuffer { slot, buffer } => {
+          vertex_buffers.retain(|(bound, _)| bound != slot);
+          vertex_buffers.push((*slot, *buffer));
+          vertex_buffers.sort_by_key(|(slot, _)| *slot);
+        }
+        RenderCommand::SetIndexBuffer(buffer) => index_buffer = Some(*buffer),
+        RenderCommand::SetDescriptorSet { index, set } => {
+          descriptor_sets.retain(|(bound, _)| bound != index);
+          descriptor_sets.push((*index, *set));
+          descriptor_sets.sort_by_key(|(index, _)| *index);
+        }
+        RenderCommand::Draw {
+          vertices,
+          instances,
+        } => draws.push(DrawCall {
+          target,
+          pipeline,
+          vertex_buffers: vertex_buffers.clone(),
+          index_buffer: None,
+          descriptor_sets: descriptor_sets.clone(),
+          elements: vertices.clone(),
+          instances: instances.clone(),
+        }),
+        RenderCommand::DrawIndexed {
+          indices, instances, ..
+        } => draws.push(DrawCall {
+          target,
+          pipeline,
+          vertex_buffers: vertex_buffers.clone(),
+          index_buffer,
+          descriptor_sets: descriptor_sets.clone(),
+          elements: indices.clone(),
+          instances: instances.clone(),
+        }),
+      }
+    }
+
+    draws
+  }
+
+  fn push(&self, call: GraphicsCall) {
+    self.calls.lock().unwrap().push(call);
+  }
+}
+
+/// a device that creates no gpu objects, it only checks the calls and logs them
+/// meant for tests and machines without a gpu
+pub(crate) struct NullDevice {
+  log: GraphicsLog,
+  surface_size: [u32; 2],
+  msaa_samples: u32,
+  next_handle: u64,
+  frame: u64,
+  buffers: HashMap<u64, BufferDesc>,
+  // the size and format of each texture, no format for the textures of render targets
+  textures: HashMap<u64, ([u32; 2], Option<TextureFormat>)>,
+  samplers: HashMap<u64, SamplerDesc>,
+  pipelines: HashMap<u64, Option<BuiltinPipeline>>,
+  builtin_pipelines: HashMap<BuiltinPipeline, PipelineHandle>,
+  // the texture of each render target
+  render_targets: HashMap<u64, TextureHandle>,
+  descriptor_sets: HashMap<u64, DescriptorSetDesc>,
+}
+
+impl NullDevice {
+  /// every sample count is accepted, they are only reported back
+  pub fn new(log: GraphicsLog, surface_size: [u32; 2], options: GraphicsOptions) -> Self {
+    Self {
+      log,
+      surface_size,
+      msaa_samples: options.msaa_samples.max(1),
+      next_handle: 0,
+      frame: 0,
+      buffers: HashMap::new(),
+      textures: HashMap::new(),
+      samplers: HashMap::new(),
+      pipelines: HashMap::new(),
+      builtin_pipelines: HashMap::new(),
+      render_targets: HashMap::new(),
+      descriptor_sets: HashMap::new(),
+    }
+  }
+
+  fn next_handle(&mut self) -> u64 {
+    self.next_handle += 1;
+    self.next_handle
+  }
+}
+
+impl GraphicsDevice for NullDevice {
+  fn backend(&self) -> GegBackend {
+    GegBackend::Null
+  }
+
+  fn surface_size(&self) -> [u32; 2] {
+    self.surface_size
+  }
+
+  fn msaa_samples(&self) -> u32 {
+    self.msaa_samples
+  }
+
+  fn resize(&mut self, width: u32, height: u32) {
+    self.surface_size = [width, height];
+    self.log.push(GraphicsCall::Resize { width, height });
+  }
+
+  fn create_buffer(
+    &mut self,
+    desc: &BufferDesc,
+    data: Option<&[u8]>,
+  ) -> Result<BufferHandle, GraphicsError> {
+    if desc.size == 0 {
+      return Err(GraphicsError::InvalidSize {
+        expected: 1,
+        actual: 0,
+      });
+    }
+    if let Some(data) = data {
+      if data.len() as u64 != desc.size {
+        return Err(GraphicsError::InvalidSize {
+          expected: desc.size,
+          actual: data.len() as u64,
+        });
+      }
+    }
+
+    let handle = BufferHandle(self.next_handle());
+    self.buffers.insert(handle.0, desc.clone());
+    self.log.push(GraphicsCall::CreateBuffer {
+      handle,
+      desc: desc.clone(),
+    });
+    Ok(handle)
+  }
+
+  fn write_buffer(
+    &mut self,
+    buffer: BufferHandle,
+    offset: u64,
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    let size = self
+      .buffers
+      .get(&buffer.0)
+      .ok_or(GraphicsError::InvalidHandle)?
+      .size;
+    let end = offset + data.len() as u64;
+    if end > size {
+      return Err(GraphicsError::InvalidSize {
+        expected: size,
+        actual: end,
+      });
+    }
+
+    self.log.push(GraphicsCall::WriteBuffer {
+      handle: buffer,
+      offset,
+      size: data.len() as u64,
+      data: data.to_vec(),
+    });
+    Ok(())
+  }
+
+  fn destroy_buffer(&mut self, buffer: BufferHandle) {
+    if self.buffers.remove(&buffer.0).is_some() {
+      self.log.push(GraphicsCall::DestroyBuffer(buffer));
+    }
+  }
+
+  fn create_texture(
+    &mut self,
+    desc: &TextureDesc,
+    data: Option<&[u8]>,
+  ) -> Result<TextureHandle, GraphicsError> {
+    let size = desc.width as u64 * desc.height as u64 * desc.format.pixel_size() as u64;
+    if let Some(data) = data {
+      if data.len() as u64 != size {
+        return Err(GraphicsError::InvalidSize {
+          expected: size,
+          actual: data.len() as u64,
+        });
+      }
+    }
+
+    let handle = TextureHandle(self.next_handle());
+    self
+      .textures
+      .insert(handle.0, ([desc.width, desc.height], Some(desc.format)));
+    self.log.push(GraphicsCall::CreateTexture {
+      handle,
+      desc: desc.clone(),
+    });
+    Ok(handle)
+  }
+
+  fn write_texture(
+    &mut self,
+    texture: TextureHandle,
+    origin: [u32; 2],
+    size: [u32; 2],
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    let (dimensions, format) = *self
+      .textures
+      .get(&texture.0)
+      .ok_or(GraphicsError::InvalidHandle)?;
+    let format = format.ok_or_else(|| {
+      GraphicsError::InvalidUsage(format!("{:?} belongs to a render target", texture))
+    })?;
+    check_texture_write(dimensions, format, origin, size, data)?;
+
+    self.log.push(GraphicsCall::WriteTexture {
+      handle: texture,
+      origin,
+      size,
+    });
+    Ok(())
+  }
+
+  fn texture_size(&self, texture: TextureHandle) -> Option<[u32; 2]> {
+    self.textures.get(&texture.0).map(|(size, _)| *size)
+  }
+
+  fn destroy_texture(&mut self, texture: TextureHandle) {
+    if self.textures.remove(&texture.0).is_some() {
+      self.log.push(GraphicsCall::DestroyTexture(texture));
+    }
+  }
+
+  fn create_sampler(&mut self, desc: &SamplerDesc) -> Result<SamplerHandle, GraphicsError> {
+    let handle = SamplerHandle(self.next_handle());
+    self.samplers.insert(handle.0, desc.clone());
+    self.log.push(GraphicsCall::CreateSampler {
+      handle,
+      desc: desc.clone(),
+    });
+    Ok(handle)
+  }
+
+  fn destroy_sampler(&mut self, sampler: SamplerHandle) {
+    if self.samplers.remove(&sampler.0).is_some() {
+      self.log.push(GraphicsCall::DestroySampler(sampler));
+    }
+  }
+
+  fn create_pipeline(&mut self, desc: &PipelineDesc) -> Result<PipelineHandle, GraphicsError> {
+    let handle = PipelineHandle(self.next_handle());
+    self.pipelines.insert(handle.0, None);
+    self.log.push(GraphicsCall::CreatePipeline {
+      handle,
+      desc: desc.clone(),
+    });
+    Ok(handle)
+  }
+
+  fn builtin_pipeline(&mut self, pipeline: BuiltinPipeline) -> PipelineHandle {
+    if let Some(handle) = self.builtin_pipelines.get(&pipeline) {
+      return *handle;
+    }
+
+    let handle = PipelineHandle(self.next_handle());
+    self.pipelines.insert(handle.0, Some(pipeline));
+    self.builtin_pipelines.insert(pipeline, handle);
+    self
+      .log
+      .push(GraphicsCall::CreateBuiltinPipeline { handle, pipeline });
+    handle
+  }
+
+  fn recreate_pipeline(
+    &mut self,
+    pipeline: PipelineHandle,
+    desc: &PipelineDesc,
+  ) -> Result<(), GraphicsError> {
+    if !matches!(self.pipelines.get(&pipeline.0), Some(None)) {
+      return Err(GraphicsError::InvalidHandle);
+    }
+
+    self.log.push(GraphicsCall::RecreatePipeline {
+      handle: pipeline,
+      desc: desc.clone(),
+    });
+    Ok(())
+  }
+
+  fn destroy_pipeline(&mut self, pipeline: PipelineHandle) {
+    // builtin pipelines are shared and live as long as the device
+    if let Some(None) = self.pipelines.get(&pipeline.0) {
+      self.pipelines.remove(&pipeline.0);
+      self.log.push(GraphicsCall::DestroyPipeline(pipeline));
+    }
+  }
+
+  fn create_render_target(
+    &mut self,
+    desc: &RenderTargetDesc,
+  ) -> Result<RenderTargetHandle, GraphicsError> {
+    if desc.width == 0 || desc.height == 0 {
+      return Err(GraphicsError::InvalidSize {
+        expected: 1,
+        actual: 0,
+      });
+    }
+
+    let texture = TextureHandle(self.next_handle());
+    self.textures.insert(texture.0, ([desc.width, desc.height], None));
+    let handle = RenderTargetHandle(self.next_handle());
+    self.render_targets.insert(handle.0, texture);
+    self.log.push(GraphicsCall::CreateRenderTarget {
+      handle,
+      desc: desc.clone(),
+    });
+    Ok(handle)
+  }
+
+  fn render_target_texture(&self, target: RenderTargetHandle) -> Option<TextureHandle> {
+    self.render_targets.get(&target.0).copied()
+  }
+
+  fn destroy_render_target(&mut self, target: RenderTargetHandle) {
+    if let Some(texture) = self.render_targets.remove(&target.0) {
+      self.textures.remove(&texture.0);
+      self.log.push(GraphicsCall::DestroyRenderTarget(target));
+    }
+  }
+
+  fn create_descriptor_set(
+    &mut self,
+    desc: &DescriptorSetDesc,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    for entry in &desc.entries {
+      match entry.resource {
+        DescriptorResource::UniformBuffer(buffer) => match self.buffers.get(&buffer.0) {
+          Some(BufferDesc {
+            usage: BufferUsage::Uniform,
+            ..
+          }) => (),
+          Some(_) => {
+            return Err(GraphicsError::InvalidUsage(format!(
+              "{:?} isn't a uniform buffer",
+              buffer
+            )))
+          }
+          None => return Err(GraphicsError::InvalidHandle),
+        },
+        DescriptorResource::Texture(texture) if !self.textures.contains_key(&texture.0) => {
+          return Err(GraphicsError::InvalidHandle)
+        }
+        DescriptorResource::Sampler(sampler) if !self.samplers.contains_key(&sampler.0) => {
+          return Err(GraphicsError::InvalidHandle)
+        }
+        DescriptorResource::Texture(_) | DescriptorResource::Sampler(_) => (),
+      }
+    }
+
+    let handle = DescriptorSetHandle(self.next_handle());
+    self.descriptor_sets.insert(handle.0, desc.clone());
+    self.log.push(GraphicsCall::CreateDescriptorSet {
+      handle,
+      desc: desc.clone(),
+    });
+    Ok(handle)
+  }
+
+  fn destroy_descriptor_set(&mut self, set: DescriptorSetHandle) {
+    if self.descriptor_sets.remove(&set.0).is_some() {
+      self.log.push(GraphicsCall::DestroyDescriptorSet(set));
+    }
+  }
+
+  fn submit(&mut self, commands: &CommandList) {
+    self.log.push(GraphicsCall::Submit {
+      index: self.frame,
+      commands: commands.commands().to_vec(),
+    });
+    self.frame += 1;
+  }
+
+  /// nothing is drawn, the capture is a transparent image of the surface size
+  fn submit_and_capture(&mut self, commands: &CommandList) -> Result<RgbaImage, GraphicsError> {
+    self.submit(commands);
+    let [width, height] = self.surface_size;
+    Ok(RgbaImage::new(width, height))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::traits::{
+    BlendMode, CommandEncoder, CullMode, DepthMode, DescriptorEntry, DescriptorSetLayout,
+    IndexFormat, PrimitiveTopology, ShaderDesc, TargetFormat,
+  };
+
+  fn device() -> (NullDevice, GraphicsLog) {
+    let log = GraphicsLog::new();
+    let device = NullDevice::new(log.clone(), [64, 64], GraphicsOptions::default());
+    (device, log)
+  }
+
+  fn buffer(device: &mut NullDevice, usage: BufferUsage) -> BufferHandle {
+    device
+      .create_buffer(&BufferDesc { size: 64, usage }, None)
+      .unwrap()
+  }
+
+  #[test]
+  fn draws_keep_the_state_bound_in_their_pass() {
+    let (mut device, _) = device();
+    let pipeline = device.builtin_pipeline(BuiltinPipeline::Mesh);
+    let vertices = buffer(&mut device, BufferUsage::Vertex);
+    let instances = buffer(&mut device, BufferUsage::Vertex);
+    let indices = buffer(&mut device, BufferUsage::Index(IndexFormat::U16));
+    let target = device
+      .create_render_target(&RenderTargetDesc {
+        width: 8,
+        height: 8,
+        format: TargetFormat::Screen,
+      })
+      .unwrap();
+
+    let mut commands = CommandList::new();
+    commands.begin_pass(PassTarget::Offscreen(target), [0.0; 4]);
+    commands.set_pipeline(pipeline);
+    commands.set_vertex_buffer(1, instances);
+    commands.set_vertex_buffer(0, vertices);
+    commands.draw(0..3, 0..1);
+    commands.set_index_buffer(indices);
+    commands.draw_indexed(0..6, 0, 0..2);
+    commands.end_pass();
+    commands.begin_pass(PassTarget::Screen, [0.0; 4]);
+    commands.draw(0..3, 0..1);
+    commands.end_pass();
+
+    let draws = GraphicsLog::draws(commands.commands());
+    assert_eq!(draws.len(), 3);
+    assert_eq!(draws[0].target, PassTarget::Offscreen(target));
+    assert_eq!(draws[0].pipeline, Some(pipeline));
+    assert_eq!(draws[0].vertex_buffers, [(0, vertices), (1, instances)]);
+    assert_eq!(draws[0].index_buffer, None);
+    assert_eq!(draws[1].index_buffer, Some(indices));
+    assert_eq!(draws[1].elements, 0..6);
+    assert_eq!(draws[1].instances, 0..2);
+    // nothing stays bound in the next pass
+    assert_eq!(draws[2].target, PassTarget::Screen);
+    assert_eq!(draws[2].pipeline, None);
+    assert!(draws[2].vertex_buffers.is_empty());
+  }
+
+  #[test]
+  fn the_last_frame_is_submitted() {
+    let (mut device, log) = device();
+    assert!(log.last_frame().is_none());
+
+    let mut commands = CommandList::new();
+    commands.begin_pass(PassTarget::Screen, [0.0; 4]);
+    commands.draw(0..3, 0..1);
+    commands.end_pass();
+    device.submit(&commands);
+    device.submit(&CommandList::new());
+
+    assert_eq!(log.frames().len(), 2);
+    assert!(log.last_frame_draws().is_empty());
+    assert_eq!(GraphicsLog::draws(&log.frames()[0]).len(), 1);
+  }
+
+  #[test]
+  fn buffer_writes_are_logged_with_their_bytes() {
+    let (mut device, log) = device();
+    let uniform = buffer(&mut device, BufferUsage::Uniform);
+    assert!(device.write_buffer(uniform, 60, &[1, 2, 3, 4]).is_ok());
+    assert!(device.write_buffer(uniform, 61, &[1, 2, 3, 4]).is_err());
+
+    let writes: Vec<GraphicsCall> = log
+      .calls()
+      .into_iter()
+      .filter(|call| matches!(call, GraphicsCall::WriteBuffer { .. }))
+      .collect();
+    assert_eq!(
+      writes,
+      [GraphicsCall::WriteBuffer {
+        handle: uniform,
+        offset: 60,
+        size: 4,
+        data: vec![1, 2, 3, 4],
+      }]
+    );
+  }
+
+  #[test]
+  fn descriptor_sets_check_their_resources() {
+    let (mut device, _) = device();
+    let uniform = buffer(&mut device, BufferUsage::Uniform);
+    let vertices = buffer(&mut device, BufferUsage::Vertex);
+
+    assert!(device
+      .create_descriptor_set(&DescriptorSetDesc::uniform_buffers(&[uniform]))
+      .is_ok());
+    assert!(matches!(
+      device.create_descriptor_set(&DescriptorSetDesc::uniform_buffers(&[vertices])),
+      Err(GraphicsError::InvalidUsage(_))
+    ));
+
+    device.destroy_buffer(uniform);
+    assert!(matches!(
+      device.create_descriptor_set(&DescriptorSetDesc::uniform_buffers(&[uniform])),
+      Err(GraphicsError::InvalidHandle)
+    ));
+    let texture = DescriptorSetDesc {
+      entries: vec![DescriptorEntry {
+        binding: 0,
+        resource: DescriptorResource::Texture(TextureHandle(1000)),
+      }],
+    };
+    assert!(matches!(
+      device.create_descriptor_set(&texture),
+      Err(GraphicsError::InvalidHandle)
+    ));
+  }
+
+  #[test]
+  fn builtin_pipelines_are_shared_and_never_recreated() {
+    let (mut device, log) = device();
+    let desc = PipelineDesc {
+      vertex_shader: ShaderDesc::wgsl("", "vs_main"),
+      fragment_shader: ShaderDesc::wgsl("", "fs_main"),
+      vertex_layouts: Vec::new(),
+      descriptor_set_layouts: vec![DescriptorSetLayout::uniform_buffers(1)],
+      topology: PrimitiveTopology::TriangleList,
+      blend: BlendMode::Opaque,
+      cull_mode: CullMode::None,
+      depth: DepthMode::Disabled,
+    };
+
+    let builtin = device.builtin_pipeline(BuiltinPipeline::Sprite);
+    assert_eq!(device.builtin_pipeline(BuiltinPipeline::Sprite), builtin);
+    assert!(matches!(
+      device.recreate_pipeline(builtin, &desc),
+      Err(GraphicsError::InvalidHandle)
+    ));
+    device.destroy_pipeline(builtin);
+    assert_eq!(device.builtin_pipeline(BuiltinPipeline::Sprite), builtin);
+
+    let pipeline = device.create_pipeline(&desc).unwrap();
+    assert!(device.recreate_pipeline(pipeline, &desc).is_ok());
+    device.destroy_pipeline(pipeline);
+    assert!(device.recreate_pipeline(pipeline, &desc).is_err());
+
+    let builtins = log
+      .calls()
+      .into_iter()
+      .filter(|call| matches!(call, GraphicsCall::CreateBuiltinPipeline { .. }))
+      .count();
+    assert_eq!(builtins, 1);
+  }
+}
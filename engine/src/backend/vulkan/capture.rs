@@ -0,0 +1,80 @@
+use crate::traits::GraphicsError;
+
+use image::RgbaImage;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+  AutoCommandBufferBuilder, CopyImageToBufferInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::format::Format;
+use vulkano::image::ImageAccess;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+/// a copy of the screen in a host visible buffer, to read a frame back
+pub(super) struct GegVkCapture {
+  buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+  dimensions: [u32; 2],
+  bgra: bool,
+}
+
+impl GegVkCapture {
+  /// records a copy of `image` into a new buffer, read once the frame's fence is signaled
+  pub fn record(
+    memory_allocator: &StandardMemoryAllocator,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    image: Arc<dyn ImageAccess>,
+    format: Format,
+  ) -> Result<Self, GraphicsError> {
+    let bgra = match format {
+      Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => false,
+      Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => true,
+      _ => {
+        return Err(GraphicsError::Readback(format!(
+          "can't read back a {:?} screen",
+          format
+        )))
+      }
+    };
+
+    let dimensions = image.dimensions().width_height();
+    let size = dimensions[0] as usize * dimensions[1] as usize * 4;
+    let buffer = CpuAccessibleBuffer::from_iter(
+      memory_allocator,
+      BufferUsage {
+        transfer_dst: true,
+        ..BufferUsage::empty()
+      },
+      false,
+      vec![0u8; size],
+    )
+    .map_err(|e| GraphicsError::Readback(e.to_string()))?;
+
+    builder
+      .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))
+      .map_err(|e| GraphicsError::Readback(e.to_string()))?;
+
+    Ok(Self {
+      buffer,
+      dimensions,
+      bgra,
+    })
+  }
+
+  /// the copied pixels, the gpu has to be done with the frame
+  pub fn read(self) -> Result<RgbaImage, GraphicsError> {
+    let mut pixels = self
+      .buffer
+      .read()
+      .map_err(|e| GraphicsError::Readback(e.to_string()))?
+      .to_vec();
+
+    if self.bgra {
+      for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+      }
+    }
+
+    let [width, height] = self.dimensions;
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("the capture has the wrong size"))
+  }
+}
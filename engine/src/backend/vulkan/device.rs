@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
 use vulkano::device::{
-  Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+  Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
 };
 use vulkano::instance::{
   debug::{DebugUtilsMessenger, DebugUtilsMessengerCreateInfo},
@@ -14,13 +14,13 @@ use vulkano::VulkanLibrary;
 use spdlog::prelude::*;
 use winit::window::Window;
 
-// @TODO make generic interface
 /// describe vulkan device and a surface and it's tied to a window
+/// in headless mode there is no window and no surface
 pub(crate) struct GegVkDevice {
-  win: Arc<Window>,
+  win: Option<Arc<Window>>,
   instance: Arc<Instance>,
   physical_device: Arc<PhysicalDevice>,
-  surface: Arc<Surface>,
+  surface: Option<Arc<Surface>>,
   device: Arc<Device>,
   queue: Arc<Queue>,
   _debug_messenger: Option<DebugUtilsMessenger>,
@@ -41,10 +41,14 @@ impl Clone for GegVkDevice {
 }
 
 impl GegVkDevice {
-  pub fn new(win: Arc<Window>) -> Self {
+  pub fn new(win: Option<Arc<Window>>) -> Self {
     let lib = VulkanLibrary::new().expect("failed to load Vulkan library");
 
-    let required_extensions = vulkano_win::required_extensions(&lib);
+    // surface extensions are only needed when presenting to a window
+    let required_extensions = match win {
+      Some(_) => vulkano_win::required_extensions(&lib),
+      None => Default::default(),
+    };
     let instance_cration_info = InstanceCreateInfo {
       // for moltenVK
       max_api_version: Some(Version::V1_2),
@@ -67,12 +71,14 @@ impl GegVkDevice {
       .ok()
     };
 
-    let surface = vulkano_win::create_surface_from_winit(win.clone(), instance.clone())
-      .expect("failed to create surface");
+    let surface = win.as_ref().map(|win| {
+      vulkano_win::create_surface_from_winit(win.clone(), instance.clone())
+        .expect("failed to create surface")
+    });
 
     // required extensions
     let device_extensions = DeviceExtensions {
-      khr_swapchain: true,
+      khr_swapchain: surface.is_some(),
       ..DeviceExtensions::empty()
     };
 
@@ -90,7 +96,9 @@ impl GegVkDevice {
             q.queue_flags.intersects(&QueueFlags {
               graphics: true,
               ..Default::default()
-            }) && p.surface_support(i as u32, &surface).unwrap_or(false)
+            }) && surface
+              .as_ref()
+              .map_or(true, |surface| p.surface_support(i as u32, surface).unwrap_or(false))
           })
           // convert to (device, index_q_family)
           .map(|i| (p, i as u32))
@@ -115,6 +123,12 @@ impl GegVkDevice {
     );
     info!("API Version: {}", physical_device.properties().api_version);
 
+    // optional features, samplers check if they are enabled
+    let enabled_features = Features {
+      sampler_anisotropy: physical_device.supported_features().sampler_anisotropy,
+      ..Features::empty()
+    };
+
     let (device, mut queues) = Device::new(
       physical_device.clone(),
       DeviceCreateInfo {
@@ -123,6 +137,7 @@ impl GegVkDevice {
           ..Default::default()
         }],
         enabled_extensions: device_extensions,
+        enabled_features,
         ..Default::default()
       },
     )
@@ -148,14 +163,18 @@ impl GegVkDevice {
     self.physical_device.clone()
   }
 
-  pub fn surface(&self) -> Arc<Surface> {
+  pub fn surface(&self) -> Option<Arc<Surface>> {
     self.surface.clone()
   }
 
-  pub fn window(&self) -> Arc<Window> {
+  pub fn window(&self) -> Option<Arc<Window>> {
     self.win.clone()
   }
 
+  pub fn is_headless(&self) -> bool {
+    self.surface.is_none()
+  }
+
   pub fn device(&self) -> Arc<Device> {
     self.device.clone()
   }
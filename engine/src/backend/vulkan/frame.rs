@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::device::Device;
+use vulkano::sync::{FenceSignalFuture, GpuFuture};
+
+use spdlog::prelude::*;
+
+pub(super) type GegVkFence = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
+
+/// what a frame in flight owns, it's only reused once the gpu is done with the frame
+/// the frames are used in turn, so the cpu records one while the gpu renders the others
+pub(super) struct GegVkFrame {
+  command_buffer_allocator: StandardCommandBufferAllocator,
+  // signaled when the last submission of this frame is done
+  fence: Option<GegVkFence>,
+}
+
+impl GegVkFrame {
+  pub fn new(device: Arc<Device>) -> Self {
+    Self {
+      command_buffer_allocator: StandardCommandBufferAllocator::new(device, Default::default()),
+      fence: None,
+    }
+  }
+
+  /// blocks until the gpu is done with the last submission of this frame
+  pub fn wait(&mut self) {
+    if let Some(fence) = self.fence.take() {
+      if let Err(e) = fence.wait(None) {
+        error!("Failed to wait for a frame in flight: {}", e);
+      }
+    }
+  }
+
+  pub fn set_fence(&mut self, fence: Option<GegVkFence>) {
+    self.fence = fence;
+  }
+
+  /// the fence of the last submission, `None` once waited on or if the submission failed
+  pub fn fence(&self) -> Option<GegVkFence> {
+    self.fence.clone()
+  }
+
+  pub fn command_buffer_allocator(&self) -> &StandardCommandBufferAllocator {
+    &self.command_buffer_allocator
+  }
+}
@@ -1,4 +1,10 @@
+pub(super) mod capture;
 pub(super) mod device;
 pub(super) mod swapchain;
+pub(super) mod offscreen;
+pub(super) mod target;
 pub(super) mod renderpass;
+pub(super) mod frame;
 pub(super) mod renderer;
+pub(super) mod resources;
+pub(super) mod pipeline;
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use vulkano::format::Format;
+use vulkano::image::{AttachmentImage, ImageAccess, ImageUsage};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+use spdlog::prelude::*;
+
+/// a color image that is rendered into instead of a swapchain image
+/// used in headless mode where there is no surface to present to
+#[derive(Clone)]
+pub(super) struct GegVkOffscreen {
+  memory_allocator: Arc<StandardMemoryAllocator>,
+  image: Arc<AttachmentImage>,
+  format: Format,
+}
+
+impl GegVkOffscreen {
+  pub fn new(memory_allocator: Arc<StandardMemoryAllocator>, dimensions: [u32; 2]) -> Self {
+    let format = Format::R8G8B8A8_SRGB;
+    let image = Self::create_image(&memory_allocator, dimensions, format);
+
+    debug!("Offscreen target created");
+
+    Self {
+      memory_allocator,
+      image,
+      format,
+    }
+  }
+
+  /// recreates the image with a new extent
+  pub fn recreate(&mut self, dimensions: [u32; 2]) {
+    self.image = Self::create_image(&self.memory_allocator, dimensions, self.format);
+    debug!("Offscreen target recreated: {:?}", dimensions);
+  }
+
+  fn create_image(
+    memory_allocator: &StandardMemoryAllocator,
+    dimensions: [u32; 2],
+    format: Format,
+  ) -> Arc<AttachmentImage> {
+    AttachmentImage::with_usage(
+      memory_allocator,
+      dimensions,
+      format,
+      ImageUsage {
+        color_attachment: true,
+        transfer_src: true,
+        ..ImageUsage::empty()
+      },
+    )
+    .expect("failed to create offscreen image")
+  }
+
+  // getters
+  pub fn image(&self) -> Arc<AttachmentImage> {
+    self.image.clone()
+  }
+
+  pub fn format(&self) -> Format {
+    self.format
+  }
+
+  pub fn dimensions(&self) -> [u32; 2] {
+    self.image.dimensions().width_height()
+  }
+}
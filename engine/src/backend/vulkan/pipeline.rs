@@ -0,0 +1,498 @@
+use crate::camera::CameraUniform;
+use crate::mesh::MeshInstance;
+use crate::shader;
+use crate::sprite::SpriteVertex;
+use crate::traits::{
+  BlendMode, BuiltinPipeline, ColorVertex, CullMode, DepthMode, DescriptorSetLayout,
+  DescriptorType, GraphicsError, PipelineDesc, PrimitiveTopology, ShaderDesc, ShaderStage,
+  TargetFormat, VertexFormat, VertexLayout, VertexStepMode,
+};
+
+use super::renderpass::GegVkRenderpass;
+
+use std::sync::Arc;
+use vulkano::descriptor_set::layout::{
+  DescriptorSetLayout as VkDescriptorSetLayout, DescriptorSetLayoutBinding,
+  DescriptorSetLayoutCreateInfo, DescriptorType as VkDescriptorType,
+};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::pipeline::graphics::{
+  color_blend::{AttachmentBlend, ColorBlendState},
+  depth_stencil::{CompareOp, DepthState, DepthStencilState},
+  input_assembly::{InputAssemblyState, PrimitiveTopology as VkPrimitiveTopology},
+  multisample::MultisampleState,
+  rasterization::{CullMode as VkCullMode, RasterizationState},
+  vertex_input::{
+    VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate,
+    VertexInputState,
+  },
+  viewport::ViewportState,
+};
+use vulkano::pipeline::layout::{PipelineLayout, PipelineLayoutCreateInfo};
+use vulkano::pipeline::{GraphicsPipeline, StateMode};
+use vulkano::render_pass::Subpass;
+use vulkano::shader::{EntryPoint, ShaderModule, ShaderStages};
+
+mod vertex_color_vs {
+  vulkano_shaders::shader! {
+    ty: "vertex",
+    src: "
+    #version 450
+
+    layout(location = 0) in vec3 position;
+    layout(location = 1) in vec3 color;
+
+    layout(location = 0) out vec3 v_color;
+
+    void main() {
+      gl_Position = vec4(position, 1.0);
+      v_color = color;
+    }
+  "
+  }
+}
+
+mod mesh_vs {
+  vulkano_shaders::shader! {
+    ty: "vertex",
+    src: "
+    #version 450
+
+    layout(location = 0) in vec3 position;
+    layout(location = 1) in vec3 color;
+    layout(location = 12) in mat4 transform;
+
+    layout(set = 0, binding = 0) uniform Camera {
+      mat4 view_projection;
+      mat4 view;
+      mat4 projection;
+      vec4 position;
+    } camera;
+
+    layout(location = 0) out vec3 v_color;
+
+    void main() {
+      gl_Position = camera.view_projection * transform * vec4(position, 1.0);
+      v_color = color;
+    }
+  "
+  }
+}
+
+mod lines_vs {
+  vulkano_shaders::shader! {
+    ty: "vertex",
+    src: "
+    #version 450
+
+    layout(location = 0) in vec3 position;
+    layout(location = 1) in vec3 color;
+
+    layout(set = 0, binding = 0) uniform Camera {
+      mat4 view_projection;
+      mat4 view;
+      mat4 projection;
+      vec4 position;
+    } camera;
+
+    layout(location = 0) out vec3 v_color;
+
+    void main() {
+      gl_Position = camera.view_projection * vec4(position, 1.0);
+      v_color = color;
+    }
+  "
+  }
+}
+
+mod vertex_color_fs {
+  vulkano_shaders::shader! {
+    ty: "fragment",
+    src: "
+    #version 450
+
+    layout(location = 0) in vec3 v_color;
+
+    layout(location = 0) out vec4 f_color;
+
+    void main() {
+      f_color = vec4(v_color, 1.0);
+    }
+  "
+  }
+}
+
+mod sprite_vs {
+  vulkano_shaders::shader! {
+    ty: "vertex",
+    src: "
+    #version 450
+
+    layout(location = 0) in vec2 position;
+    layout(location = 1) in vec2 uv;
+    layout(location = 2) in vec4 color;
+
+    layout(set = 0, binding = 0) uniform Camera {
+      mat4 view_projection;
+      mat4 view;
+      mat4 projection;
+      vec4 position;
+    } camera;
+
+    layout(location = 0) out vec2 v_uv;
+    layout(location = 1) out vec4 v_color;
+
+    void main() {
+      gl_Position = camera.view_projection * vec4(position, 0.0, 1.0);
+      v_uv = uv;
+      v_color = color;
+    }
+  "
+  }
+}
+
+mod sprite_fs {
+  vulkano_shaders::shader! {
+    ty: "fragment",
+    src: "
+    #version 450
+
+    layout(location = 0) in vec2 v_uv;
+    layout(location = 1) in vec4 v_color;
+
+    layout(set = 1, binding = 0) uniform texture2D sprite_texture;
+    layout(set = 1, binding = 1) uniform sampler sprite_sampler;
+
+    layout(location = 0) out vec4 f_color;
+
+    void main() {
+      f_color = texture(sampler2D(sprite_texture, sprite_sampler), v_uv) * v_color;
+    }
+  "
+  }
+}
+
+/// the fixed function state of a pipeline
+struct PipelineState<'a> {
+  vertex_layouts: &'a [VertexLayout],
+  descriptor_set_layouts: &'a [DescriptorSetLayout],
+  topology: PrimitiveTopology,
+  blend: BlendMode,
+  cull_mode: CullMode,
+  depth: DepthMode,
+}
+
+/// a pipeline built for each target format, a pass uses the one matching its target
+pub(super) struct GegVkPipeline {
+  screen: Arc<GraphicsPipeline>,
+  hdr: Arc<GraphicsPipeline>,
+}
+
+impl GegVkPipeline {
+  pub fn get(&self, format: TargetFormat) -> &Arc<GraphicsPipeline> {
+    match format {
+      TargetFormat::Screen => &self.screen,
+      TargetFormat::Hdr => &self.hdr,
+    }
+  }
+}
+
+/// creates a pipeline from user provided shaders
+pub(super) fn create_pipeline(
+  device: Arc<Device>,
+  geg_renderpass: &GegVkRenderpass,
+  desc: &PipelineDesc,
+) -> Result<GegVkPipeline, GraphicsError> {
+  let vs = load_shader(device.clone(), &desc.vertex_shader, ShaderStage::Vertex)?;
+  let fs = load_shader(device.clone(), &desc.fragment_shader, ShaderStage::Fragment)?;
+
+  let vs_entry = entry_point(&vs, &desc.vertex_shader)?;
+  let fs_entry = entry_point(&fs, &desc.fragment_shader)?;
+
+  build_pipeline(
+    device,
+    geg_renderpass,
+    vs_entry,
+    fs_entry,
+    PipelineState {
+      vertex_layouts: &desc.vertex_layouts,
+      descriptor_set_layouts: &desc.descriptor_set_layouts,
+      topology: desc.topology,
+      blend: desc.blend,
+      cull_mode: desc.cull_mode,
+      depth: desc.depth,
+    },
+  )
+}
+
+/// creates a pipeline with the engine's own shaders
+pub(super) fn create_builtin_pipeline(
+  device: Arc<Device>,
+  geg_renderpass: &GegVkRenderpass,
+  pipeline: BuiltinPipeline,
+) -> GegVkPipeline {
+  match pipeline {
+    BuiltinPipeline::VertexColor => {
+      let vs = vertex_color_vs::load(device.clone()).expect("failed to create shader module");
+      let fs = vertex_color_fs::load(device.clone()).expect("failed to create shader module");
+
+      build_pipeline(
+        device,
+        geg_renderpass,
+        vs.entry_point("main").unwrap(),
+        fs.entry_point("main").unwrap(),
+        PipelineState {
+          vertex_layouts: &[ColorVertex::layout()],
+          descriptor_set_layouts: &[],
+          topology: PrimitiveTopology::TriangleList,
+          blend: BlendMode::Opaque,
+          cull_mode: CullMode::None,
+          depth: DepthMode::Disabled,
+        },
+      )
+      .expect("failed to create builtin pipeline")
+    }
+
+    BuiltinPipeline::Mesh => {
+      let vs = mesh_vs::load(device.clone()).expect("failed to create shader module");
+      let fs = vertex_color_fs::load(device.clone()).expect("failed to create shader module");
+
+      build_pipeline(
+        device,
+        geg_renderpass,
+        vs.entry_point("main").unwrap(),
+        fs.entry_point("main").unwrap(),
+        PipelineState {
+          vertex_layouts: &[ColorVertex::layout(), MeshInstance::layout()],
+          descriptor_set_layouts: &[CameraUniform::layout()],
+          topology: PrimitiveTopology::TriangleList,
+          blend: BlendMode::Opaque,
+          cull_mode: CullMode::None,
+          depth: DepthMode::ReadWrite,
+        },
+      )
+      .expect("failed to create builtin pipeline")
+    }
+
+    BuiltinPipeline::Sprite => {
+      let vs = sprite_vs::load(device.clone()).expect("failed to create shader module");
+      let fs = sprite_fs::load(device.clone()).expect("failed to create shader module");
+
+      build_pipeline(
+        device,
+        geg_renderpass,
+        vs.entry_point("main").unwrap(),
+        fs.entry_point("main").unwrap(),
+        PipelineState {
+          vertex_layouts: &[SpriteVertex::layout()],
+          descriptor_set_layouts: &[CameraUniform::layout(), DescriptorSetLayout::texture()],
+          topology: PrimitiveTopology::TriangleList,
+          blend: BlendMode::Alpha,
+          cull_mode: CullMode::None,
+          depth: DepthMode::Disabled,
+        },
+      )
+      .expect("failed to create builtin pipeline")
+    }
+
+    BuiltinPipeline::Lines | BuiltinPipeline::LinesOnTop => {
+      let vs = lines_vs::load(device.clone()).expect("failed to create shader module");
+      let fs = vertex_color_fs::load(device.clone()).expect("failed to create shader module");
+
+      build_pipeline(
+        device,
+        geg_renderpass,
+        vs.entry_point("main").unwrap(),
+        fs.entry_point("main").unwrap(),
+        PipelineState {
+          vertex_layouts: &[ColorVertex::layout()],
+          descriptor_set_layouts: &[CameraUniform::layout()],
+          topology: PrimitiveTopology::LineList,
+          blend: BlendMode::Opaque,
+          cull_mode: CullMode::None,
+          depth: match pipeline {
+            BuiltinPipeline::Lines => DepthMode::ReadOnly,
+            _ => DepthMode::Disabled,
+          },
+        },
+      )
+      .expect("failed to create builtin pipeline")
+    }
+  }
+}
+
+fn build_pipeline(
+  device: Arc<Device>,
+  geg_renderpass: &GegVkRenderpass,
+  vs: EntryPoint,
+  fs: EntryPoint,
+  state: PipelineState,
+) -> Result<GegVkPipeline, GraphicsError> {
+  let mut vertex_input = VertexInputState::new();
+  for (binding, layout) in state.vertex_layouts.iter().enumerate() {
+    vertex_input = vertex_input.binding(
+      binding as u32,
+      VertexInputBindingDescription {
+        stride: layout.stride,
+        input_rate: match layout.step_mode {
+          VertexStepMode::Vertex => VertexInputRate::Vertex,
+          VertexStepMode::Instance => VertexInputRate::Instance { divisor: 1 },
+        },
+      },
+    );
+
+    for attribute in &layout.attributes {
+      vertex_input = vertex_input.attribute(
+        attribute.location,
+        VertexInputAttributeDescription {
+          binding: binding as u32,
+          format: to_vk_vertex_format(attribute.format),
+          offset: attribute.offset,
+        },
+      );
+    }
+  }
+
+  let topology = match state.topology {
+    PrimitiveTopology::TriangleList => VkPrimitiveTopology::TriangleList,
+    PrimitiveTopology::TriangleStrip => VkPrimitiveTopology::TriangleStrip,
+    PrimitiveTopology::LineList => VkPrimitiveTopology::LineList,
+    PrimitiveTopology::PointList => VkPrimitiveTopology::PointList,
+  };
+
+  let color_blend = match state.blend {
+    BlendMode::Opaque => ColorBlendState::new(1),
+    BlendMode::Alpha => ColorBlendState::new(1).blend_alpha(),
+    BlendMode::Additive => ColorBlendState::new(1).blend(AttachmentBlend::additive()),
+  };
+
+  let cull_mode = match state.cull_mode {
+    CullMode::None => VkCullMode::None,
+    CullMode::Front => VkCullMode::Front,
+    CullMode::Back => VkCullMode::Back,
+  };
+
+  let depth_stencil = match state.depth {
+    DepthMode::Disabled => DepthStencilState::disabled(),
+    DepthMode::ReadWrite => DepthStencilState::simple_depth_test(),
+    DepthMode::ReadOnly => DepthStencilState {
+      depth: Some(DepthState {
+        enable_dynamic: false,
+        write_enable: StateMode::Fixed(false),
+        compare_op: StateMode::Fixed(CompareOp::Less),
+      }),
+      ..DepthStencilState::disabled()
+    },
+  };
+
+  // the layout comes from the desc and not from the shaders, so descriptor sets
+  // can be created without knowing which pipeline they will be used with
+  let set_layouts = state
+    .descriptor_set_layouts
+    .iter()
+    .map(|layout| create_descriptor_set_layout(device.clone(), layout))
+    .collect::<Result<Vec<_>, _>>()?;
+  let layout = PipelineLayout::new(
+    device.clone(),
+    PipelineLayoutCreateInfo {
+      set_layouts,
+      ..Default::default()
+    },
+  )
+  .map_err(|e| GraphicsError::InvalidPipeline(e.to_string()))?;
+
+  // the variants only differ in the render pass they are used in
+  let build = |format: TargetFormat| {
+    GraphicsPipeline::start()
+      .vertex_input_state(vertex_input.clone())
+      .vertex_shader(vs.clone(), ())
+      .input_assembly_state(InputAssemblyState::new().topology(topology))
+      .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+      .rasterization_state(RasterizationState::new().cull_mode(cull_mode))
+      .fragment_shader(fs.clone(), ())
+      .color_blend_state(color_blend.clone())
+      .depth_stencil_state(depth_stencil.clone())
+      .multisample_state(MultisampleState {
+        rasterization_samples: geg_renderpass.samples(),
+        ..MultisampleState::new()
+      })
+      .render_pass(Subpass::from(geg_renderpass.render_pass(format), 0).unwrap())
+      .with_pipeline_layout(device.clone(), layout.clone())
+      .map_err(|e| GraphicsError::InvalidPipeline(e.to_string()))
+  };
+
+  Ok(GegVkPipeline {
+    screen: build(TargetFormat::Screen)?,
+    hdr: build(TargetFormat::Hdr)?,
+  })
+}
+
+fn create_descriptor_set_layout(
+  device: Arc<Device>,
+  layout: &DescriptorSetLayout,
+) -> Result<Arc<VkDescriptorSetLayout>, GraphicsError> {
+  let bindings = layout
+    .bindings
+    .iter()
+    .map(|binding| {
+      let descriptor_type = match binding.ty {
+        DescriptorType::UniformBuffer => VkDescriptorType::UniformBuffer,
+        DescriptorType::Texture => VkDescriptorType::SampledImage,
+        DescriptorType::Sampler => VkDescriptorType::Sampler,
+      };
+
+      (
+        binding.binding,
+        DescriptorSetLayoutBinding {
+          stages: ShaderStages {
+            vertex: true,
+            fragment: true,
+            ..ShaderStages::empty()
+          },
+          ..DescriptorSetLayoutBinding::descriptor_type(descriptor_type)
+        },
+      )
+    })
+    .collect();
+
+  VkDescriptorSetLayout::new(
+    device,
+    DescriptorSetLayoutCreateInfo {
+      bindings,
+      ..Default::default()
+    },
+  )
+  .map_err(|e| GraphicsError::InvalidPipeline(e.to_string()))
+}
+
+fn load_shader(
+  device: Arc<Device>,
+  desc: &ShaderDesc,
+  stage: ShaderStage,
+) -> Result<Arc<ShaderModule>, GraphicsError> {
+  let words = shader::to_spirv(desc, stage)?;
+  // safety: vulkano validates the spir-v it reflects, the rest is up to the shader author
+  unsafe { ShaderModule::from_words(device, &words) }
+    .map_err(|e| GraphicsError::InvalidShader(e.to_string()))
+}
+
+fn entry_point<'a>(
+  module: &'a ShaderModule,
+  desc: &ShaderDesc,
+) -> Result<EntryPoint<'a>, GraphicsError> {
+  module.entry_point(&desc.entry_point).ok_or_else(|| {
+    GraphicsError::InvalidShader(format!("no entry point named {}", desc.entry_point))
+  })
+}
+
+fn to_vk_vertex_format(format: VertexFormat) -> Format {
+  match format {
+    VertexFormat::Float32 => Format::R32_SFLOAT,
+    VertexFormat::Float32x2 => Format::R32G32_SFLOAT,
+    VertexFormat::Float32x3 => Format::R32G32B32_SFLOAT,
+    VertexFormat::Float32x4 => Format::R32G32B32A32_SFLOAT,
+    VertexFormat::Uint32 => Format::R32_UINT,
+    VertexFormat::Unorm8x4 => Format::R8G8B8A8_UNORM,
+  }
+}
@@ -1,215 +1,956 @@
-use super::{device::GegVkDevice, renderpass::GegVkRenderpass, swapchain::GegVkSwapchain};
+use super::{
+  capture::GegVkCapture,
+  device::GegVkDevice,
+  frame::GegVkFrame,
+  offscreen::GegVkOffscreen,
+  pipeline::{create_builtin_pipeline, create_pipeline, GegVkPipeline},
+  renderpass::{GegVkRenderpass, HDR_FORMAT},
+  resources::{
+    to_vk_address_mode, to_vk_filter, to_vk_texture_format, GegVkBuffer, GegVkBufferData,
+    GegVkBuiltSet, GegVkRenderTarget, GegVkTexture,
+  },
+  swapchain::GegVkSwapchain,
+  target::GegVkTarget,
+};
+use crate::backend::{GegBackend, GraphicsOptions};
+use crate::traits::{
+  check_texture_write, BufferDesc, BufferHandle, BufferUsage, BuiltinPipeline, CommandList,
+  DescriptorResource, DescriptorSetDesc, DescriptorSetHandle, GraphicsDevice, GraphicsError,
+  PassTarget, PipelineDesc, PipelineHandle, RenderCommand, RenderTargetDesc,
+  RenderTargetHandle, SamplerDesc, SamplerHandle, TargetFormat, TextureDesc, TextureHandle,
+};
 
-use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
 use spdlog::prelude::*;
-use std::collections::{hash_map, HashMap};
+use std::collections::HashMap;
 use std::sync::Arc;
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::buffer::{BufferUsage as VkBufferUsage, CpuAccessibleBuffer};
 use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
 use vulkano::command_buffer::{
-  AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo,
-  SecondaryCommandBufferAbstract, SubpassContents,
+  AutoCommandBufferBuilder, BufferImageCopy, CommandBufferUsage, CopyBufferToImageInfo,
+  PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo, SubpassContents,
 };
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::{Device, Queue};
-use vulkano::format::Format;
-use vulkano::memory::allocator::StandardMemoryAllocator;
-use vulkano::pipeline::graphics::vertex_input::{
-  BuffersDefinition, Vertex as VertexTrait, VertexInputAttributeDescription, VertexInputState,
+use vulkano::image::{
+  view::{ImageView, ImageViewAbstract},
+  AttachmentImage, ImageDimensions, ImageUsage, ImmutableImage, MipmapsCount,
 };
+use vulkano::memory::allocator::StandardMemoryAllocator;
 use vulkano::pipeline::{
-  graphics::{
-    input_assembly::InputAssemblyState,
-    viewport::{Viewport, ViewportState},
-  },
-  GraphicsPipeline,
+  graphics::viewport::Viewport, GraphicsPipeline, Pipeline, PipelineBindPoint,
 };
-use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Sampler, SamplerAddressMode, SamplerCreateInfo};
 use vulkano::swapchain::{AcquireError, SwapchainPresentInfo};
-use vulkano::sync::{self, GpuFuture};
-
-#[repr(C)]
-#[derive(Default, Copy, Clone, Zeroable, Pod)]
-struct Vertex {
-  position: [f32; 3],
-  color: [f32; 3],
-}
-vulkano::impl_vertex!(Vertex, position, color);
+use vulkano::sync::{self, FlushError, GpuFuture};
 
 pub(crate) struct GegVkRenderer {
   device: Arc<Device>,
   queue: Arc<Queue>,
-  geg_swapchain: GegVkSwapchain,
+  geg_target: GegVkTarget,
   geg_renderpass: GegVkRenderpass,
-  default_pipeline: Arc<GraphicsPipeline>,
+  memory_allocator: Arc<StandardMemoryAllocator>,
+  // for the uploads made outside of the frames
   command_buffer_allocator: StandardCommandBufferAllocator,
-  lastframe: Option<Box<dyn GpuFuture>>,
-  vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+  descriptor_set_allocator: StandardDescriptorSetAllocator,
+  frames: Vec<GegVkFrame>,
+  // the frame recorded by the next submit, the frames are used in turn
+  frame_index: usize,
+  // the extent the target should be recreated with, the window's size when there is one
+  dimensions: [u32; 2],
+  recreate_target: bool,
+  next_handle: u64,
+  buffers: HashMap<u64, GegVkBuffer>,
+  textures: HashMap<u64, GegVkTexture>,
+  samplers: HashMap<u64, Arc<Sampler>>,
+  pipelines: HashMap<u64, GegVkPipeline>,
+  builtin_pipelines: HashMap<BuiltinPipeline, PipelineHandle>,
+  render_targets: HashMap<u64, GegVkRenderTarget>,
+  // every frame reads its own copy of the written buffers, so the vulkan sets are built
+  // when drawing
+  descriptor_sets: HashMap<u64, DescriptorSetDesc>,
+  // the vulkan sets of each descriptor set and frame in flight, one per pipeline layout
+  built_descriptor_sets: HashMap<(u64, usize), Vec<GegVkBuiltSet>>,
 }
 
 impl GegVkRenderer {
-  pub fn new(geg_device: GegVkDevice) -> Self {
+  /// `dimensions` is only used for the offscreen target when running headless
+  pub fn new(geg_device: GegVkDevice, dimensions: [u32; 2], options: GraphicsOptions) -> Self {
     let device = geg_device.device();
     let queue = geg_device.queue();
-    let geg_swapchain = GegVkSwapchain::new(geg_device.clone());
-    let geg_renderpass = GegVkRenderpass::new(geg_device.clone(), geg_swapchain.clone());
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let geg_target = if geg_device.is_headless() {
+      GegVkTarget::Offscreen(GegVkOffscreen::new(memory_allocator.clone(), dimensions))
+    } else {
+      GegVkTarget::Swapchain(GegVkSwapchain::new(geg_device.clone()))
+    };
+    let geg_renderpass = GegVkRenderpass::new(
+      geg_device.clone(),
+      &geg_target,
+      memory_allocator.clone(),
+      options,
+    );
     let command_buffer_allocator =
       StandardCommandBufferAllocator::new(device.clone(), Default::default());
+    let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
+
+    let dimensions = geg_target.dimensions();
+    let frames = (0..options.frames_in_flight.max(1))
+      .map(|_| GegVkFrame::new(device.clone()))
+      .collect::<Vec<_>>();
+
+    debug!("Renderer created with {} frames in flight", frames.len());
+    Self {
+      device: device.clone(),
+      queue,
+      geg_target,
+      geg_renderpass,
+      memory_allocator,
+      command_buffer_allocator,
+      descriptor_set_allocator,
+      frames,
+      frame_index: 0,
+      dimensions,
+      recreate_target: false,
+      next_handle: 0,
+      buffers: HashMap::new(),
+      textures: HashMap::new(),
+      samplers: HashMap::new(),
+      pipelines: HashMap::new(),
+      builtin_pipelines: HashMap::new(),
+      render_targets: HashMap::new(),
+      descriptor_sets: HashMap::new(),
+      built_descriptor_sets: HashMap::new(),
+    }
+  }
 
-    // temporary
-    mod vert_shader {
-      vulkano_shaders::shader! {
-        ty: "vertex",
-        src: "
-        #version 450
+  /// recreates the target and everything that depends on its images
+  /// returns `false` if the target can't be recreated right now
+  fn recreate_target(&mut self) -> bool {
+    // the swapchain can go out of date before the resize event arrives
+    if let Some(dimensions) = self.geg_target.window_dimensions() {
+      self.dimensions = dimensions;
+    }
 
-        layout(location = 0) in vec3 position;
-        layout(location = 1) in vec3 color;
+    // minimized, nothing to present to
+    if self.dimensions[0] == 0 || self.dimensions[1] == 0 {
+      return false;
+    }
+
+    if !self.geg_target.recreate(self.dimensions) {
+      return false;
+    }
 
-        layout(location = 0) out vec3 v_color;
+    self.geg_renderpass.recreate_frame_buffers(&self.geg_target);
+    self.recreate_target = false;
+    true
+  }
+
+  fn next_handle(&mut self) -> u64 {
+    self.next_handle += 1;
+    self.next_handle
+  }
 
-        void main() {
-          gl_Position = vec4(position, 1.0);
-          v_color = color;
+  /// a command buffer for the copies made outside of the frames
+  fn uploads(&self) -> Result<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, GraphicsError> {
+    AutoCommandBufferBuilder::primary(
+      &self.command_buffer_allocator,
+      self.queue.queue_family_index(),
+      CommandBufferUsage::OneTimeSubmit,
+    )
+    .map_err(|e| GraphicsError::Device(e.to_string()))
+  }
+
+  /// runs the copies and blocks until the gpu is done with them, so the resources can be
+  /// used by the next frame without tracking another future
+  fn run_uploads(
+    &self,
+    uploads: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+  ) -> Result<(), GraphicsError> {
+    uploads
+      .build()
+      .map_err(|e| GraphicsError::Device(e.to_string()))?
+      .execute(self.queue.clone())
+      .map_err(|e| GraphicsError::Device(e.to_string()))?
+      .then_signal_fence_and_flush()
+      .map_err(|e| GraphicsError::Device(e.to_string()))?
+      .wait(None)
+      .map_err(|e| GraphicsError::Device(e.to_string()))
+  }
+
+  /// draws and presents a frame, with `capture` the screen is also copied into a buffer
+  /// that is returned to be read back, `None` if the frame was skipped
+  fn render(
+    &mut self,
+    commands: &CommandList,
+    capture: bool,
+  ) -> Option<Result<GegVkCapture, GraphicsError>> {
+    if self.recreate_target && !self.recreate_target() {
+      return None;
+    }
+
+    // the previous submission of this frame has to be done before its buffers are written
+    let frame = self.frame_index;
+    self.frames[frame].wait();
+    for buffer in self.buffers.values_mut() {
+      buffer.sync(&self.memory_allocator, frame);
+    }
+
+    let swapchain = match &self.geg_target {
+      GegVkTarget::Swapchain(geg_swapchain) => Some(geg_swapchain.swapchain()),
+      GegVkTarget::Offscreen(_) => None,
+    };
+
+    // the offscreen target has a single image that is always ready
+    let (image_index, acquire_future) = match &swapchain {
+      Some(swapchain) => match vulkano::swapchain::acquire_next_image(swapchain.clone(), None) {
+        Ok((image_index, suboptimal, acquire_future)) => {
+          // the image can still be presented but it doesn't match the surface anymore
+          if suboptimal {
+            self.recreate_target = true;
+          }
+          (image_index, Some(acquire_future))
         }
-      "
+        Err(AcquireError::OutOfDate) => {
+          self.recreate_target = true;
+          return None;
+        }
+        Err(e) => {
+          error!("Failed to acquire the next image: {}", e);
+          return None;
+        }
+      },
+      None => (0, None),
+    };
+
+    let mut builder = match AutoCommandBufferBuilder::primary(
+      self.frames[frame].command_buffer_allocator(),
+      self.queue.queue_family_index(),
+      CommandBufferUsage::OneTimeSubmit,
+    ) {
+      Ok(builder) => builder,
+      Err(e) => {
+        error!("Failed to create the frame's command buffer: {}", e);
+        return None;
       }
+    };
+
+    self.record(&mut builder, commands, image_index, frame);
+
+    // the acquired image has to be rendered into before it's presented
+    if !commands.has_screen_pass() {
+      let mut clear = CommandList::new();
+      clear.push(RenderCommand::BeginPass {
+        target: PassTarget::Screen,
+        clear_color: [0.0, 0.0, 0.0, 1.0],
+      });
+      clear.push(RenderCommand::EndPass);
+      self.record(&mut builder, &clear, image_index, frame);
     }
 
-    mod frag_shader {
-      vulkano_shaders::shader! {
-        ty: "fragment",
-        src: "
-        #version 450
+    // copied before the image is presented, it can't be read once it's back in the swapchain
+    let capture = capture.then(|| match self.geg_target.image(image_index) {
+      Some(image) => GegVkCapture::record(
+        &self.memory_allocator,
+        &mut builder,
+        image,
+        self.geg_target.format(),
+      ),
+      None => Err(GraphicsError::Readback(
+        "the swapchain images can't be copied from".to_string(),
+      )),
+    });
 
-        layout(location = 0) in vec3 v_color;
+    let command_buffer = match builder.build() {
+      Ok(command_buffer) => command_buffer,
+      Err(e) => {
+        error!("Failed to build the frame's command buffer: {}", e);
+        return None;
+      }
+    };
 
-        layout(location = 0) out vec4 f_color;
+    // ordered after the last submitted frame, which may still be rendering
+    let previous = (frame + self.frames.len() - 1) % self.frames.len();
+    let mut future: Box<dyn GpuFuture> = match self.frames[previous].fence() {
+      Some(fence) => Box::new(fence),
+      None => Box::new(sync::now(self.device.clone())),
+    };
+
+    if let Some(acquire_future) = acquire_future {
+      future = Box::new(future.join(acquire_future));
+    }
 
-        void main() {
-          f_color = vec4(v_color, 1.0);
+    let mut future: Box<dyn GpuFuture> =
+      match future.then_execute(self.queue.clone(), command_buffer) {
+        Ok(future) => Box::new(future),
+        Err(e) => {
+          error!("Failed to execute the frame: {}", e);
+          return None;
         }
-      "
+      };
+
+    if let Some(swapchain) = swapchain {
+      future = Box::new(future.then_swapchain_present(
+        self.queue.clone(),
+        SwapchainPresentInfo::swapchain_image_index(swapchain, image_index),
+      ));
+    }
+
+    let fence = match future.then_signal_fence_and_flush() {
+      Ok(fence) => Some(Arc::new(fence)),
+      Err(FlushError::OutOfDate) => {
+        self.recreate_target = true;
+        None
+      }
+      Err(e) => {
+        error!("Failed to submit the frame: {}", e);
+        None
       }
+    };
+    let submitted = fence.is_some();
+    self.frames[frame].set_fence(fence);
+    self.frame_index = (frame + 1) % self.frames.len();
+
+    match capture {
+      Some(Ok(_)) if !submitted => Some(Err(GraphicsError::Readback(
+        "the frame wasn't submitted".to_string(),
+      ))),
+      capture => capture,
     }
+  }
 
-    let vertices = [
-      Vertex {
-        position: [-0.5, 0.5, 0.0],
-        color: [1.0, 0.0, 0.0],
-      },
-      Vertex {
-        position: [0.5, 0.5, 0.0],
-        color: [0.0, 1.0, 0.0],
+  /// translates the recorded commands into vulkan commands, reading the buffers of `frame`
+  fn record(
+    &mut self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    commands: &CommandList,
+    image_index: u32,
+    frame: usize,
+  ) {
+    let mut in_pass = false;
+    let mut pipeline: Option<Arc<GraphicsPipeline>> = None;
+    // pipelines are picked for the format of the pass they draw in
+    let mut format = TargetFormat::Screen;
+    let mut descriptor_sets: Vec<(u32, DescriptorSetHandle)> = Vec::new();
+    let mut bind_descriptor_sets = false;
+
+    for command in commands.commands() {
+      let is_draw = matches!(
+        command,
+        RenderCommand::Draw { .. } | RenderCommand::DrawIndexed { .. }
+      );
+      if is_draw && bind_descriptor_sets {
+        if let Some(pipeline) = &pipeline {
+          self.bind_descriptor_sets(builder, pipeline, &descriptor_sets, frame);
+        }
+        bind_descriptor_sets = false;
+      }
+
+      match command {
+        RenderCommand::BeginPass {
+          target,
+          clear_color,
+        } => {
+          if in_pass {
+            warn!("A pass was started before the previous one ended");
+            builder.end_render_pass().unwrap();
+            in_pass = false;
+          }
+
+          let (framebuffer, dimensions, target_format) = match target {
+            PassTarget::Screen => (
+              self.geg_renderpass.frame_buffers()[image_index as usize].clone(),
+              self.geg_target.dimensions(),
+              TargetFormat::Screen,
+            ),
+            PassTarget::Offscreen(handle) => match self.render_targets.get(&handle.0) {
+              Some(target) => (
+                target.framebuffer.clone(),
+                target.framebuffer.extent(),
+                target.format,
+              ),
+              None => {
+                warn!("Skipping a pass into an unknown render target {:?}", handle);
+                continue;
+              }
+            },
+          };
+
+          builder
+            .begin_render_pass(
+              RenderPassBeginInfo {
+                clear_values: self.geg_renderpass.clear_values(*clear_color),
+                ..RenderPassBeginInfo::framebuffer(framebuffer)
+              },
+              SubpassContents::Inline,
+            )
+            .unwrap()
+            // flipped so clip space has y pointing up like the other backends
+            .set_viewport(
+              0,
+              [Viewport {
+                origin: [0.0, dimensions[1] as f32],
+                dimensions: [dimensions[0] as f32, -(dimensions[1] as f32)],
+                depth_range: 0.0..1.0,
+              }],
+            );
+          in_pass = true;
+          format = target_format;
+
+          // nothing stays bound between passes, like on the other backends
+          pipeline = None;
+          descriptor_sets.clear();
+        }
+
+        RenderCommand::EndPass => {
+          if in_pass {
+            builder.end_render_pass().unwrap();
+            in_pass = false;
+          }
+        }
+
+        RenderCommand::SetPipeline(handle) => match self.pipelines.get(&handle.0) {
+          Some(vk_pipeline) => {
+            let vk_pipeline = vk_pipeline.get(format);
+            builder.bind_pipeline_graphics(vk_pipeline.clone());
+            pipeline = Some(vk_pipeline.clone());
+            bind_descriptor_sets = true;
+          }
+          None => warn!("Binding an unknown pipeline {:?}", handle),
+        },
+
+        RenderCommand::SetVertexBuffer { slot, buffer } => match self.buffers.get(&buffer.0) {
+          Some(geg_buffer) => match geg_buffer.data(frame) {
+            GegVkBufferData::Bytes(data) if geg_buffer.usage() == BufferUsage::Vertex => {
+              builder.bind_vertex_buffers(*slot, data.clone());
+            }
+            _ => warn!("Binding a non vertex buffer as a vertex buffer {:?}", buffer),
+          },
+          None => warn!("Binding an unknown vertex buffer {:?}", buffer),
+        },
+
+        RenderCommand::SetIndexBuffer(buffer) => match self.buffers.get(&buffer.0) {
+          Some(geg_buffer) => match geg_buffer.data(frame) {
+            GegVkBufferData::U16(buffer) => {
+              builder.bind_index_buffer(buffer.clone());
+            }
+            GegVkBufferData::U32(buffer) => {
+              builder.bind_index_buffer(buffer.clone());
+            }
+            GegVkBufferData::Bytes(_) => {
+              warn!("Binding a non index buffer as an index buffer {:?}", buffer)
+            }
+          },
+          None => warn!("Binding an unknown index buffer {:?}", buffer),
+        },
+
+        RenderCommand::SetDescriptorSet { index, set } => {
+          descriptor_sets.retain(|(bound, _)| bound != index);
+          descriptor_sets.push((*index, *set));
+          bind_descriptor_sets = true;
+        }
+
+        RenderCommand::Draw {
+          vertices,
+          instances,
+        } => {
+          if let Err(e) = builder.draw(
+            vertices.len() as u32,
+            instances.len() as u32,
+            vertices.start,
+            instances.start,
+          ) {
+            error!("Draw failed: {}", e);
+          }
+        }
+
+        RenderCommand::DrawIndexed {
+          indices,
+          base_vertex,
+          instances,
+        } => {
+          if let Err(e) = builder.draw_indexed(
+            indices.len() as u32,
+            instances.len() as u32,
+            indices.start,
+            *base_vertex,
+            instances.start,
+          ) {
+            error!("Indexed draw failed: {}", e);
+          }
+        }
+      }
+    }
+
+    if in_pass {
+      warn!("A pass wasn't ended");
+      builder.end_render_pass().unwrap();
+    }
+  }
+
+  /// builds the bound descriptor sets for the layout of `pipeline` and binds them
+  fn bind_descriptor_sets(
+    &mut self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: &Arc<GraphicsPipeline>,
+    descriptor_sets: &[(u32, DescriptorSetHandle)],
+    frame: usize,
+  ) {
+    for (index, set) in descriptor_sets {
+      // the pipeline doesn't read this set
+      let layout = match pipeline.layout().set_layouts().get(*index as usize) {
+        Some(layout) => layout.clone(),
+        None => continue,
+      };
+
+      match self.build_descriptor_set(layout, *set, frame) {
+        Ok(descriptor_set) => {
+          builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            *index,
+            descriptor_set,
+          );
+        }
+        Err(e) => warn!("Skipping the descriptor set {:?}: {}", set, e),
+      }
+    }
+  }
+
+  /// the vulkan set of `set` for `layout`, only built again once a resource changed, like
+  /// the copy of a buffer the frame reads
+  fn build_descriptor_set(
+    &mut self,
+    layout: Arc<DescriptorSetLayout>,
+    set: DescriptorSetHandle,
+    frame: usize,
+  ) -> Result<Arc<PersistentDescriptorSet>, String> {
+    let desc = self
+      .descriptor_sets
+      .get(&set.0)
+      .ok_or_else(|| "the set doesn't exist".to_string())?;
+
+    let mut writes = Vec::new();
+    let mut resources = Vec::new();
+    for entry in &desc.entries {
+      match entry.resource {
+        DescriptorResource::UniformBuffer(buffer) => {
+          match self.buffers.get(&buffer.0).map(|geg_buffer| geg_buffer.data(frame)) {
+            Some(GegVkBufferData::Bytes(data)) => {
+              resources.push(Arc::as_ptr(data) as *const ());
+              writes.push(WriteDescriptorSet::buffer(entry.binding, data.clone()))
+            }
+            _ => return Err(format!("{:?} was destroyed", buffer)),
+          }
+        }
+        DescriptorResource::Texture(texture) => match self.textures.get(&texture.0) {
+          Some(geg_texture) => {
+            resources.push(Arc::as_ptr(&geg_texture.view) as *const ());
+            writes.push(WriteDescriptorSet::image_view(entry.binding, geg_texture.view.clone()))
+          }
+          None => return Err(format!("{:?} was destroyed", texture)),
+        },
+        DescriptorResource::Sampler(sampler) => match self.samplers.get(&sampler.0) {
+          Some(vk_sampler) => {
+            resources.push(Arc::as_ptr(vk_sampler) as *const ());
+            writes.push(WriteDescriptorSet::sampler(entry.binding, vk_sampler.clone()))
+          }
+          None => return Err(format!("{:?} was destroyed", sampler)),
+        },
+      }
+    }
+
+    let built = self.built_descriptor_sets.entry((set.0, frame)).or_default();
+    let cached = built.iter().position(|built| Arc::ptr_eq(&built.layout, &layout));
+    if let Some(index) = cached {
+      if built[index].resources == resources {
+        return Ok(built[index].set.clone());
+      }
+      built.swap_remove(index);
+    }
+
+    let vk_set =
+      PersistentDescriptorSet::new(&self.descriptor_set_allocator, layout.clone(), writes)
+        .map_err(|e| e.to_string())?;
+    built.push(GegVkBuiltSet {
+      layout,
+      resources,
+      set: vk_set.clone(),
+    });
+    Ok(vk_set)
+  }
+}
+
+impl GraphicsDevice for GegVkRenderer {
+  fn backend(&self) -> GegBackend {
+    GegBackend::Vulkano
+  }
+
+  fn surface_size(&self) -> [u32; 2] {
+    self.geg_target.dimensions()
+  }
+
+  fn msaa_samples(&self) -> u32 {
+    self.geg_renderpass.samples() as u32
+  }
+
+  /// marks the target as out of date, it will be recreated before the next frame
+  fn resize(&mut self, width: u32, height: u32) {
+    self.dimensions = [width, height];
+    self.recreate_target = true;
+  }
+
+  fn create_buffer(
+    &mut self,
+    desc: &BufferDesc,
+    data: Option<&[u8]>,
+  ) -> Result<BufferHandle, GraphicsError> {
+    let contents = match data {
+      Some(data) if data.len() as u64 != desc.size => {
+        return Err(GraphicsError::InvalidSize {
+          expected: desc.size,
+          actual: data.len() as u64,
+        });
+      }
+      Some(data) => data.to_vec(),
+      None => vec![0; desc.size as usize],
+    };
+
+    let buffer =
+      GegVkBuffer::new(&self.memory_allocator, desc.usage, contents, self.frames.len())?;
+    let handle = self.next_handle();
+    self.buffers.insert(handle, buffer);
+    Ok(BufferHandle(handle))
+  }
+
+  fn write_buffer(
+    &mut self,
+    buffer: BufferHandle,
+    offset: u64,
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    self
+      .buffers
+      .get_mut(&buffer.0)
+      .ok_or(GraphicsError::InvalidHandle)?
+      .write(&self.memory_allocator, offset, data)
+  }
+
+  fn destroy_buffer(&mut self, buffer: BufferHandle) {
+    // frames in flight keep their own reference to the buffer
+    self.buffers.remove(&buffer.0);
+  }
+
+  fn create_texture(
+    &mut self,
+    desc: &TextureDesc,
+    data: Option<&[u8]>,
+  ) -> Result<TextureHandle, GraphicsError> {
+    let size = desc.width as u64 * desc.height as u64 * desc.format.pixel_size() as u64;
+    let contents = match data {
+      Some(data) if data.len() as u64 != size => {
+        return Err(GraphicsError::InvalidSize {
+          expected: size,
+          actual: data.len() as u64,
+        });
+      }
+      Some(data) => data.to_vec(),
+      None => vec![0; size as usize],
+    };
+
+    // the pixels go through a host visible staging buffer into device local memory,
+    // the call blocks until the copy is done so avoid creating textures every frame
+    let mut uploads = self.uploads()?;
+    let image = ImmutableImage::from_iter(
+      &*self.memory_allocator,
+      contents,
+      ImageDimensions::Dim2d {
+        width: desc.width,
+        height: desc.height,
+        array_layers: 1,
       },
-      Vertex {
-        position: [0.0, -0.25, 0.0],
-        color: [0.0, 0.0, 1.0],
+      MipmapsCount::One,
+      to_vk_texture_format(desc.format),
+      &mut uploads,
+    )
+    .map_err(|e| GraphicsError::Device(e.to_string()))?;
+    self.run_uploads(uploads)?;
+    let view: Arc<dyn ImageViewAbstract> =
+      ImageView::new_default(image).map_err(|e| GraphicsError::Device(e.to_string()))?;
+
+    let handle = self.next_handle();
+    self.textures.insert(
+      handle,
+      GegVkTexture {
+        view,
+        dimensions: [desc.width, desc.height],
+        format: Some(desc.format),
       },
-    ];
-
-    let memory_allocator = StandardMemoryAllocator::new_default(device.clone());
-    let vertex_buffer = CpuAccessibleBuffer::from_iter(
-      &memory_allocator,
-      BufferUsage {
-        vertex_buffer: true,
-        ..BufferUsage::empty()
+    );
+    Ok(TextureHandle(handle))
+  }
+
+  fn write_texture(
+    &mut self,
+    texture: TextureHandle,
+    origin: [u32; 2],
+    size: [u32; 2],
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    let geg_texture = self
+      .textures
+      .get(&texture.0)
+      .ok_or(GraphicsError::InvalidHandle)?;
+    let format = geg_texture.format.ok_or_else(|| {
+      GraphicsError::InvalidUsage(format!("{:?} belongs to a render target", texture))
+    })?;
+    check_texture_write(geg_texture.dimensions, format, origin, size, data)?;
+    let image = geg_texture.view.image();
+
+    // the frames in flight may still sample the old pixels
+    for frame in &mut self.frames {
+      frame.wait();
+    }
+
+    let staging = CpuAccessibleBuffer::from_iter(
+      &*self.memory_allocator,
+      VkBufferUsage {
+        transfer_src: true,
+        ..VkBufferUsage::empty()
       },
-      true,
-      vertices,
+      false,
+      data.iter().copied(),
     )
-    .unwrap();
+    .map_err(|e| GraphicsError::Device(e.to_string()))?;
 
-    let vs = vert_shader::load(device.clone()).expect("failed to create shader module");
-    let fs = frag_shader::load(device.clone()).expect("failed to create shader module");
+    let mut uploads = self.uploads()?;
+    uploads
+      .copy_buffer_to_image(CopyBufferToImageInfo {
+        regions: [BufferImageCopy {
+          image_subresource: image.subresource_layers(),
+          image_offset: [origin[0], origin[1], 0],
+          image_extent: [size[0], size[1], 1],
+          ..Default::default()
+        }]
+        .into(),
+        ..CopyBufferToImageInfo::buffer_image(staging, image)
+      })
+      .map_err(|e| GraphicsError::Device(e.to_string()))?;
+    self.run_uploads(uploads)
+  }
+
+  fn texture_size(&self, texture: TextureHandle) -> Option<[u32; 2]> {
+    self.textures.get(&texture.0).map(|texture| texture.dimensions)
+  }
 
-    let viewport = Viewport {
-      origin: [0.0, 0.0],
-      dimensions: geg_device.window().inner_size().into(),
-      depth_range: 0.0..1.0,
+  fn destroy_texture(&mut self, texture: TextureHandle) {
+    self.textures.remove(&texture.0);
+  }
+
+  fn create_sampler(&mut self, desc: &SamplerDesc) -> Result<SamplerHandle, GraphicsError> {
+    // anisotropy is an optional device feature
+    let anisotropy = match desc.anisotropy() {
+      1 => None,
+      _ if !self.device.enabled_features().sampler_anisotropy => {
+        warn!("Anisotropic filtering isn't supported, creating the sampler without it");
+        None
+      }
+      samples => {
+        let max = self.device.physical_device().properties().max_sampler_anisotropy;
+        Some((samples as f32).min(max))
+      }
     };
 
-    let pipeline = GraphicsPipeline::start()
-      .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-      .vertex_shader(vs.entry_point("main").unwrap(), ())
-      .input_assembly_state(InputAssemblyState::new())
-      .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
-      .fragment_shader(fs.entry_point("main").unwrap(), ())
-      .render_pass(Subpass::from(geg_renderpass.render_pass(), 0).unwrap())
-      .build(device.clone())
-      .unwrap();
-
-    debug!("Renderer created");
-    Self {
-      device: device.clone(),
-      queue,
-      geg_swapchain,
-      geg_renderpass,
-      default_pipeline: pipeline,
-      command_buffer_allocator,
-      lastframe: Some(Box::new(sync::now(device.clone()))),
-      vertex_buffer,
+    let sampler = Sampler::new(
+      self.device.clone(),
+      SamplerCreateInfo {
+        mag_filter: to_vk_filter(desc.mag_filter),
+        min_filter: to_vk_filter(desc.min_filter),
+        address_mode: [
+          to_vk_address_mode(desc.address_mode[0]),
+          to_vk_address_mode(desc.address_mode[1]),
+          // textures are 2d, clamped like the wgpu backend
+          SamplerAddressMode::ClampToEdge,
+        ],
+        anisotropy,
+        ..Default::default()
+      },
+    )
+    .map_err(|e| GraphicsError::InvalidUsage(e.to_string()))?;
+
+    let handle = self.next_handle();
+    self.samplers.insert(handle, sampler);
+    Ok(SamplerHandle(handle))
+  }
+
+  fn destroy_sampler(&mut self, sampler: SamplerHandle) {
+    // frames in flight keep their own reference to the sampler
+    self.samplers.remove(&sampler.0);
+  }
+
+  fn create_pipeline(&mut self, desc: &PipelineDesc) -> Result<PipelineHandle, GraphicsError> {
+    let pipeline = create_pipeline(self.device.clone(), &self.geg_renderpass, desc)?;
+    let handle = self.next_handle();
+    self.pipelines.insert(handle, pipeline);
+    Ok(PipelineHandle(handle))
+  }
+
+  fn builtin_pipeline(&mut self, pipeline: BuiltinPipeline) -> PipelineHandle {
+    if let Some(handle) = self.builtin_pipelines.get(&pipeline) {
+      return *handle;
     }
+
+    let vk_pipeline = create_builtin_pipeline(
+      self.device.clone(),
+      &self.geg_renderpass,
+      pipeline,
+    );
+    let handle = PipelineHandle(self.next_handle());
+    self.pipelines.insert(handle.0, vk_pipeline);
+    self.builtin_pipelines.insert(pipeline, handle);
+    handle
   }
 
-  pub fn render(&mut self) {
-    self.lastframe.as_mut().unwrap().cleanup_finished();
+  fn recreate_pipeline(
+    &mut self,
+    pipeline: PipelineHandle,
+    desc: &PipelineDesc,
+  ) -> Result<(), GraphicsError> {
+    if !self.pipelines.contains_key(&pipeline.0)
+      || self.builtin_pipelines.values().any(|handle| *handle == pipeline)
+    {
+      return Err(GraphicsError::InvalidHandle);
+    }
 
-    let mut builder = AutoCommandBufferBuilder::primary(
-      &self.command_buffer_allocator,
-      self.queue.queue_family_index(),
-      CommandBufferUsage::MultipleSubmit,
+    let vk_pipeline = create_pipeline(self.device.clone(), &self.geg_renderpass, desc)?;
+    self.pipelines.insert(pipeline.0, vk_pipeline);
+    Ok(())
+  }
+
+  fn destroy_pipeline(&mut self, pipeline: PipelineHandle) {
+    // builtin pipelines are shared and live as long as the device
+    if self.builtin_pipelines.values().any(|handle| *handle == pipeline) {
+      return;
+    }
+    self.pipelines.remove(&pipeline.0);
+  }
+
+  fn create_render_target(
+    &mut self,
+    desc: &RenderTargetDesc,
+  ) -> Result<RenderTargetHandle, GraphicsError> {
+    if desc.width == 0 || desc.height == 0 {
+      return Err(GraphicsError::InvalidSize {
+        expected: 1,
+        actual: 0,
+      });
+    }
+
+    let format = match desc.format {
+      TargetFormat::Screen => self.geg_target.format(),
+      TargetFormat::Hdr => HDR_FORMAT,
+    };
+    let image = AttachmentImage::with_usage(
+      &*self.memory_allocator,
+      [desc.width, desc.height],
+      format,
+      ImageUsage {
+        color_attachment: true,
+        sampled: true,
+        ..ImageUsage::empty()
+      },
     )
-    .unwrap();
+    .map_err(|e| GraphicsError::Device(e.to_string()))?;
 
-    let (image_index, suboptimal, acquire_future) =
-      match vulkano::swapchain::acquire_next_image(self.geg_swapchain.swapchain(), None) {
-        Ok(r) => r,
-        Err(AcquireError::OutOfDate) => {
-          return;
-        }
-        Err(e) => panic!("Failed to acquire next image: {e:?}"),
-      };
+    let view: Arc<dyn ImageViewAbstract> =
+      ImageView::new_default(image).map_err(|e| GraphicsError::Device(e.to_string()))?;
+    let framebuffer = self.geg_renderpass.create_frame_buffer(view.clone(), desc.format)?;
 
-    builder
-      .begin_render_pass(
-        RenderPassBeginInfo {
-          clear_values: vec![Some([0.0, 0.0, 0.0, 1.0].into())],
-          ..RenderPassBeginInfo::framebuffer(
-            self.geg_renderpass.frame_buffers()[image_index as usize].clone(),
-          )
-        },
-        SubpassContents::Inline,
-      )
-      .unwrap()
-      // new stuff
-      .bind_pipeline_graphics(self.default_pipeline.clone())
-      .bind_vertex_buffers(0, self.vertex_buffer.clone())
-      .draw(
-        3, 1, 0, 0, // 3 is the number of vertices, 1 is the number of instances
-      )
-      .unwrap()
-      .end_render_pass()
-      .unwrap();
-
-    let command_buffer = builder.build().unwrap();
-
-    let future = self
-      .lastframe
-      .take()
-      .unwrap_or_else(|| Box::new(sync::now(self.device.clone())))
-      .join(acquire_future)
-      .then_execute(self.queue.clone(), command_buffer)
-      .unwrap()
-      .then_swapchain_present(
-        self.queue.clone(),
-        SwapchainPresentInfo::swapchain_image_index(self.geg_swapchain.swapchain(), image_index),
-      )
-      .then_signal_fence_and_flush();
+    let texture = TextureHandle(self.next_handle());
+    self.textures.insert(
+      texture.0,
+      GegVkTexture {
+        view,
+        dimensions: [desc.width, desc.height],
+        format: None,
+      },
+    );
 
-    if future.is_ok() {
-      self.lastframe = Some(Box::new(future.unwrap()));
-    } else {
-      self.lastframe = Some(Box::new(sync::now(self.device.clone())));
+    let handle = self.next_handle();
+    self.render_targets.insert(
+      handle,
+      GegVkRenderTarget {
+        framebuffer,
+        texture,
+        format: desc.format,
+      },
+    );
+    Ok(RenderTargetHandle(handle))
+  }
+
+  fn render_target_texture(&self, target: RenderTargetHandle) -> Option<TextureHandle> {
+    self.render_targets.get(&target.0).map(|target| target.texture)
+  }
+
+  fn destroy_render_target(&mut self, target: RenderTargetHandle) {
+    if let Some(target) = self.render_targets.remove(&target.0) {
+      self.textures.remove(&target.texture.0);
     }
   }
+
+  fn create_descriptor_set(
+    &mut self,
+    desc: &DescriptorSetDesc,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    for entry in &desc.entries {
+      match entry.resource {
+        DescriptorResource::UniformBuffer(buffer) => {
+          let geg_buffer = self
+            .buffers
+            .get(&buffer.0)
+            .ok_or(GraphicsError::InvalidHandle)?;
+          if geg_buffer.usage() != BufferUsage::Uniform {
+            return Err(GraphicsError::InvalidUsage(format!(
+              "{:?} isn't a uniform buffer",
+              buffer
+            )));
+          }
+        }
+        DescriptorResource::Texture(texture) if !self.textures.contains_key(&texture.0) => {
+          return Err(GraphicsError::InvalidHandle)
+        }
+        DescriptorResource::Sampler(sampler) if !self.samplers.contains_key(&sampler.0) => {
+          return Err(GraphicsError::InvalidHandle)
+        }
+        DescriptorResource::Texture(_) | DescriptorResource::Sampler(_) => (),
+      }
+    }
+
+    let handle = self.next_handle();
+    self.descriptor_sets.insert(handle, desc.clone());
+    Ok(DescriptorSetHandle(handle))
+  }
+
+  fn destroy_descriptor_set(&mut self, set: DescriptorSetHandle) {
+    self.descriptor_sets.remove(&set.0);
+    self.built_descriptor_sets.retain(|(handle, _), _| *handle != set.0);
+  }
+
+  fn submit(&mut self, commands: &CommandList) {
+    self.render(commands, false);
+  }
+
+  fn submit_and_capture(&mut self, commands: &CommandList) -> Result<RgbaImage, GraphicsError> {
+    let capture = match self.render(commands, true) {
+      Some(capture) => capture?,
+      None => return Err(GraphicsError::Readback("the frame was skipped".to_string())),
+    };
+
+    // the copy is done once the gpu is done with the frame that was just submitted
+    let frame = (self.frame_index + self.frames.len() - 1) % self.frames.len();
+    self.frames[frame].wait();
+    capture.read()
+  }
 }
@@ -1,67 +1,277 @@
-use spdlog::debug;
+use spdlog::prelude::*;
 use std::sync::Arc;
-use vulkano::{
-  image::view::ImageView,
-  render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::{
+  view::{ImageView, ImageViewAbstract},
+  AttachmentImage, SampleCount, SampleCounts,
 };
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass};
 
-use super::{device::GegVkDevice, swapchain::GegVkSwapchain};
+use super::{device::GegVkDevice, target::GegVkTarget};
+use crate::backend::GraphicsOptions;
+use crate::traits::{GraphicsError, TargetFormat};
 
+/// the color format of `TargetFormat::Hdr` targets
+pub(super) const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// a single subpass with a color, a depth and, with msaa, a resolve attachment
+/// render targets use it too, hdr targets use a copy with a float color attachment
 pub(super) struct GegVkRenderpass {
   render_pass: Arc<RenderPass>,
+  hdr_render_pass: Arc<RenderPass>,
+  memory_allocator: Arc<StandardMemoryAllocator>,
+  samples: SampleCount,
+  depth_format: Format,
   frame_buffers: Vec<Arc<Framebuffer>>,
 }
 
 impl GegVkRenderpass {
-  pub fn new(geg_device: GegVkDevice, geg_swapchain: GegVkSwapchain) -> Self {
-    let render_pass = vulkano::single_pass_renderpass!(
-      geg_device.device(),
+  pub fn new(
+    geg_device: GegVkDevice,
+    geg_target: &GegVkTarget,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    options: GraphicsOptions,
+  ) -> Self {
+    let physical_device = geg_device.physical_device();
+    let samples = supported_samples(&physical_device, options.msaa_samples);
+    let depth_format = depth_format(&physical_device, options.stencil);
+    info!("Using {:?} msaa and a {:?} depth attachment", samples, depth_format);
+
+    let device = geg_device.device();
+    let color_format = geg_target.format();
+    let render_pass = create_render_pass(device.clone(), color_format, depth_format, samples);
+    let hdr_render_pass = create_render_pass(device, HDR_FORMAT, depth_format, samples);
+
+    let mut geg_renderpass = Self {
+      render_pass,
+      hdr_render_pass,
+      memory_allocator,
+      samples,
+      depth_format,
+      frame_buffers: Vec::new(),
+    };
+    geg_renderpass.frame_buffers = geg_renderpass.create_frame_buffers(geg_target);
+
+    debug!("Renderpass created");
+
+    geg_renderpass
+  }
+
+  /// regenerates the framebuffers and their depth and msaa images,
+  /// should be called after the target is recreated
+  pub fn recreate_frame_buffers(&mut self, geg_target: &GegVkTarget) {
+    self.frame_buffers = self.create_frame_buffers(geg_target);
+    debug!("Framebuffers recreated");
+  }
+
+  /// a framebuffer that ends up in `view`, with its own depth and msaa images
+  /// `format` has to match the format of `view`
+  pub fn create_frame_buffer(
+    &self,
+    view: Arc<dyn ImageViewAbstract>,
+    format: TargetFormat,
+  ) -> Result<Arc<Framebuffer>, GraphicsError> {
+    let dimensions = view.image().dimensions().width_height();
+    let depth = self.create_attachment(dimensions, self.depth_format)?;
+
+    let attachments = if self.samples == SampleCount::Sample1 {
+      vec![view, depth]
+    } else {
+      let color = self.create_attachment(dimensions, view.format().unwrap())?;
+      vec![color, depth, view]
+    };
+
+    Framebuffer::new(
+      self.render_pass(format),
+      FramebufferCreateInfo {
+        attachments,
+        ..Default::default()
+      },
+    )
+    .map_err(|e| GraphicsError::Device(e.to_string()))
+  }
+
+  /// the clear values of a pass, in the order of the attachments
+  pub fn clear_values(&self, clear_color: [f32; 4]) -> Vec<Option<ClearValue>> {
+    let depth = if self.depth_format.aspects().stencil {
+      ClearValue::DepthStencil((1.0, 0))
+    } else {
+      ClearValue::Depth(1.0)
+    };
+
+    let mut clear_values = vec![Some(clear_color.into()), Some(depth)];
+    if self.samples != SampleCount::Sample1 {
+      // the resolve attachment is overwritten
+      clear_values.push(None);
+    }
+    clear_values
+  }
+
+  fn create_frame_buffers(&self, geg_target: &GegVkTarget) -> Vec<Arc<Framebuffer>> {
+    geg_target
+      .image_views()
+      .into_iter()
+      .map(|view| {
+        self
+          .create_frame_buffer(view, TargetFormat::Screen)
+          .expect("failed to create frame buffer")
+      })
+      .collect::<Vec<_>>()
+  }
+
+  fn create_attachment(
+    &self,
+    dimensions: [u32; 2],
+    format: Format,
+  ) -> Result<Arc<dyn ImageViewAbstract>, GraphicsError> {
+    let image = AttachmentImage::transient_multisampled(
+      &*self.memory_allocator,
+      dimensions,
+      self.samples,
+      format,
+    )
+    .map_err(|e| GraphicsError::Device(e.to_string()))?;
+    let view: Arc<dyn ImageViewAbstract> =
+      ImageView::new_default(image).map_err(|e| GraphicsError::Device(e.to_string()))?;
+    Ok(view)
+  }
+
+  // getters
+  /// the render pass drawing into targets of `format`
+  pub fn render_pass(&self, format: TargetFormat) -> Arc<RenderPass> {
+    match format {
+      TargetFormat::Screen => self.render_pass.clone(),
+      TargetFormat::Hdr => self.hdr_render_pass.clone(),
+    }
+  }
+
+  pub fn frame_buffers(&self) -> &Vec<Arc<Framebuffer>> {
+    &self.frame_buffers
+  }
+
+  pub fn samples(&self) -> SampleCount {
+    self.samples
+  }
+}
+
+/// a single subpass drawing into a `color_format` image, resolved from a multisampled one with
+/// msaa, the formats can't be named like the attachments of the macro
+fn create_render_pass(
+  device: Arc<Device>,
+  color_format: Format,
+  depth_format: Format,
+  samples: SampleCount,
+) -> Arc<RenderPass> {
+  if samples == SampleCount::Sample1 {
+    vulkano::single_pass_renderpass!(
+      device,
       attachments: {
         color: {
           load: Clear,
           store: Store,
-          format: geg_swapchain.format(),
+          format: color_format,
+          samples: 1,
+        },
+        depth: {
+          load: Clear,
+          store: DontCare,
+          format: depth_format,
           samples: 1,
         }
       },
       pass: {
         color: [color],
-        depth_stencil: {}
+        depth_stencil: {depth}
       }
     )
-    .unwrap();
-
-    let frame_buffers = geg_swapchain
-      .images()
-      .iter()
-      .map(|image| {
-        let view = ImageView::new_default(image.clone()).unwrap();
-
-        Framebuffer::new(
-          render_pass.clone(),
-          FramebufferCreateInfo {
-            attachments: vec![view],
-            ..Default::default()
-          },
-        )
-        .unwrap()
-      })
-      .collect::<Vec<_>>();
+    .unwrap()
+  } else {
+    // the multisampled images are only kept until they are resolved into the target
+    vulkano::single_pass_renderpass!(
+      device,
+      attachments: {
+        color: {
+          load: Clear,
+          store: DontCare,
+          format: color_format,
+          samples: samples as u32,
+        },
+        depth: {
+          load: Clear,
+          store: DontCare,
+          format: depth_format,
+          samples: samples as u32,
+        },
+        resolve: {
+          load: DontCare,
+          store: Store,
+          format: color_format,
+          samples: 1,
+        }
+      },
+      pass: {
+        color: [color],
+        depth_stencil: {depth},
+        resolve: [resolve]
+      }
+    )
+    .unwrap()
+  }
+}
 
-    debug!("Renderpass created");
+/// the highest sample count up to `requested` that works for both color and depth
+fn supported_samples(physical_device: &PhysicalDevice, requested: u32) -> SampleCount {
+  let properties = physical_device.properties();
+  let color = properties.framebuffer_color_sample_counts;
+  let depth = properties.framebuffer_depth_sample_counts;
+  let is_supported = |counts: SampleCounts, samples: u32| match samples {
+    1 => counts.sample1,
+    2 => counts.sample2,
+    4 => counts.sample4,
+    8 => counts.sample8,
+    16 => counts.sample16,
+    32 => counts.sample32,
+    64 => counts.sample64,
+    _ => false,
+  };
 
-    Self {
-      render_pass,
-      frame_buffers,
-    }
-  }
+  let requested = requested.max(1);
+  let samples = [64, 32, 16, 8, 4, 2]
+    .into_iter()
+    .find(|&samples| {
+      samples <= requested && is_supported(color, samples) && is_supported(depth, samples)
+    })
+    .unwrap_or(1);
 
-  // getters
-  pub fn render_pass(&self) -> Arc<RenderPass> {
-    self.render_pass.clone()
+  if samples != requested {
+    warn!("{}x msaa isn't supported, using {}x", requested, samples);
   }
+  SampleCount::try_from(samples).unwrap()
+}
 
-  pub fn frame_buffers(&self) -> &Vec<Arc<Framebuffer>> {
-    &self.frame_buffers
-  }
+/// the first depth format the device can render into
+fn depth_format(physical_device: &PhysicalDevice, stencil: bool) -> Format {
+  let candidates: &[Format] = if stencil {
+    &[
+      Format::D24_UNORM_S8_UINT,
+      Format::D32_SFLOAT_S8_UINT,
+      Format::D16_UNORM_S8_UINT,
+    ]
+  } else {
+    &[Format::D32_SFLOAT, Format::X8_D24_UNORM_PACK32, Format::D16_UNORM]
+  };
+
+  candidates
+    .iter()
+    .copied()
+    .find(|&format| {
+      matches!(
+        physical_device.format_properties(format),
+        Ok(properties) if properties.optimal_tiling_features.depth_stencil_attachment
+      )
+    })
+    .expect("failed to find a supported depth format")
 }
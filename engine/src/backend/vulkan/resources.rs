@@ -0,0 +1,264 @@
+use crate::traits::{
+  AddressMode, BufferUsage, FilterMode, GraphicsError, IndexFormat, TargetFormat, TextureFormat,
+  TextureHandle,
+};
+
+use spdlog::prelude::*;
+use std::ops::Range;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage as VkBufferUsage, CpuAccessibleBuffer};
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::PersistentDescriptorSet;
+use vulkano::format::Format;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::render_pass::Framebuffer;
+use vulkano::sampler::{Filter, SamplerAddressMode};
+
+/// the gpu side of a buffer, index buffers are typed so they can be bound
+#[derive(Clone)]
+pub(super) enum GegVkBufferData {
+  Bytes(Arc<CpuAccessibleBuffer<[u8]>>),
+  U16(Arc<CpuAccessibleBuffer<[u16]>>),
+  U32(Arc<CpuAccessibleBuffer<[u32]>>),
+}
+
+/// a host visible buffer and a copy of its content
+/// written buffers get a gpu copy per frame in flight, the content is copied into the one
+/// of a frame once the gpu is done with it, so a write never races with a frame in flight
+pub(super) struct GegVkBuffer {
+  usage: BufferUsage,
+  contents: Vec<u8>,
+  frames: usize,
+  // buffers that are never written keep the single copy they were created with
+  copies: Vec<GegVkBufferCopy>,
+}
+
+struct GegVkBufferCopy {
+  data: GegVkBufferData,
+  // the bytes written since this copy was last updated
+  dirty: Option<Range<usize>>,
+}
+
+impl GegVkBuffer {
+  pub fn new(
+    memory_allocator: &StandardMemoryAllocator,
+    usage: BufferUsage,
+    contents: Vec<u8>,
+    frames: usize,
+  ) -> Result<Self, GraphicsError> {
+    if contents.is_empty() {
+      return Err(GraphicsError::InvalidSize {
+        expected: 1,
+        actual: 0,
+      });
+    }
+
+    let data = Self::create_data(memory_allocator, usage, &contents);
+    Ok(Self {
+      usage,
+      contents,
+      frames,
+      copies: vec![GegVkBufferCopy { data, dirty: None }],
+    })
+  }
+
+  /// updates the content, the copies are updated by `GegVkBuffer::sync`
+  pub fn write(
+    &mut self,
+    memory_allocator: &StandardMemoryAllocator,
+    offset: u64,
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    let start = offset as usize;
+    let end = start + data.len();
+    if end > self.contents.len() {
+      return Err(GraphicsError::InvalidSize {
+        expected: self.contents.len() as u64,
+        actual: end as u64,
+      });
+    }
+
+    self.contents[start..end].copy_from_slice(data);
+
+    for copy in &mut self.copies {
+      copy.dirty = Some(match copy.dirty.take() {
+        Some(dirty) => dirty.start.min(start)..dirty.end.max(end),
+        None => start..end,
+      });
+    }
+
+    // the other frames get their own copy the first time the buffer is written
+    while self.copies.len() < self.frames {
+      self.copies.push(GegVkBufferCopy {
+        data: Self::create_data(memory_allocator, self.usage, &self.contents),
+        dirty: None,
+      });
+    }
+    Ok(())
+  }
+
+  /// copies the written content into the copy of `frame`
+  /// the gpu must be done with the previous frame that used it
+  pub fn sync(&mut self, memory_allocator: &StandardMemoryAllocator, frame: usize) {
+    let copy = match self.copies.get_mut(frame) {
+      Some(copy) => copy,
+      None => return,
+    };
+    let dirty = match copy.dirty.take() {
+      Some(dirty) => dirty,
+      None => return,
+    };
+
+    let written = match &copy.data {
+      GegVkBufferData::Bytes(buffer) => buffer
+        .write()
+        .map(|mut lock| lock[dirty.clone()].copy_from_slice(&self.contents[dirty.clone()])),
+      GegVkBufferData::U16(buffer) => buffer.write().map(|mut lock| {
+        let dirty = aligned(dirty.clone(), 2);
+        for (index, c) in self.contents[dirty.clone()].chunks_exact(2).enumerate() {
+          lock[dirty.start / 2 + index] = u16::from_ne_bytes([c[0], c[1]]);
+        }
+      }),
+      GegVkBufferData::U32(buffer) => buffer.write().map(|mut lock| {
+        let dirty = aligned(dirty.clone(), 4);
+        for (index, c) in self.contents[dirty.clone()].chunks_exact(4).enumerate() {
+          lock[dirty.start / 4 + index] = u32::from_ne_bytes([c[0], c[1], c[2], c[3]]);
+        }
+      }),
+    };
+
+    // shouldn't happen once the frame's fence is signaled, a new copy is always safe
+    if let Err(e) = written {
+      warn!("Recreating a buffer still used by the gpu: {}", e);
+      copy.data = Self::create_data(memory_allocator, self.usage, &self.contents);
+    }
+  }
+
+  pub fn usage(&self) -> BufferUsage {
+    self.usage
+  }
+
+  /// the copy drawn by `frame`
+  pub fn data(&self, frame: usize) -> &GegVkBufferData {
+    &self.copies[frame.min(self.copies.len() - 1)].data
+  }
+
+  fn create_data(
+    memory_allocator: &StandardMemoryAllocator,
+    usage: BufferUsage,
+    contents: &[u8],
+  ) -> GegVkBufferData {
+    match usage {
+      BufferUsage::Uniform => GegVkBufferData::Bytes(
+        CpuAccessibleBuffer::from_iter(
+          memory_allocator,
+          VkBufferUsage {
+            uniform_buffer: true,
+            ..VkBufferUsage::empty()
+          },
+          false,
+          contents.iter().copied(),
+        )
+        .expect("failed to create uniform buffer"),
+      ),
+
+      BufferUsage::Vertex => GegVkBufferData::Bytes(
+        CpuAccessibleBuffer::from_iter(
+          memory_allocator,
+          VkBufferUsage {
+            vertex_buffer: true,
+            ..VkBufferUsage::empty()
+          },
+          false,
+          contents.iter().copied(),
+        )
+        .expect("failed to create vertex buffer"),
+      ),
+
+      BufferUsage::Index(IndexFormat::U16) => GegVkBufferData::U16(
+        CpuAccessibleBuffer::from_iter(
+          memory_allocator,
+          VkBufferUsage {
+            index_buffer: true,
+            ..VkBufferUsage::empty()
+          },
+          false,
+          contents
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]])),
+        )
+        .expect("failed to create index buffer"),
+      ),
+
+      BufferUsage::Index(IndexFormat::U32) => GegVkBufferData::U32(
+        CpuAccessibleBuffer::from_iter(
+          memory_allocator,
+          VkBufferUsage {
+            index_buffer: true,
+            ..VkBufferUsage::empty()
+          },
+          false,
+          contents
+            .chunks_exact(4)
+            .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]])),
+        )
+        .expect("failed to create index buffer"),
+      ),
+    }
+  }
+}
+
+/// widens a byte range to whole elements of `size` bytes
+fn aligned(range: Range<usize>, size: usize) -> Range<usize> {
+  range.start / size * size..(range.end + size - 1) / size * size
+}
+
+/// a sampled image, render targets register their color image as one too
+pub(super) struct GegVkTexture {
+  pub view: Arc<dyn ImageViewAbstract>,
+  pub dimensions: [u32; 2],
+  /// `None` for the textures of render targets, they can't be written
+  pub format: Option<TextureFormat>,
+}
+
+/// an offscreen color image that passes can render into
+pub(super) struct GegVkRenderTarget {
+  pub framebuffer: Arc<Framebuffer>,
+  pub texture: TextureHandle,
+  pub format: TargetFormat,
+}
+
+/// a vulkan set built from a descriptor set for the layout of a pipeline, reused until the
+/// resources it was built from change
+pub(super) struct GegVkBuiltSet {
+  pub layout: Arc<DescriptorSetLayout>,
+  // the addresses of the buffers, views and samplers, the set keeps them alive so they
+  // can't be reused by other resources
+  pub resources: Vec<*const ()>,
+  pub set: Arc<PersistentDescriptorSet>,
+}
+
+pub(super) fn to_vk_texture_format(format: TextureFormat) -> Format {
+  match format {
+    TextureFormat::Rgba8Unorm => Format::R8G8B8A8_UNORM,
+    TextureFormat::Rgba8Srgb => Format::R8G8B8A8_SRGB,
+    TextureFormat::R8Unorm => Format::R8_UNORM,
+    TextureFormat::Rgba16Float => Format::R16G16B16A16_SFLOAT,
+  }
+}
+
+pub(super) fn to_vk_address_mode(mode: AddressMode) -> SamplerAddressMode {
+  match mode {
+    AddressMode::ClampToEdge => SamplerAddressMode::ClampToEdge,
+    AddressMode::Repeat => SamplerAddressMode::Repeat,
+    AddressMode::MirrorRepeat => SamplerAddressMode::MirroredRepeat,
+  }
+}
+
+pub(super) fn to_vk_filter(mode: FilterMode) -> Filter {
+  match mode {
+    FilterMode::Nearest => Filter::Nearest,
+    FilterMode::Linear => Filter::Linear,
+  }
+}
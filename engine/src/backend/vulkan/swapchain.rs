@@ -4,12 +4,17 @@ use std::sync::Arc;
 use vulkano::format::Format;
 use vulkano::image::ImageUsage;
 use vulkano::image::SwapchainImage;
-use vulkano::swapchain::{ColorSpace, Swapchain, SwapchainCreateInfo, PresentMode};
+use vulkano::swapchain::{
+  ColorSpace, PresentMode, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
+};
 
 use spdlog::prelude::*;
+use winit::window::Window;
 
 #[derive(Clone)]
 pub(super) struct GegVkSwapchain {
+  // read for the extent when the swapchain goes out of date without a resize event
+  window: Arc<Window>,
   swapchain: Arc<Swapchain>,
   images: Vec<Arc<SwapchainImage>>,
   format: Format,
@@ -18,7 +23,9 @@ pub(super) struct GegVkSwapchain {
 
 impl GegVkSwapchain {
   pub fn new(geg_device: GegVkDevice) -> Self {
-    let surface = geg_device.surface();
+    let surface = geg_device
+      .surface()
+      .expect("can't create a swapchain without a surface");
     let physical_device = geg_device.physical_device();
 
     let caps = physical_device
@@ -44,7 +51,10 @@ impl GegVkSwapchain {
         )
       });
 
-    let dimensions = geg_device.window().inner_size();
+    let window = geg_device
+      .window()
+      .expect("can't create a swapchain without a window");
+    let dimensions = window.inner_size();
 
     let present_mode = physical_device
       .surface_present_modes(&surface)
@@ -57,7 +67,7 @@ impl GegVkSwapchain {
 
     let (swapchain, images) = Swapchain::new(
       geg_device.device(),
-      geg_device.surface(),
+      surface,
       SwapchainCreateInfo {
         min_image_count: caps.min_image_count + 1, // How many buffers to use in the swapchain
         image_extent: dimensions.into(),
@@ -66,6 +76,8 @@ impl GegVkSwapchain {
         present_mode,
         image_usage: ImageUsage {
           color_attachment: true, // What the images are going to be used for
+          // copied from for screenshots when the surface allows it
+          transfer_src: caps.supported_usage_flags.transfer_src,
           ..Default::default()
         },
         composite_alpha,
@@ -77,6 +89,7 @@ impl GegVkSwapchain {
     debug!("Swapchain created");
 
     Self {
+      window,
       swapchain,
       images,
       format,
@@ -84,6 +97,25 @@ impl GegVkSwapchain {
     }
   }
 
+  /// recreates the swapchain with a new extent, returns `false` if the surface can't be
+  /// used with that extent right now (e.g. the window is minimized)
+  pub fn recreate(&mut self, dimensions: [u32; 2]) -> bool {
+    let (swapchain, images) = match self.swapchain.recreate(SwapchainCreateInfo {
+      image_extent: dimensions,
+      ..self.swapchain.create_info()
+    }) {
+      Ok(r) => r,
+      Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return false,
+      Err(e) => panic!("failed to recreate swapchain: {e:?}"),
+    };
+
+    self.swapchain = swapchain;
+    self.images = images;
+
+    debug!("Swapchain recreated: {:?}", dimensions);
+    true
+  }
+
   // getters
   pub fn swapchain(&self) -> Arc<Swapchain> {
     self.swapchain.clone()
@@ -100,4 +132,13 @@ impl GegVkSwapchain {
   pub fn color_space(&self) -> ColorSpace {
     self.color_space
   }
+
+  pub fn dimensions(&self) -> [u32; 2] {
+    self.swapchain.image_extent()
+  }
+
+  /// the current size of the window, the extent the swapchain should have
+  pub fn window_dimensions(&self) -> [u32; 2] {
+    self.window.inner_size().into()
+  }
 }
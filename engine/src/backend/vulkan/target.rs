@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::ImageAccess;
+
+use super::{offscreen::GegVkOffscreen, swapchain::GegVkSwapchain};
+
+/// what the renderer draws into, the swapchain images when there is a window
+/// or a single offscreen image when running headless
+pub(super) enum GegVkTarget {
+  Swapchain(GegVkSwapchain),
+  Offscreen(GegVkOffscreen),
+}
+
+impl GegVkTarget {
+  /// recreates the target images with a new extent
+  /// returns `false` if the target can't be recreated right now
+  pub fn recreate(&mut self, dimensions: [u32; 2]) -> bool {
+    match self {
+      GegVkTarget::Swapchain(swapchain) => swapchain.recreate(dimensions),
+      GegVkTarget::Offscreen(offscreen) => {
+        offscreen.recreate(dimensions);
+        true
+      }
+    }
+  }
+
+  /// one view per image that can be rendered into
+  pub fn image_views(&self) -> Vec<Arc<dyn ImageViewAbstract>> {
+    match self {
+      GegVkTarget::Swapchain(swapchain) => swapchain
+        .images()
+        .iter()
+        .map(|image| ImageView::new_default(image.clone()).unwrap() as Arc<dyn ImageViewAbstract>)
+        .collect(),
+      GegVkTarget::Offscreen(offscreen) => {
+        vec![ImageView::new_default(offscreen.image()).unwrap()]
+      }
+    }
+  }
+
+  /// the image at `image_index`, `None` if it can't be copied from
+  pub fn image(&self, image_index: u32) -> Option<Arc<dyn ImageAccess>> {
+    match self {
+      GegVkTarget::Swapchain(swapchain) => {
+        if !swapchain.swapchain().image_usage().transfer_src {
+          return None;
+        }
+        let image = swapchain.images().get(image_index as usize)?.clone();
+        Some(image as Arc<dyn ImageAccess>)
+      }
+      GegVkTarget::Offscreen(offscreen) => Some(offscreen.image() as Arc<dyn ImageAccess>),
+    }
+  }
+
+  pub fn format(&self) -> Format {
+    match self {
+      GegVkTarget::Swapchain(swapchain) => swapchain.format(),
+      GegVkTarget::Offscreen(offscreen) => offscreen.format(),
+    }
+  }
+
+  pub fn dimensions(&self) -> [u32; 2] {
+    match self {
+      GegVkTarget::Swapchain(swapchain) => swapchain.dimensions(),
+      GegVkTarget::Offscreen(offscreen) => offscreen.dimensions(),
+    }
+  }
+
+  /// the size of the window, `None` for the offscreen target that only changes on resize
+  pub fn window_dimensions(&self) -> Option<[u32; 2]> {
+    match self {
+      GegVkTarget::Swapchain(swapchain) => Some(swapchain.window_dimensions()),
+      GegVkTarget::Offscreen(_) => None,
+    }
+  }
+}
@@ -0,0 +1,103 @@
+use crate::traits::GraphicsError;
+
+use image::RgbaImage;
+use std::num::NonZeroU32;
+use std::sync::mpsc;
+
+/// a copy of the screen in a buffer the cpu can map, to read a frame back
+pub(super) struct GegWgpuCapture {
+  buffer: wgpu::Buffer,
+  dimensions: [u32; 2],
+  // the rows of a texture copy are padded to 256 bytes
+  padded_row: u32,
+  bgra: bool,
+}
+
+impl GegWgpuCapture {
+  /// records a copy of `texture` into a new buffer, read once the encoder is submitted
+  pub fn record(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    dimensions: [u32; 2],
+  ) -> Result<Self, GraphicsError> {
+    let bgra = match format {
+      wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+      wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+      _ => {
+        return Err(GraphicsError::Readback(format!(
+          "can't read back a {:?} screen",
+          format
+        )))
+      }
+    };
+
+    let [width, height] = dimensions;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row = (width * 4).div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("geg capture"),
+      size: padded_row as u64 * height as u64,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+
+    encoder.copy_texture_to_buffer(
+      texture.as_image_copy(),
+      wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: NonZeroU32::new(padded_row),
+          rows_per_image: None,
+        },
+      },
+      wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+    );
+
+    Ok(Self {
+      buffer,
+      dimensions,
+      padded_row,
+      bgra,
+    })
+  }
+
+  /// waits for the copy and returns the pixels without the row padding
+  pub fn read(self, device: &wgpu::Device) -> Result<RgbaImage, GraphicsError> {
+    let slice = self.buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+      let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+
+    match receiver.recv() {
+      Ok(Ok(())) => {}
+      Ok(Err(e)) => return Err(GraphicsError::Readback(e.to_string())),
+      Err(_) => return Err(GraphicsError::Readback("the buffer was never mapped".to_string())),
+    }
+
+    let [width, height] = self.dimensions;
+    let row = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row * height as usize);
+    for padded in slice.get_mapped_range().chunks_exact(self.padded_row as usize) {
+      pixels.extend_from_slice(&padded[..row]);
+    }
+    self.buffer.unmap();
+
+    if self.bgra {
+      for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+      }
+    }
+
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("the capture has the wrong size"))
+  }
+}
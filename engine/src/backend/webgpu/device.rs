@@ -0,0 +1,71 @@
+use spdlog::prelude::*;
+use winit::window::Window;
+
+/// the wgpu adapter and device, with a surface when there is a window
+/// the backends can be picked with the `WGPU_BACKEND` env variable, e.g. `WGPU_BACKEND=gl`
+pub(crate) struct GegWgpuDevice {
+  surface: Option<wgpu::Surface>,
+  adapter: wgpu::Adapter,
+  device: wgpu::Device,
+  queue: wgpu::Queue,
+}
+
+impl GegWgpuDevice {
+  /// the window must outlive the surface, the target that takes it keeps the window alive
+  pub fn new(win: Option<&Window>) -> Self {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all);
+    let instance = wgpu::Instance::new(backends);
+
+    // safety: the window is kept alive as long as the surface
+    let surface = win.map(|win| unsafe { instance.create_surface(win) });
+
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+      power_preference: wgpu::PowerPreference::HighPerformance,
+      force_fallback_adapter: false,
+      compatible_surface: surface.as_ref(),
+    }))
+    .expect("failed to find a suitable adapter");
+
+    let info = adapter.get_info();
+    info!("Using {} ({:?}) as the wgpu adapter", info.name, info.backend);
+
+    let (device, queue) = pollster::block_on(adapter.request_device(
+      &wgpu::DeviceDescriptor {
+        label: Some("geg device"),
+        features: wgpu::Features::empty(),
+        // so the gl fallback works too
+        limits: wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+      },
+      None,
+    ))
+    .expect("failed to create device");
+
+    // wgpu panics on validation errors by default
+    device.on_uncaptured_error(|e| error!("wgpu: {}", e));
+
+    debug!("Device created");
+    Self {
+      surface,
+      adapter,
+      device,
+      queue,
+    }
+  }
+
+  /// the surface is handed over to the target that presents to it
+  pub fn take_surface(&mut self) -> Option<wgpu::Surface> {
+    self.surface.take()
+  }
+
+  pub fn adapter(&self) -> &wgpu::Adapter {
+    &self.adapter
+  }
+
+  pub fn device(&self) -> &wgpu::Device {
+    &self.device
+  }
+
+  pub fn queue(&self) -> &wgpu::Queue {
+    &self.queue
+  }
+}
@@ -0,0 +1,6 @@
+pub(super) mod capture;
+pub(super) mod device;
+pub(super) mod target;
+pub(super) mod resources;
+pub(super) mod pipeline;
+pub(super) mod renderer;
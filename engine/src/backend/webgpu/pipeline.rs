@@ -0,0 +1,440 @@
+use crate::camera::CameraUniform;
+use crate::mesh::MeshInstance;
+use crate::shader;
+use crate::sprite::SpriteVertex;
+use crate::traits::{
+  BlendMode, BuiltinPipeline, ColorVertex, CullMode, DepthMode, DescriptorSetLayout,
+  DescriptorType, GraphicsError, PipelineDesc, PrimitiveTopology, ShaderDesc, ShaderStage,
+  TargetFormat, VertexFormat, VertexLayout, VertexStepMode,
+};
+
+use super::resources::AttachmentFormats;
+
+use std::borrow::Cow;
+
+const BUILTIN_SHADER: &str = "
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec3<f32>,
+};
+
+struct Camera {
+  view_projection: mat4x4<f32>,
+  view: mat4x4<f32>,
+  projection: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(1) color: vec3<f32>) -> VertexOutput {
+  var out: VertexOutput;
+  out.position = vec4<f32>(position, 1.0);
+  out.color = color;
+  return out;
+}
+
+@vertex
+fn vs_mesh(
+  @location(0) position: vec3<f32>,
+  @location(1) color: vec3<f32>,
+  @location(12) transform_0: vec4<f32>,
+  @location(13) transform_1: vec4<f32>,
+  @location(14) transform_2: vec4<f32>,
+  @location(15) transform_3: vec4<f32>,
+) -> VertexOutput {
+  let transform = mat4x4<f32>(transform_0, transform_1, transform_2, transform_3);
+  var out: VertexOutput;
+  out.position = camera.view_projection * transform * vec4<f32>(position, 1.0);
+  out.color = color;
+  return out;
+}
+
+@vertex
+fn vs_lines(@location(0) position: vec3<f32>, @location(1) color: vec3<f32>) -> VertexOutput {
+  var out: VertexOutput;
+  out.position = camera.view_projection * vec4<f32>(position, 1.0);
+  out.color = color;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  return vec4<f32>(in.color, 1.0);
+}
+
+struct SpriteOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+  @location(1) color: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var sprite_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var sprite_sampler: sampler;
+
+@vertex
+fn vs_sprite(
+  @location(0) position: vec2<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) color: vec4<f32>,
+) -> SpriteOutput {
+  var out: SpriteOutput;
+  out.position = camera.view_projection * vec4<f32>(position, 0.0, 1.0);
+  out.uv = uv;
+  out.color = color;
+  return out;
+}
+
+@fragment
+fn fs_sprite(in: SpriteOutput) -> @location(0) vec4<f32> {
+  return textureSample(sprite_texture, sprite_sampler, in.uv) * in.color;
+}
+";
+
+/// the fixed function state of a pipeline
+#[derive(Clone, Copy)]
+struct PipelineState<'a> {
+  vertex_layouts: &'a [VertexLayout],
+  descriptor_set_layouts: &'a [DescriptorSetLayout],
+  topology: PrimitiveTopology,
+  blend: BlendMode,
+  cull_mode: CullMode,
+  depth: DepthMode,
+}
+
+/// a pipeline built for each target format, a pass uses the one matching its target
+pub(super) struct GegWgpuPipeline {
+  screen: wgpu::RenderPipeline,
+  hdr: wgpu::RenderPipeline,
+}
+
+impl GegWgpuPipeline {
+  pub fn get(&self, format: TargetFormat) -> &wgpu::RenderPipeline {
+    match format {
+      TargetFormat::Screen => &self.screen,
+      TargetFormat::Hdr => &self.hdr,
+    }
+  }
+}
+
+/// creates a pipeline from user provided shaders
+pub(super) fn create_pipeline(
+  device: &wgpu::Device,
+  formats: AttachmentFormats,
+  desc: &PipelineDesc,
+) -> Result<GegWgpuPipeline, GraphicsError> {
+  let vs = load_shader(device, &desc.vertex_shader, ShaderStage::Vertex)?;
+  let fs = load_shader(device, &desc.fragment_shader, ShaderStage::Fragment)?;
+
+  build_pipeline(
+    device,
+    formats,
+    (&vs, &desc.vertex_shader.entry_point),
+    (&fs, &desc.fragment_shader.entry_point),
+    PipelineState {
+      vertex_layouts: &desc.vertex_layouts,
+      descriptor_set_layouts: &desc.descriptor_set_layouts,
+      topology: desc.topology,
+      blend: desc.blend,
+      cull_mode: desc.cull_mode,
+      depth: desc.depth,
+    },
+  )
+}
+
+/// creates a pipeline with the engine's own shaders
+pub(super) fn create_builtin_pipeline(
+  device: &wgpu::Device,
+  formats: AttachmentFormats,
+  pipeline: BuiltinPipeline,
+) -> GegWgpuPipeline {
+  let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    label: Some("geg builtin"),
+    source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(BUILTIN_SHADER)),
+  });
+
+  match pipeline {
+    BuiltinPipeline::VertexColor => {
+      build_pipeline(
+        device,
+        formats,
+        (&shader, "vs_main"),
+        (&shader, "fs_main"),
+        PipelineState {
+          vertex_layouts: &[ColorVertex::layout()],
+          descriptor_set_layouts: &[],
+          topology: PrimitiveTopology::TriangleList,
+          blend: BlendMode::Opaque,
+          cull_mode: CullMode::None,
+          depth: DepthMode::Disabled,
+        },
+      )
+      .expect("failed to create builtin pipeline")
+    }
+
+    BuiltinPipeline::Mesh => build_pipeline(
+      device,
+      formats,
+      (&shader, "vs_mesh"),
+      (&shader, "fs_main"),
+      PipelineState {
+        vertex_layouts: &[ColorVertex::layout(), MeshInstance::layout()],
+        descriptor_set_layouts: &[CameraUniform::layout()],
+        topology: PrimitiveTopology::TriangleList,
+        blend: BlendMode::Opaque,
+        cull_mode: CullMode::None,
+        depth: DepthMode::ReadWrite,
+      },
+    )
+    .expect("failed to create builtin pipeline"),
+
+    BuiltinPipeline::Sprite => build_pipeline(
+      device,
+      formats,
+      (&shader, "vs_sprite"),
+      (&shader, "fs_sprite"),
+      PipelineState {
+        vertex_layouts: &[SpriteVertex::layout()],
+        descriptor_set_layouts: &[CameraUniform::layout(), DescriptorSetLayout::texture()],
+        topology: PrimitiveTopology::TriangleList,
+        blend: BlendMode::Alpha,
+        cull_mode: CullMode::None,
+        depth: DepthMode::Disabled,
+      },
+    )
+    .expect("failed to create builtin pipeline"),
+
+    BuiltinPipeline::Lines | BuiltinPipeline::LinesOnTop => build_pipeline(
+      device,
+      formats,
+      (&shader, "vs_lines"),
+      (&shader, "fs_main"),
+      PipelineState {
+        vertex_layouts: &[ColorVertex::layout()],
+        descriptor_set_layouts: &[CameraUniform::layout()],
+        topology: PrimitiveTopology::LineList,
+        blend: BlendMode::Opaque,
+        cull_mode: CullMode::None,
+        depth: match pipeline {
+          BuiltinPipeline::Lines => DepthMode::ReadOnly,
+          _ => DepthMode::Disabled,
+        },
+      },
+    )
+    .expect("failed to create builtin pipeline"),
+  }
+}
+
+fn build_pipeline(
+  device: &wgpu::Device,
+  formats: AttachmentFormats,
+  vs: (&wgpu::ShaderModule, &str),
+  fs: (&wgpu::ShaderModule, &str),
+  state: PipelineState,
+) -> Result<GegWgpuPipeline, GraphicsError> {
+  Ok(GegWgpuPipeline {
+    screen: build_variant(device, formats.target(TargetFormat::Screen), vs, fs, state)?,
+    hdr: build_variant(device, formats.target(TargetFormat::Hdr), vs, fs, state)?,
+  })
+}
+
+fn build_variant(
+  device: &wgpu::Device,
+  formats: AttachmentFormats,
+  vs: (&wgpu::ShaderModule, &str),
+  fs: (&wgpu::ShaderModule, &str),
+  state: PipelineState,
+) -> Result<wgpu::RenderPipeline, GraphicsError> {
+  let attributes: Vec<Vec<wgpu::VertexAttribute>> = state
+    .vertex_layouts
+    .iter()
+    .map(|layout| {
+      layout
+        .attributes
+        .iter()
+        .map(|attribute| wgpu::VertexAttribute {
+          format: to_wgpu_vertex_format(attribute.format),
+          offset: attribute.offset as u64,
+          shader_location: attribute.location,
+        })
+        .collect()
+    })
+    .collect();
+
+  let buffers: Vec<wgpu::VertexBufferLayout> = state
+    .vertex_layouts
+    .iter()
+    .zip(&attributes)
+    .map(|(layout, attributes)| wgpu::VertexBufferLayout {
+      array_stride: layout.stride as u64,
+      step_mode: match layout.step_mode {
+        VertexStepMode::Vertex => wgpu::VertexStepMode::Vertex,
+        VertexStepMode::Instance => wgpu::VertexStepMode::Instance,
+      },
+      attributes,
+    })
+    .collect();
+
+  let topology = match state.topology {
+    PrimitiveTopology::TriangleList => wgpu::PrimitiveTopology::TriangleList,
+    PrimitiveTopology::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+    PrimitiveTopology::LineList => wgpu::PrimitiveTopology::LineList,
+    PrimitiveTopology::PointList => wgpu::PrimitiveTopology::PointList,
+  };
+
+  let blend = match state.blend {
+    BlendMode::Opaque => wgpu::BlendState::REPLACE,
+    BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+    BlendMode::Additive => wgpu::BlendState {
+      color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+      },
+      alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+      },
+    },
+  };
+
+  let cull_mode = match state.cull_mode {
+    CullMode::None => None,
+    CullMode::Front => Some(wgpu::Face::Front),
+    CullMode::Back => Some(wgpu::Face::Back),
+  };
+
+  // every pass has a depth attachment, so pipelines without depth still need the state
+  let (depth_write_enabled, depth_compare) = match state.depth {
+    DepthMode::Disabled => (false, wgpu::CompareFunction::Always),
+    DepthMode::ReadWrite => (true, wgpu::CompareFunction::Less),
+    DepthMode::ReadOnly => (false, wgpu::CompareFunction::Less),
+  };
+
+  let bind_group_layouts: Vec<wgpu::BindGroupLayout> = state
+    .descriptor_set_layouts
+    .iter()
+    .map(|layout| create_bind_group_layout(device, layout))
+    .collect();
+  let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = bind_group_layouts.iter().collect();
+
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: None,
+    bind_group_layouts: &bind_group_layouts,
+    push_constant_ranges: &[],
+  });
+
+  device.push_error_scope(wgpu::ErrorFilter::Validation);
+  let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: None,
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: vs.0,
+      entry_point: vs.1,
+      buffers: &buffers,
+    },
+    primitive: wgpu::PrimitiveState {
+      topology,
+      cull_mode,
+      ..Default::default()
+    },
+    depth_stencil: Some(wgpu::DepthStencilState {
+      format: formats.depth,
+      depth_write_enabled,
+      depth_compare,
+      stencil: wgpu::StencilState::default(),
+      bias: wgpu::DepthBiasState::default(),
+    }),
+    multisample: wgpu::MultisampleState {
+      count: formats.samples,
+      ..Default::default()
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: fs.0,
+      entry_point: fs.1,
+      targets: &[Some(wgpu::ColorTargetState {
+        format: formats.color,
+        blend: Some(blend),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    multiview: None,
+  });
+
+  match pollster::block_on(device.pop_error_scope()) {
+    Some(e) => Err(GraphicsError::InvalidPipeline(e.to_string())),
+    None => Ok(pipeline),
+  }
+}
+
+/// wgpu shares identical layouts, so bind groups work with every pipeline using the same layout
+pub(super) fn create_bind_group_layout(
+  device: &wgpu::Device,
+  layout: &DescriptorSetLayout,
+) -> wgpu::BindGroupLayout {
+  let entries: Vec<wgpu::BindGroupLayoutEntry> = layout
+    .bindings
+    .iter()
+    .map(|binding| wgpu::BindGroupLayoutEntry {
+      binding: binding.binding,
+      visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+      ty: match binding.ty {
+        DescriptorType::UniformBuffer => wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        DescriptorType::Texture => wgpu::BindingType::Texture {
+          sample_type: wgpu::TextureSampleType::Float { filterable: true },
+          view_dimension: wgpu::TextureViewDimension::D2,
+          multisampled: false,
+        },
+        DescriptorType::Sampler => {
+          wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        }
+      },
+      count: None,
+    })
+    .collect();
+
+  device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    label: None,
+    entries: &entries,
+  })
+}
+
+fn load_shader(
+  device: &wgpu::Device,
+  desc: &ShaderDesc,
+  stage: ShaderStage,
+) -> Result<wgpu::ShaderModule, GraphicsError> {
+  // wgpu panics on shaders it can't parse, so naga parses and validates them first
+  let (module, _) = shader::to_naga(desc, stage)?;
+
+  device.push_error_scope(wgpu::ErrorFilter::Validation);
+  let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    label: None,
+    source: wgpu::ShaderSource::Naga(Cow::Owned(module)),
+  });
+
+  match pollster::block_on(device.pop_error_scope()) {
+    Some(e) => Err(GraphicsError::InvalidShader(e.to_string())),
+    None => Ok(module),
+  }
+}
+
+fn to_wgpu_vertex_format(format: VertexFormat) -> wgpu::VertexFormat {
+  match format {
+    VertexFormat::Float32 => wgpu::VertexFormat::Float32,
+    VertexFormat::Float32x2 => wgpu::VertexFormat::Float32x2,
+    VertexFormat::Float32x3 => wgpu::VertexFormat::Float32x3,
+    VertexFormat::Float32x4 => wgpu::VertexFormat::Float32x4,
+    VertexFormat::Uint32 => wgpu::VertexFormat::Uint32,
+    VertexFormat::Unorm8x4 => wgpu::VertexFormat::Unorm8x4,
+  }
+}
@@ -0,0 +1,674 @@
+use super::{
+  capture::GegWgpuCapture,
+  device::GegWgpuDevice,
+  pipeline::{create_bind_group_layout, create_builtin_pipeline, create_pipeline, GegWgpuPipeline},
+  resources::{
+    to_wgpu_address_mode, to_wgpu_filter_mode, to_wgpu_texture_format, AttachmentFormats,
+    GegWgpuAttachments, GegWgpuBuffer, GegWgpuRenderTarget, GegWgpuTexture, HDR_FORMAT,
+  },
+  target::GegWgpuTarget,
+};
+use crate::backend::{GegBackend, GraphicsOptions};
+use crate::traits::{
+  check_texture_write, BufferDesc, BufferHandle, BufferUsage, BuiltinPipeline, CommandList,
+  DescriptorResource, DescriptorSetDesc, DescriptorSetHandle, GraphicsDevice, GraphicsError,
+  PassTarget, PipelineDesc, PipelineHandle, RenderCommand, RenderTargetDesc,
+  RenderTargetHandle, SamplerDesc, SamplerHandle, TargetFormat, TextureDesc, TextureHandle,
+};
+
+use image::RgbaImage;
+use spdlog::prelude::*;
+use std::collections::HashMap;
+use std::num::{NonZeroU32, NonZeroU8};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+use winit::window::Window;
+
+pub(crate) struct GegWgpuRenderer {
+  // dropped before the device
+  geg_target: GegWgpuTarget,
+  geg_device: GegWgpuDevice,
+  formats: AttachmentFormats,
+  // recreated with the target
+  screen_attachments: GegWgpuAttachments,
+  // the extent the target should be recreated with
+  dimensions: [u32; 2],
+  recreate_target: bool,
+  next_handle: u64,
+  buffers: HashMap<u64, GegWgpuBuffer>,
+  textures: HashMap<u64, GegWgpuTexture>,
+  samplers: HashMap<u64, wgpu::Sampler>,
+  pipelines: HashMap<u64, GegWgpuPipeline>,
+  builtin_pipelines: HashMap<BuiltinPipeline, PipelineHandle>,
+  render_targets: HashMap<u64, GegWgpuRenderTarget>,
+  descriptor_sets: HashMap<u64, wgpu::BindGroup>,
+}
+
+impl GegWgpuRenderer {
+  /// `dimensions` is only used for the offscreen target when running headless
+  pub fn new(win: Option<Arc<Window>>, dimensions: [u32; 2], options: GraphicsOptions) -> Self {
+    let mut geg_device = GegWgpuDevice::new(win.as_deref());
+    let geg_target = GegWgpuTarget::new(&mut geg_device, win, dimensions);
+    let dimensions = geg_target.dimensions();
+
+    let color = geg_target.format();
+    let depth = if options.stencil {
+      wgpu::TextureFormat::Depth24PlusStencil8
+    } else {
+      wgpu::TextureFormat::Depth32Float
+    };
+    let formats = AttachmentFormats {
+      color,
+      depth,
+      samples: supported_samples(geg_device.adapter(), color, depth, options.msaa_samples),
+    };
+    info!("Using {}x msaa and a {:?} depth attachment", formats.samples, depth);
+    let screen_attachments = GegWgpuAttachments::new(geg_device.device(), formats, dimensions);
+
+    debug!("Renderer created");
+    Self {
+      geg_target,
+      geg_device,
+      formats,
+      screen_attachments,
+      dimensions,
+      recreate_target: false,
+      next_handle: 0,
+      buffers: HashMap::new(),
+      textures: HashMap::new(),
+      samplers: HashMap::new(),
+      pipelines: HashMap::new(),
+      builtin_pipelines: HashMap::new(),
+      render_targets: HashMap::new(),
+      descriptor_sets: HashMap::new(),
+    }
+  }
+
+  fn stencil(&self) -> bool {
+    self.formats.depth == wgpu::TextureFormat::Depth24PlusStencil8
+  }
+
+  fn next_handle(&mut self) -> u64 {
+    self.next_handle += 1;
+    self.next_handle
+  }
+
+  /// draws and presents a frame, with `capture` the screen is also copied into a buffer
+  /// that is returned to be read back, `None` if the frame was skipped
+  fn render(
+    &mut self,
+    commands: &CommandList,
+    capture: bool,
+  ) -> Option<Result<GegWgpuCapture, GraphicsError>> {
+    if self.recreate_target {
+      if !self
+        .geg_target
+        .recreate(self.geg_device.device(), self.dimensions)
+      {
+        return None;
+      }
+      self.screen_attachments = GegWgpuAttachments::new(
+        self.geg_device.device(),
+        self.formats,
+        self.geg_target.dimensions(),
+      );
+      self.recreate_target = false;
+    }
+
+    let frame = self.geg_target.acquire(self.geg_device.device())?;
+
+    let mut encoder =
+      self
+        .geg_device
+        .device()
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+          label: Some("geg frame"),
+        });
+
+    self.record(&mut encoder, commands, &frame.view);
+
+    // the acquired image has to be rendered into before it's presented
+    if !commands.has_screen_pass() {
+      let mut clear = CommandList::new();
+      clear.push(RenderCommand::BeginPass {
+        target: PassTarget::Screen,
+        clear_color: [0.0, 0.0, 0.0, 1.0],
+      });
+      clear.push(RenderCommand::EndPass);
+      self.record(&mut encoder, &clear, &frame.view);
+    }
+
+    // copied before the image is presented, it can't be read once it's back in the swapchain
+    let capture = capture.then(|| match self.geg_target.texture(&frame) {
+      Some(texture) => GegWgpuCapture::record(
+        self.geg_device.device(),
+        &mut encoder,
+        texture,
+        self.geg_target.format(),
+        self.geg_target.dimensions(),
+      ),
+      None => Err(GraphicsError::Readback(
+        "the surface can't be copied from".to_string(),
+      )),
+    });
+
+    self.geg_device.queue().submit(Some(encoder.finish()));
+
+    if let Some(surface_texture) = frame.surface_texture {
+      surface_texture.present();
+    }
+    if frame.suboptimal {
+      self.recreate_target = true;
+    }
+
+    capture
+  }
+
+  /// translates the recorded commands into wgpu passes
+  fn record(&self, encoder: &mut wgpu::CommandEncoder, commands: &CommandList, screen: &wgpu::TextureView) {
+    let mut commands = commands.commands().iter().peekable();
+
+    while let Some(command) = commands.next() {
+      let (target, clear_color) = match command {
+        RenderCommand::BeginPass {
+          target,
+          clear_color,
+        } => (target, clear_color),
+        _ => {
+          warn!("Skipping {:?} recorded outside of a pass", command);
+          continue;
+        }
+      };
+
+      let (view, attachments, format) = match target {
+        PassTarget::Screen => (screen, &self.screen_attachments, TargetFormat::Screen),
+        PassTarget::Offscreen(handle) => match self.render_targets.get(&handle.0) {
+          Some(target) => (&target.view, &target.attachments, target.format),
+          None => {
+            warn!("Skipping a pass into an unknown render target {:?}", handle);
+            while commands
+              .next_if(|command| !matches!(command, RenderCommand::BeginPass { .. }))
+              .is_some()
+            {}
+            continue;
+          }
+        },
+      };
+
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        // with msaa the multisampled texture is drawn into and resolved into the target
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: attachments.msaa.as_ref().unwrap_or(view),
+          resolve_target: attachments.msaa.as_ref().map(|_| view),
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color {
+              r: clear_color[0] as f64,
+              g: clear_color[1] as f64,
+              b: clear_color[2] as f64,
+              a: clear_color[3] as f64,
+            }),
+            store: attachments.msaa.is_none(),
+          },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: &attachments.depth,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: false,
+          }),
+          stencil_ops: self.stencil().then_some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(0),
+            store: false,
+          }),
+        }),
+      });
+
+      let mut ended = false;
+      while let Some(command) =
+        commands.next_if(|command| !matches!(command, RenderCommand::BeginPass { .. }))
+      {
+        match command {
+          RenderCommand::BeginPass { .. } => unreachable!(),
+
+          RenderCommand::EndPass => {
+            ended = true;
+            break;
+          }
+
+          RenderCommand::SetPipeline(handle) => match self.pipelines.get(&handle.0) {
+            Some(pipeline) => pass.set_pipeline(pipeline.get(format)),
+            None => warn!("Binding an unknown pipeline {:?}", handle),
+          },
+
+          RenderCommand::SetVertexBuffer { slot, buffer } => match self.buffers.get(&buffer.0) {
+            Some(geg_buffer) if geg_buffer.usage() == BufferUsage::Vertex => {
+              pass.set_vertex_buffer(*slot, geg_buffer.slice())
+            }
+            Some(_) => warn!("Binding a non vertex buffer as a vertex buffer {:?}", buffer),
+            None => warn!("Binding an unknown vertex buffer {:?}", buffer),
+          },
+
+          RenderCommand::SetIndexBuffer(buffer) => match self.buffers.get(&buffer.0) {
+            Some(geg_buffer) => match geg_buffer.index_format() {
+              Some(format) => pass.set_index_buffer(geg_buffer.slice(), format),
+              None => warn!("Binding a non index buffer as an index buffer {:?}", buffer),
+            },
+            None => warn!("Binding an unknown index buffer {:?}", buffer),
+          },
+
+          RenderCommand::SetDescriptorSet { index, set } => {
+            match self.descriptor_sets.get(&set.0) {
+              Some(bind_group) => pass.set_bind_group(*index, bind_group, &[]),
+              None => warn!("Binding an unknown descriptor set {:?}", set),
+            }
+          }
+
+          RenderCommand::Draw {
+            vertices,
+            instances,
+          } => pass.draw(vertices.clone(), instances.clone()),
+
+          RenderCommand::DrawIndexed {
+            indices,
+            base_vertex,
+            instances,
+          } => pass.draw_indexed(indices.clone(), *base_vertex, instances.clone()),
+        }
+      }
+
+      if !ended {
+        warn!("A pass wasn't ended");
+      }
+    }
+  }
+}
+
+impl GraphicsDevice for GegWgpuRenderer {
+  fn backend(&self) -> GegBackend {
+    GegBackend::Wgpu
+  }
+
+  fn surface_size(&self) -> [u32; 2] {
+    self.geg_target.dimensions()
+  }
+
+  fn msaa_samples(&self) -> u32 {
+    self.formats.samples
+  }
+
+  /// marks the target as out of date, it will be recreated before the next frame
+  fn resize(&mut self, width: u32, height: u32) {
+    self.dimensions = [width, height];
+    self.recreate_target = true;
+  }
+
+  fn create_buffer(
+    &mut self,
+    desc: &BufferDesc,
+    data: Option<&[u8]>,
+  ) -> Result<BufferHandle, GraphicsError> {
+    let contents = match data {
+      Some(data) if data.len() as u64 != desc.size => {
+        return Err(GraphicsError::InvalidSize {
+          expected: desc.size,
+          actual: data.len() as u64,
+        });
+      }
+      Some(data) => data.to_vec(),
+      None => vec![0; desc.size as usize],
+    };
+
+    let buffer = GegWgpuBuffer::new(self.geg_device.device(), desc.usage, contents)?;
+    let handle = self.next_handle();
+    self.buffers.insert(handle, buffer);
+    Ok(BufferHandle(handle))
+  }
+
+  fn write_buffer(
+    &mut self,
+    buffer: BufferHandle,
+    offset: u64,
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    self
+      .buffers
+      .get_mut(&buffer.0)
+      .ok_or(GraphicsError::InvalidHandle)?
+      .write(self.geg_device.queue(), offset, data)
+  }
+
+  fn destroy_buffer(&mut self, buffer: BufferHandle) {
+    // wgpu keeps the buffer alive until the submitted frames are done with it
+    self.buffers.remove(&buffer.0);
+  }
+
+  fn create_texture(
+    &mut self,
+    desc: &TextureDesc,
+    data: Option<&[u8]>,
+  ) -> Result<TextureHandle, GraphicsError> {
+    let size = desc.width as u64 * desc.height as u64 * desc.format.pixel_size() as u64;
+    let contents = match data {
+      Some(data) if data.len() as u64 != size => {
+        return Err(GraphicsError::InvalidSize {
+          expected: size,
+          actual: data.len() as u64,
+        });
+      }
+      Some(data) => data.to_vec(),
+      None => vec![0; size as usize],
+    };
+
+    let texture = self.geg_device.device().create_texture_with_data(
+      self.geg_device.queue(),
+      &wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+          width: desc.width,
+          height: desc.height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: to_wgpu_texture_format(desc.format),
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      },
+      &contents,
+    );
+
+    let handle = self.next_handle();
+    self.textures.insert(
+      handle,
+      GegWgpuTexture {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        texture,
+        dimensions: [desc.width, desc.height],
+        format: Some(desc.format),
+      },
+    );
+    Ok(TextureHandle(handle))
+  }
+
+  fn write_texture(
+    &mut self,
+    texture: TextureHandle,
+    origin: [u32; 2],
+    size: [u32; 2],
+    data: &[u8],
+  ) -> Result<(), GraphicsError> {
+    let geg_texture = self
+      .textures
+      .get(&texture.0)
+      .ok_or(GraphicsError::InvalidHandle)?;
+    let format = geg_texture.format.ok_or_else(|| {
+      GraphicsError::InvalidUsage(format!("{:?} belongs to a render target", texture))
+    })?;
+    check_texture_write(geg_texture.dimensions, format, origin, size, data)?;
+
+    // the queue copies the pixels before the next submitted frame reads them
+    self.geg_device.queue().write_texture(
+      wgpu::ImageCopyTexture {
+        texture: &geg_texture.texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d {
+          x: origin[0],
+          y: origin[1],
+          z: 0,
+        },
+        aspect: wgpu::TextureAspect::All,
+      },
+      data,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: NonZeroU32::new(size[0] * format.pixel_size()),
+        rows_per_image: NonZeroU32::new(size[1]),
+      },
+      wgpu::Extent3d {
+        width: size[0],
+        height: size[1],
+        depth_or_array_layers: 1,
+      },
+    );
+    Ok(())
+  }
+
+  fn texture_size(&self, texture: TextureHandle) -> Option<[u32; 2]> {
+    self.textures.get(&texture.0).map(|texture| texture.dimensions)
+  }
+
+  fn destroy_texture(&mut self, texture: TextureHandle) {
+    self.textures.remove(&texture.0);
+  }
+
+  fn create_sampler(&mut self, desc: &SamplerDesc) -> Result<SamplerHandle, GraphicsError> {
+    let sampler = self
+      .geg_device
+      .device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: None,
+        address_mode_u: to_wgpu_address_mode(desc.address_mode[0]),
+        address_mode_v: to_wgpu_address_mode(desc.address_mode[1]),
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: to_wgpu_filter_mode(desc.mag_filter),
+        min_filter: to_wgpu_filter_mode(desc.min_filter),
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        anisotropy_clamp: NonZeroU8::new(desc.anisotropy()).filter(|samples| samples.get() > 1),
+        ..Default::default()
+      });
+
+    let handle = self.next_handle();
+    self.samplers.insert(handle, sampler);
+    Ok(SamplerHandle(handle))
+  }
+
+  fn destroy_sampler(&mut self, sampler: SamplerHandle) {
+    self.samplers.remove(&sampler.0);
+  }
+
+  fn create_pipeline(&mut self, desc: &PipelineDesc) -> Result<PipelineHandle, GraphicsError> {
+    let pipeline = create_pipeline(self.geg_device.device(), self.formats, desc)?;
+    let handle = self.next_handle();
+    self.pipelines.insert(handle, pipeline);
+    Ok(PipelineHandle(handle))
+  }
+
+  fn builtin_pipeline(&mut self, pipeline: BuiltinPipeline) -> PipelineHandle {
+    if let Some(handle) = self.builtin_pipelines.get(&pipeline) {
+      return *handle;
+    }
+
+    let wgpu_pipeline = create_builtin_pipeline(self.geg_device.device(), self.formats, pipeline);
+    let handle = PipelineHandle(self.next_handle());
+    self.pipelines.insert(handle.0, wgpu_pipeline);
+    self.builtin_pipelines.insert(pipeline, handle);
+    handle
+  }
+
+  fn recreate_pipeline(
+    &mut self,
+    pipeline: PipelineHandle,
+    desc: &PipelineDesc,
+  ) -> Result<(), GraphicsError> {
+    if !self.pipelines.contains_key(&pipeline.0)
+      || self.builtin_pipelines.values().any(|handle| *handle == pipeline)
+    {
+      return Err(GraphicsError::InvalidHandle);
+    }
+
+    let wgpu_pipeline = create_pipeline(self.geg_device.device(), self.formats, desc)?;
+    self.pipelines.insert(pipeline.0, wgpu_pipeline);
+    Ok(())
+  }
+
+  fn destroy_pipeline(&mut self, pipeline: PipelineHandle) {
+    // builtin pipelines are shared and live as long as the device
+    if self.builtin_pipelines.values().any(|handle| *handle == pipeline) {
+      return;
+    }
+    self.pipelines.remove(&pipeline.0);
+  }
+
+  fn create_render_target(
+    &mut self,
+    desc: &RenderTargetDesc,
+  ) -> Result<RenderTargetHandle, GraphicsError> {
+    if desc.width == 0 || desc.height == 0 {
+      return Err(GraphicsError::InvalidSize {
+        expected: 1,
+        actual: 0,
+      });
+    }
+
+    let formats = self.formats.target(desc.format);
+    let texture = self
+      .geg_device
+      .device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label: Some("geg render target"),
+        size: wgpu::Extent3d {
+          width: desc.width,
+          height: desc.height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: formats.color,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+      });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let texture_handle = TextureHandle(self.next_handle());
+    self.textures.insert(
+      texture_handle.0,
+      GegWgpuTexture {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        texture,
+        dimensions: [desc.width, desc.height],
+        format: None,
+      },
+    );
+
+    let handle = self.next_handle();
+    self.render_targets.insert(
+      handle,
+      GegWgpuRenderTarget {
+        view,
+        attachments: GegWgpuAttachments::new(
+          self.geg_device.device(),
+          formats,
+          [desc.width, desc.height],
+        ),
+        texture: texture_handle,
+        format: desc.format,
+      },
+    );
+    Ok(RenderTargetHandle(handle))
+  }
+
+  fn render_target_texture(&self, target: RenderTargetHandle) -> Option<TextureHandle> {
+    self.render_targets.get(&target.0).map(|target| target.texture)
+  }
+
+  fn destroy_render_target(&mut self, target: RenderTargetHandle) {
+    if let Some(target) = self.render_targets.remove(&target.0) {
+      self.textures.remove(&target.texture.0);
+    }
+  }
+
+  fn create_descriptor_set(
+    &mut self,
+    desc: &DescriptorSetDesc,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    let mut entries = Vec::new();
+    for entry in &desc.entries {
+      let resource = match entry.resource {
+        DescriptorResource::UniformBuffer(buffer) => {
+          let geg_buffer = self
+            .buffers
+            .get(&buffer.0)
+            .ok_or(GraphicsError::InvalidHandle)?;
+          if geg_buffer.usage() != BufferUsage::Uniform {
+            return Err(GraphicsError::InvalidUsage(format!(
+              "{:?} isn't a uniform buffer",
+              buffer
+            )));
+          }
+          geg_buffer.buffer().as_entire_binding()
+        }
+        DescriptorResource::Texture(texture) => wgpu::BindingResource::TextureView(
+          &self
+            .textures
+            .get(&texture.0)
+            .ok_or(GraphicsError::InvalidHandle)?
+            .view,
+        ),
+        DescriptorResource::Sampler(sampler) => wgpu::BindingResource::Sampler(
+          self
+            .samplers
+            .get(&sampler.0)
+            .ok_or(GraphicsError::InvalidHandle)?,
+        ),
+      };
+
+      entries.push(wgpu::BindGroupEntry {
+        binding: entry.binding,
+        resource,
+      });
+    }
+
+    let device = self.geg_device.device();
+    let layout = create_bind_group_layout(device, &desc.layout());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: None,
+      layout: &layout,
+      entries: &entries,
+    });
+
+    let handle = self.next_handle();
+    self.descriptor_sets.insert(handle, bind_group);
+    Ok(DescriptorSetHandle(handle))
+  }
+
+  fn destroy_descriptor_set(&mut self, set: DescriptorSetHandle) {
+    self.descriptor_sets.remove(&set.0);
+  }
+
+  fn submit(&mut self, commands: &CommandList) {
+    self.render(commands, false);
+  }
+
+  fn submit_and_capture(&mut self, commands: &CommandList) -> Result<RgbaImage, GraphicsError> {
+    match self.render(commands, true) {
+      Some(capture) => capture?.read(self.geg_device.device()),
+      None => Err(GraphicsError::Readback("the frame was skipped".to_string())),
+    }
+  }
+}
+
+/// 4x msaa when it's requested and every attachment format supports it, wgpu has no other counts
+fn supported_samples(
+  adapter: &wgpu::Adapter,
+  color: wgpu::TextureFormat,
+  depth: wgpu::TextureFormat,
+  requested: u32,
+) -> u32 {
+  let resolves = |format| {
+    adapter.get_texture_format_features(format).flags.contains(
+      wgpu::TextureFormatFeatureFlags::MULTISAMPLE
+        | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE,
+    )
+  };
+  let depth_flags = adapter.get_texture_format_features(depth).flags;
+  let supported = resolves(color)
+    && resolves(HDR_FORMAT)
+    && depth_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE);
+
+  let samples = if requested >= 4 && supported { 4 } else { 1 };
+  if samples != requested.max(1) {
+    warn!("{}x msaa isn't supported, using {}x", requested, samples);
+  }
+  samples
+}
@@ -0,0 +1,199 @@
+use crate::traits::{
+  AddressMode, BufferUsage, FilterMode, GraphicsError, IndexFormat, TargetFormat, TextureFormat,
+  TextureHandle,
+};
+
+use wgpu::util::DeviceExt;
+
+/// a gpu buffer and a copy of its content
+/// wgpu only copies whole words, the copy is used to pad unaligned writes
+pub(super) struct GegWgpuBuffer {
+  buffer: wgpu::Buffer,
+  contents: Vec<u8>,
+  size: u64,
+  usage: BufferUsage,
+  index_format: Option<wgpu::IndexFormat>,
+}
+
+impl GegWgpuBuffer {
+  pub fn new(
+    device: &wgpu::Device,
+    usage: BufferUsage,
+    mut contents: Vec<u8>,
+  ) -> Result<Self, GraphicsError> {
+    if contents.is_empty() {
+      return Err(GraphicsError::InvalidSize {
+        expected: 1,
+        actual: 0,
+      });
+    }
+
+    let size = contents.len() as u64;
+    contents.resize(align(contents.len()), 0);
+
+    let (wgpu_usage, index_format) = match usage {
+      BufferUsage::Vertex => (wgpu::BufferUsages::VERTEX, None),
+      BufferUsage::Index(IndexFormat::U16) => {
+        (wgpu::BufferUsages::INDEX, Some(wgpu::IndexFormat::Uint16))
+      }
+      BufferUsage::Index(IndexFormat::U32) => {
+        (wgpu::BufferUsages::INDEX, Some(wgpu::IndexFormat::Uint32))
+      }
+      BufferUsage::Uniform => (wgpu::BufferUsages::UNIFORM, None),
+    };
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: None,
+      contents: &contents,
+      usage: wgpu_usage | wgpu::BufferUsages::COPY_DST,
+    });
+
+    Ok(Self {
+      buffer,
+      contents,
+      size,
+      usage,
+      index_format,
+    })
+  }
+
+  pub fn write(&mut self, queue: &wgpu::Queue, offset: u64, data: &[u8]) -> Result<(), GraphicsError> {
+    let start = offset as usize;
+    let end = start + data.len();
+    if end as u64 > self.size {
+      return Err(GraphicsError::InvalidSize {
+        expected: self.size,
+        actual: end as u64,
+      });
+    }
+
+    self.contents[start..end].copy_from_slice(data);
+
+    let start = start & !(wgpu::COPY_BUFFER_ALIGNMENT as usize - 1);
+    let end = align(end);
+    queue.write_buffer(&self.buffer, start as u64, &self.contents[start..end]);
+    Ok(())
+  }
+
+  pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+    self.buffer.slice(..self.size)
+  }
+
+  pub fn buffer(&self) -> &wgpu::Buffer {
+    &self.buffer
+  }
+
+  pub fn usage(&self) -> BufferUsage {
+    self.usage
+  }
+
+  /// `None` for vertex and uniform buffers
+  pub fn index_format(&self) -> Option<wgpu::IndexFormat> {
+    self.index_format
+  }
+}
+
+/// a sampled texture, render targets register their color texture as one too
+pub(super) struct GegWgpuTexture {
+  pub texture: wgpu::Texture,
+  pub view: wgpu::TextureView,
+  pub dimensions: [u32; 2],
+  /// `None` for the textures of render targets, they can't be written
+  pub format: Option<TextureFormat>,
+}
+
+/// an offscreen color texture that passes can render into
+pub(super) struct GegWgpuRenderTarget {
+  pub view: wgpu::TextureView,
+  pub attachments: GegWgpuAttachments,
+  pub texture: TextureHandle,
+  pub format: TargetFormat,
+}
+
+/// the color format of `TargetFormat::Hdr` targets
+pub(super) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// the formats of the attachments every pass has, pipelines are built to match them
+#[derive(Debug, Clone, Copy)]
+pub(super) struct AttachmentFormats {
+  /// the screen format
+  pub color: wgpu::TextureFormat,
+  pub depth: wgpu::TextureFormat,
+  pub samples: u32,
+}
+
+impl AttachmentFormats {
+  /// the formats of a pass drawing into a target of `format`
+  pub fn target(self, format: TargetFormat) -> Self {
+    match format {
+      TargetFormat::Screen => self,
+      TargetFormat::Hdr => Self {
+        color: HDR_FORMAT,
+        ..self
+      },
+    }
+  }
+}
+
+/// the depth texture of a pass and the multisampled texture resolved into its target
+pub(super) struct GegWgpuAttachments {
+  pub depth: wgpu::TextureView,
+  /// `None` without msaa
+  pub msaa: Option<wgpu::TextureView>,
+}
+
+impl GegWgpuAttachments {
+  pub fn new(device: &wgpu::Device, formats: AttachmentFormats, dimensions: [u32; 2]) -> Self {
+    let create = |format, label| {
+      device
+        .create_texture(&wgpu::TextureDescriptor {
+          label: Some(label),
+          size: wgpu::Extent3d {
+            width: dimensions[0],
+            height: dimensions[1],
+            depth_or_array_layers: 1,
+          },
+          mip_level_count: 1,
+          sample_count: formats.samples,
+          dimension: wgpu::TextureDimension::D2,
+          format,
+          usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+
+    Self {
+      depth: create(formats.depth, "geg depth"),
+      msaa: (formats.samples > 1).then(|| create(formats.color, "geg msaa")),
+    }
+  }
+}
+
+pub(super) fn to_wgpu_texture_format(format: TextureFormat) -> wgpu::TextureFormat {
+  match format {
+    TextureFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+    TextureFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+    TextureFormat::R8Unorm => wgpu::TextureFormat::R8Unorm,
+    TextureFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+  }
+}
+
+pub(super) fn to_wgpu_address_mode(mode: AddressMode) -> wgpu::AddressMode {
+  match mode {
+    AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+    AddressMode::Repeat => wgpu::AddressMode::Repeat,
+    AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+  }
+}
+
+pub(super) fn to_wgpu_filter_mode(mode: FilterMode) -> wgpu::FilterMode {
+  match mode {
+    FilterMode::Nearest => wgpu::FilterMode::Nearest,
+    FilterMode::Linear => wgpu::FilterMode::Linear,
+  }
+}
+
+fn align(size: usize) -> usize {
+  let alignment = wgpu::COPY_BUFFER_ALIGNMENT as usize;
+  (size + alignment - 1) & !(alignment - 1)
+}
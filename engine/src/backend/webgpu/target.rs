@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use spdlog::prelude::*;
+use winit::window::Window;
+
+use super::device::GegWgpuDevice;
+
+/// what the renderer draws into, the window surface or a single offscreen texture
+/// when running headless
+pub(super) enum GegWgpuTarget {
+  Surface {
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    // dropped after the surface
+    _win: Arc<Window>,
+  },
+  Offscreen {
+    texture: wgpu::Texture,
+    format: wgpu::TextureFormat,
+    dimensions: [u32; 2],
+  },
+}
+
+/// the image a frame is drawn into
+pub(super) struct GegWgpuFrame {
+  pub view: wgpu::TextureView,
+  /// presented once the frame is submitted
+  pub surface_texture: Option<wgpu::SurfaceTexture>,
+  /// the image doesn't match the surface anymore, it's reconfigured after the present
+  /// since it can't be while the image is alive
+  pub suboptimal: bool,
+}
+
+impl GegWgpuTarget {
+  pub fn new(geg_device: &mut GegWgpuDevice, win: Option<Arc<Window>>, dimensions: [u32; 2]) -> Self {
+    match (geg_device.take_surface(), win) {
+      (Some(surface), Some(win)) => {
+        let formats = surface.get_supported_formats(geg_device.adapter());
+        let format = formats
+          .iter()
+          .copied()
+          .find(|format| format.describe().srgb)
+          .unwrap_or(formats[0]);
+
+        // copied from for screenshots, gl surfaces can only be rendered into
+        let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        if geg_device.adapter().get_info().backend != wgpu::Backend::Gl {
+          usage |= wgpu::TextureUsages::COPY_SRC;
+        }
+
+        let size = win.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+          usage,
+          format,
+          width: size.width.max(1),
+          height: size.height.max(1),
+          present_mode: wgpu::PresentMode::Fifo,
+          alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        };
+        surface.configure(geg_device.device(), &config);
+
+        GegWgpuTarget::Surface {
+          surface,
+          config,
+          _win: win,
+        }
+      }
+
+      _ => {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        GegWgpuTarget::Offscreen {
+          texture: Self::create_offscreen(geg_device.device(), format, dimensions),
+          format,
+          dimensions,
+        }
+      }
+    }
+  }
+
+  /// recreates the target images with a new extent
+  /// returns `false` if the target can't be recreated right now
+  pub fn recreate(&mut self, device: &wgpu::Device, dimensions: [u32; 2]) -> bool {
+    // minimized, nothing to present to
+    if dimensions[0] == 0 || dimensions[1] == 0 {
+      return false;
+    }
+
+    match self {
+      GegWgpuTarget::Surface {
+        surface, config, ..
+      } => {
+        config.width = dimensions[0];
+        config.height = dimensions[1];
+        surface.configure(device, config);
+      }
+      GegWgpuTarget::Offscreen {
+        texture,
+        format,
+        dimensions: current,
+      } => {
+        *texture = Self::create_offscreen(device, *format, dimensions);
+        *current = dimensions;
+      }
+    }
+    true
+  }
+
+  /// the image to draw the next frame into, `None` if the frame has to be skipped
+  pub fn acquire(&mut self, device: &wgpu::Device) -> Option<GegWgpuFrame> {
+    match self {
+      GegWgpuTarget::Surface {
+        surface, config, ..
+      } => match surface.get_current_texture() {
+        Ok(surface_texture) => Some(GegWgpuFrame {
+          view: surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default()),
+          suboptimal: surface_texture.suboptimal,
+          surface_texture: Some(surface_texture),
+        }),
+        Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+          surface.configure(device, config);
+          None
+        }
+        Err(wgpu::SurfaceError::Timeout) => None,
+        Err(wgpu::SurfaceError::OutOfMemory) => {
+          error!("Out of memory acquiring the next image, skipping the frame");
+          None
+        }
+      },
+      GegWgpuTarget::Offscreen { texture, .. } => Some(GegWgpuFrame {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        surface_texture: None,
+        suboptimal: false,
+      }),
+    }
+  }
+
+  /// the texture `frame` is drawn into, `None` if it can't be copied from
+  pub fn texture<'a>(&'a self, frame: &'a GegWgpuFrame) -> Option<&'a wgpu::Texture> {
+    match self {
+      GegWgpuTarget::Surface { config, .. } => frame
+        .surface_texture
+        .as_ref()
+        .filter(|_| config.usage.contains(wgpu::TextureUsages::COPY_SRC))
+        .map(|surface_texture| &surface_texture.texture),
+      GegWgpuTarget::Offscreen { texture, .. } => Some(texture),
+    }
+  }
+
+  pub fn format(&self) -> wgpu::TextureFormat {
+    match self {
+      GegWgpuTarget::Surface { config, .. } => config.format,
+      GegWgpuTarget::Offscreen { format, .. } => *format,
+    }
+  }
+
+  pub fn dimensions(&self) -> [u32; 2] {
+    match self {
+      GegWgpuTarget::Surface { config, .. } => [config.width, config.height],
+      GegWgpuTarget::Offscreen { dimensions, .. } => *dimensions,
+    }
+  }
+
+  fn create_offscreen(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    dimensions: [u32; 2],
+  ) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("geg offscreen target"),
+      size: wgpu::Extent3d {
+        width: dimensions[0],
+        height: dimensions[1],
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    })
+  }
+}
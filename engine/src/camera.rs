@@ -0,0 +1,182 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+use crate::traits::DescriptorSetLayout;
+
+/// How a camera maps the view space to clip space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+  /// `fov_y` is the vertical field of view in radians.
+  Perspective { fov_y: f32, near: f32, far: f32 },
+  /// `height` is the visible height in world units, the width follows the aspect ratio.
+  Orthographic { height: f32, near: f32, far: f32 },
+}
+
+/// A right handed camera with y up, looking down -z in view space.
+///
+/// The camera of the `GraphicsContext` follows the window's aspect ratio,
+/// other cameras are kept in sync with `Camera::resize`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+  pub projection: Projection,
+  /// Width over height.
+  pub aspect_ratio: f32,
+  position: Vec3,
+  view: Mat4,
+}
+
+impl Camera {
+  pub fn perspective(fov_y: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+    Self::new(Projection::Perspective { fov_y, near, far }, aspect_ratio)
+  }
+
+  pub fn orthographic(height: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+    Self::new(Projection::Orthographic { height, near, far }, aspect_ratio)
+  }
+
+  /// A camera at the origin looking down -z.
+  pub fn new(projection: Projection, aspect_ratio: f32) -> Self {
+    Self {
+      projection,
+      aspect_ratio,
+      position: Vec3::ZERO,
+      view: Mat4::IDENTITY,
+    }
+  }
+
+  /// Moves the camera to `eye` and points it at `target`.
+  pub fn look_at(&mut self, eye: Vec3, target: Vec3, up: Vec3) {
+    self.position = eye;
+    self.view = Mat4::look_at_rh(eye, target, up);
+  }
+
+  /// Replaces the world to view transform.
+  pub fn set_view(&mut self, view: Mat4) {
+    self.position = view.inverse().w_axis.truncate();
+    self.view = view;
+  }
+
+  /// Updates the aspect ratio to match a target of `width` by `height` pixels.
+  pub fn resize(&mut self, width: u32, height: u32) {
+    // minimized windows have no size, the last ratio is kept for when they come back
+    if width > 0 && height > 0 {
+      self.aspect_ratio = width as f32 / height as f32;
+    }
+  }
+
+  pub fn position(&self) -> Vec3 {
+    self.position
+  }
+
+  pub fn view(&self) -> Mat4 {
+    self.view
+  }
+
+  /// The view to clip space transform, depth goes from 0 at `near` to 1 at `far`.
+  pub fn projection_matrix(&self) -> Mat4 {
+    match self.projection {
+      Projection::Perspective { fov_y, near, far } => {
+        Mat4::perspective_rh(fov_y, self.aspect_ratio, near, far)
+      }
+      Projection::Orthographic { height, near, far } => {
+        let half_height = height / 2.0;
+        let half_width = half_height * self.aspect_ratio;
+        Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, near, far)
+      }
+    }
+  }
+
+  pub fn view_projection(&self) -> Mat4 {
+    self.projection_matrix() * self.view
+  }
+
+  pub fn uniform(&self) -> CameraUniform {
+    let projection = self.projection_matrix();
+    CameraUniform {
+      view_projection: (projection * self.view).to_cols_array_2d(),
+      view: self.view.to_cols_array_2d(),
+      projection: projection.to_cols_array_2d(),
+      position: self.position.extend(1.0).to_array(),
+    }
+  }
+}
+
+impl Default for Camera {
+  /// A 2D camera showing -1 to 1 vertically, like clip space with the aspect ratio applied.
+  fn default() -> Self {
+    Self::orthographic(2.0, 1.0, -1.0, 1.0)
+  }
+}
+
+/// The camera as read by shaders, a uniform buffer at binding 0 of its set.
+///
+/// `GraphicsContext::draw_mesh` binds the context's camera at set 0.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Zeroable, Pod)]
+pub struct CameraUniform {
+  pub view_projection: [[f32; 4]; 4],
+  pub view: [[f32; 4]; 4],
+  pub projection: [[f32; 4]; 4],
+  /// The camera position in world space, `w` is always 1.
+  pub position: [f32; 4],
+}
+
+impl CameraUniform {
+  pub fn layout() -> DescriptorSetLayout {
+    DescriptorSetLayout::uniform_buffers(1)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use glam::Vec4;
+
+  fn assert_close(a: Vec3, b: Vec3) {
+    assert!(a.abs_diff_eq(b, 1e-5), "{} != {}", a, b);
+  }
+
+  #[test]
+  fn look_at_points_down_minus_z() {
+    let mut camera = Camera::default();
+    camera.look_at(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+    assert_eq!(camera.position(), Vec3::new(0.0, 0.0, 5.0));
+    let view = camera.view();
+    assert_close(view.transform_point3(Vec3::ZERO), Vec3::new(0.0, 0.0, -5.0));
+    assert_close(view.transform_point3(Vec3::new(1.0, 2.0, 5.0)), Vec3::new(1.0, 2.0, 0.0));
+
+    // set_view keeps the position in sync
+    let mut other = Camera::default();
+    other.set_view(view);
+    assert_close(other.position(), camera.position());
+  }
+
+  #[test]
+  fn perspective_depth_goes_from_near_to_far() {
+    let camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 2.0, 0.1, 100.0);
+    let project = |point: Vec3| camera.projection_matrix().project_point3(point);
+
+    assert!((project(Vec3::new(0.0, 0.0, -0.1)).z - 0.0).abs() < 1e-5);
+    assert!((project(Vec3::new(0.0, 0.0, -100.0)).z - 1.0).abs() < 1e-5);
+    // a 90 degree fov sees as far up as it sees forward, twice as far sideways
+    assert!((project(Vec3::new(0.0, 1.0, -1.0)).y - 1.0).abs() < 1e-5);
+    assert!((project(Vec3::new(2.0, 0.0, -1.0)).x - 1.0).abs() < 1e-5);
+  }
+
+  #[test]
+  fn orthographic_height_is_fitted_to_the_aspect_ratio() {
+    let mut camera = Camera::orthographic(100.0, 1.0, -1.0, 1.0);
+    camera.resize(200, 100);
+    assert_eq!(camera.aspect_ratio, 2.0);
+    // a minimized window keeps the ratio
+    camera.resize(0, 0);
+    assert_eq!(camera.aspect_ratio, 2.0);
+
+    let corner = camera.projection_matrix() * Vec4::new(100.0, 50.0, 0.0, 1.0);
+    assert_close(corner.truncate(), Vec3::new(1.0, 1.0, 0.5));
+
+    let uniform = camera.uniform();
+    assert_eq!(uniform.position, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(uniform.view_projection, camera.view_projection().to_cols_array_2d());
+  }
+}
@@ -0,0 +1,130 @@
+use crate::{
+  backend::GraphicsContext,
+  debug_draw::DebugDraw,
+  input::InputState,
+  input_map::InputMap,
+  layer::Layer,
+  layer_stack::LayerId,
+  time::Time,
+  window::GegWindow,
+};
+
+/// A change to the layer stack requested by a layer.
+/// They are applied after the current callback returns.
+pub(crate) enum LayerCommand {
+  PushLayer(LayerId, Box<dyn Layer>),
+  PushOverlay(LayerId, Box<dyn Layer>),
+  PopLayer,
+  PopOverlay,
+  Remove(LayerId),
+}
+
+/// The engine state, passed to every layer callback.
+pub struct GegContext {
+  pub(crate) window: GegWindow,
+  pub(crate) graphics: GraphicsContext,
+  pub(crate) input: InputState,
+  pub(crate) input_map: InputMap,
+  pub(crate) time: Time,
+  pub(crate) exit_requested: bool,
+  pub(crate) layer_commands: Vec<LayerCommand>,
+}
+
+impl GegContext {
+  pub(crate) fn new(window: GegWindow, graphics: GraphicsContext, time: Time) -> Self {
+    Self {
+      window,
+      graphics,
+      input: InputState::default(),
+      input_map: InputMap::default(),
+      time,
+      exit_requested: false,
+      layer_commands: Vec::new(),
+    }
+  }
+
+  pub fn window(&self) -> &GegWindow {
+    &self.window
+  }
+
+  pub fn window_mut(&mut self) -> &mut GegWindow {
+    &mut self.window
+  }
+
+  pub fn graphics(&mut self) -> &mut GraphicsContext {
+    &mut self.graphics
+  }
+
+  /// Lines and labels drawn over this frame, see `DebugDraw`.
+  pub fn debug_draw(&mut self) -> &mut DebugDraw {
+    self.graphics.debug_draw()
+  }
+
+  pub fn input(&self) -> &InputState {
+    &self.input
+  }
+
+  pub fn input_map(&self) -> &InputMap {
+    &self.input_map
+  }
+
+  /// Used to rebind or load the bindings.
+  pub fn input_map_mut(&mut self) -> &mut InputMap {
+    &mut self.input_map
+  }
+
+  pub fn is_action_down(&self, action: &str) -> bool {
+    self.input_map.is_action_down(&self.input, action)
+  }
+
+  pub fn was_action_pressed(&self, action: &str) -> bool {
+    self.input_map.was_action_pressed(&self.input, action)
+  }
+
+  pub fn was_action_released(&self, action: &str) -> bool {
+    self.input_map.was_action_released(&self.input, action)
+  }
+
+  pub fn axis(&self, axis: &str) -> f32 {
+    self.input_map.axis_value(&self.input, axis)
+  }
+
+  pub fn time(&self) -> &Time {
+    &self.time
+  }
+
+  /// Asks the application to quit at the end of the current frame.
+  pub fn request_exit(&mut self) {
+    self.exit_requested = true;
+  }
+
+  pub fn exit_requested(&self) -> bool {
+    self.exit_requested
+  }
+
+  /// Pushes a layer on top of the normal layers once the current callback returns.
+  pub fn push_layer(&mut self, layer: Box<dyn Layer>) -> LayerId {
+    let id = LayerId::next();
+    self.layer_commands.push(LayerCommand::PushLayer(id, layer));
+    id
+  }
+
+  /// Pushes an overlay on top of every layer once the current callback returns.
+  pub fn push_overlay(&mut self, layer: Box<dyn Layer>) -> LayerId {
+    let id = LayerId::next();
+    self.layer_commands.push(LayerCommand::PushOverlay(id, layer));
+    id
+  }
+
+  pub fn pop_layer(&mut self) {
+    self.layer_commands.push(LayerCommand::PopLayer);
+  }
+
+  pub fn pop_overlay(&mut self) {
+    self.layer_commands.push(LayerCommand::PopOverlay);
+  }
+
+  pub fn remove_layer(&mut self, id: LayerId) {
+    self.layer_commands.push(LayerCommand::Remove(id));
+  }
+}
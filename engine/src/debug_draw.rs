@@ -0,0 +1,469 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use glam::{BVec3, Mat4, Vec2, Vec3};
+use spdlog::prelude::*;
+
+use crate::backend::GraphicsContext;
+use crate::text::{Align, Font, FontId, Text, TextRenderer};
+use crate::traits::{
+  BufferDesc, BufferHandle, BufferUsage, BuiltinPipeline, ColorVertex, CommandEncoder,
+  GraphicsDevice, GraphicsError, PassTarget, RenderCommand,
+};
+
+/// Line segments per circle, spheres are drawn as three of them.
+const CIRCLE_SEGMENTS: usize = 32;
+/// The pixel size of `DebugDraw::text_3d` labels.
+const LABEL_SIZE: f32 = 16.0;
+
+/// Lines and labels drawn over the screen pass of the current frame, for debugging.
+///
+/// Every call queues a shape that is drawn for one frame, the returned `DebugShape` can keep
+/// it around for longer or draw it over the scene. They are seen through the camera of the
+/// `GraphicsContext` and added at the end of the last screen pass when the frame is submitted.
+///
+/// The depth of a pass drawing a post processed copy of the scene doesn't hold the scene, so
+/// shapes added there are never hidden. `GraphicsContext::render_debug_lines` records the lines
+/// into the scene pass instead, the labels are still added to the screen pass.
+#[derive(Default)]
+pub struct DebugDraw {
+  vertices: Vec<ColorVertex>,
+  shapes: Vec<Shape>,
+  labels: Vec<Label>,
+  // the vertices of this frame's shapes, depth tested ones first
+  frame_vertices: Vec<ColorVertex>,
+  vertex_buffer: Option<(BufferHandle, usize)>,
+  // the lines of this frame were already recorded into a pass
+  lines_recorded: bool,
+  // the text renderer is created with the font the first time a label is drawn
+  font: Option<Font>,
+  text: Option<(TextRenderer, FontId)>,
+  warned_font: bool,
+}
+
+/// A queued debug shape, drawn for one frame behind closer geometry unless changed.
+pub struct DebugShape<'a> {
+  lifetime: &'a mut Lifetime,
+}
+
+impl DebugShape<'_> {
+  /// Keeps drawing the shape every frame until the frames' delta times add up to `duration`.
+  ///
+  /// It's counted with `Time::delta`, so a replayed run keeps the shape for the same frames.
+  pub fn lasting(self, duration: Duration) -> Self {
+    self.lifetime.remaining = Some(duration.as_secs_f32());
+    self
+  }
+
+  /// Draws the shape over everything, even what is in front of it.
+  pub fn on_top(self) -> Self {
+    self.lifetime.on_top = true;
+    self
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Lifetime {
+  // the seconds left to draw the shape for, `None` for shapes drawn only once
+  remaining: Option<f32>,
+  on_top: bool,
+}
+
+struct Shape {
+  vertices: Range<usize>,
+  lifetime: Lifetime,
+}
+
+struct Label {
+  position: Vec3,
+  text: String,
+  color: Vec3,
+  lifetime: Lifetime,
+}
+
+impl DebugDraw {
+  /// The font of the `text_3d` labels, they aren't drawn until one is set.
+  pub fn set_font(&mut self, font: Font) {
+    self.font = Some(font);
+    self.text = None;
+  }
+
+  pub fn line(&mut self, a: Vec3, b: Vec3, color: Vec3) -> DebugShape<'_> {
+    self.push([([a, b], color)])
+  }
+
+  /// The edges of an axis aligned box.
+  pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Vec3) -> DebugShape<'_> {
+    // bit 0, 1 and 2 of the index pick the x, y and z of `max` over `min`
+    let corner = |i: usize| {
+      Vec3::select(BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0), max, min)
+    };
+    let edges = (0..8)
+      .flat_map(|i| [1, 2, 4].map(|axis| (i, axis)))
+      .filter(|(i, axis)| i & axis == 0)
+      .map(|(i, axis)| ([corner(i), corner(i | axis)], color));
+    self.push(edges)
+  }
+
+  pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec3) -> DebugShape<'_> {
+    let lines = circle(center, normal, radius).map(|line| (line, color));
+    self.push(lines)
+  }
+
+  /// A circle around each axis.
+  pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec3) -> DebugShape<'_> {
+    let lines = [Vec3::X, Vec3::Y, Vec3::Z]
+      .into_iter()
+      .flat_map(|normal| circle(center, normal, radius))
+      .map(|line| (line, color));
+    self.push(lines)
+  }
+
+  /// A line with a head at `to`, a fifth of its length.
+  pub fn arrow(&mut self, from: Vec3, to: Vec3, color: Vec3) -> DebugShape<'_> {
+    let direction = to - from;
+    let length = direction.length();
+    if length <= f32::EPSILON {
+      return self.line(from, to, color);
+    }
+
+    let head = length * 0.2;
+    let (u, v) = (direction / length).any_orthonormal_pair();
+    let base = to - direction / length * head;
+    let tips = [u, -u, v, -v].map(|side| ([to, base + side * head * 0.5], color));
+    self.push([([from, to], color)].into_iter().chain(tips))
+  }
+
+  /// A grid on the xz plane, `size` wide and split into `cells` cells along each side.
+  pub fn grid(&mut self, center: Vec3, size: f32, cells: u32, color: Vec3) -> DebugShape<'_> {
+    let cells = cells.max(1);
+    let half = size / 2.0;
+    let lines = (0..=cells).flat_map(|i| {
+      let offset = i as f32 / cells as f32 * size - half;
+      [
+        [Vec3::new(offset, 0.0, -half), Vec3::new(offset, 0.0, half)],
+        [Vec3::new(-half, 0.0, offset), Vec3::new(half, 0.0, offset)],
+      ]
+      .map(|[a, b]| ([center + a, center + b], color))
+    });
+    self.push(lines)
+  }
+
+  /// The x, y and z axes of a transform in red, green and blue, `size` long.
+  pub fn axes(&mut self, transform: Mat4, size: f32) -> DebugShape<'_> {
+    let origin = transform.transform_point3(Vec3::ZERO);
+    let axes = [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| {
+      let end = transform.transform_point3(axis * size);
+      ([origin, end], axis)
+    });
+    self.push(axes)
+  }
+
+  /// A label centered above a point, it stays the same size and is always drawn on top.
+  ///
+  /// Needs a font, see `DebugDraw::set_font`.
+  pub fn text_3d(&mut self, position: Vec3, text: &str, color: Vec3) -> DebugShape<'_> {
+    self.labels.push(Label {
+      position,
+      text: text.to_string(),
+      color,
+      lifetime: Lifetime::default(),
+    });
+    DebugShape {
+      lifetime: &mut self.labels.last_mut().unwrap().lifetime,
+    }
+  }
+
+  /// Removes every queued shape, including the lasting ones.
+  pub fn clear(&mut self) {
+    self.vertices.clear();
+    self.shapes.clear();
+    self.labels.clear();
+  }
+
+  fn push(&mut self, lines: impl IntoIterator<Item = ([Vec3; 2], Vec3)>) -> DebugShape<'_> {
+    let start = self.vertices.len();
+    self.vertices.extend(lines.into_iter().flat_map(|(line, color)| {
+      line.map(|position| ColorVertex {
+        position: position.to_array(),
+        color: color.to_array(),
+      })
+    }));
+    self.shapes.push(Shape {
+      vertices: start..self.vertices.len(),
+      lifetime: Lifetime::default(),
+    });
+    DebugShape {
+      lifetime: &mut self.shapes.last_mut().unwrap().lifetime,
+    }
+  }
+
+  /// records the shapes at the end of the last screen pass and drops the expired ones
+  pub(crate) fn render(&mut self, graphics: &mut GraphicsContext) {
+    let lines = !std::mem::take(&mut self.lines_recorded) && !self.shapes.is_empty();
+    if !lines && self.labels.is_empty() {
+      self.retain(|lifetime| lifetime.remaining.is_some());
+      return;
+    }
+
+    let tail = match graphics.encoder().reopen_screen_pass() {
+      Some(tail) => tail,
+      None => {
+        graphics
+          .encoder()
+          .begin_pass(PassTarget::Screen, [0.0, 0.0, 0.0, 1.0]);
+        vec![RenderCommand::EndPass]
+      }
+    };
+
+    if lines {
+      self.render_lines(graphics);
+    }
+    self.render_labels(graphics);
+    for command in tail {
+      graphics.encoder().push(command);
+    }
+
+    self.retain(|lifetime| lifetime.remaining.is_some());
+  }
+
+  /// records the lines into the pass being recorded, `render` skips them this frame
+  pub(crate) fn render_lines_in_pass(&mut self, graphics: &mut GraphicsContext) {
+    if !self.lines_recorded {
+      self.render_lines(graphics);
+      self.lines_recorded = true;
+    }
+  }
+
+  /// counts `dt` off the lasting shapes and drops the ones that ran out of time,
+  /// called with the delta time of every frame before its update
+  pub(crate) fn advance(&mut self, dt: f32) {
+    let mut alive = |lifetime: &mut Lifetime| match &mut lifetime.remaining {
+      Some(remaining) => {
+        *remaining -= dt;
+        *remaining > 0.0
+      }
+      None => true,
+    };
+    self.retain(&mut alive);
+  }
+
+  fn render_lines(&mut self, graphics: &mut GraphicsContext) {
+    self.frame_vertices.clear();
+    let mut ranges = [0..0, 0..0];
+    for (on_top, range) in [false, true].into_iter().zip(&mut ranges) {
+      let start = self.frame_vertices.len();
+      for shape in self.shapes.iter().filter(|shape| shape.lifetime.on_top == on_top) {
+        self
+          .frame_vertices
+          .extend_from_slice(&self.vertices[shape.vertices.clone()]);
+      }
+      *range = start as u32..self.frame_vertices.len() as u32;
+    }
+    if self.frame_vertices.is_empty() {
+      return;
+    }
+
+    let buffer = match self.upload(graphics.device()) {
+      Ok(buffer) => buffer,
+      Err(e) => {
+        error!("Failed to upload the debug lines: {}", e);
+        return;
+      }
+    };
+
+    let camera_set = graphics.camera_set();
+    for (pipeline, vertices) in [BuiltinPipeline::Lines, BuiltinPipeline::LinesOnTop]
+      .into_iter()
+      .zip(ranges)
+      .filter(|(_, vertices)| !vertices.is_empty())
+    {
+      let pipeline = graphics.device().builtin_pipeline(pipeline);
+      let encoder = graphics.encoder();
+      encoder.set_pipeline(pipeline);
+      encoder.set_vertex_buffer(0, buffer);
+      encoder.set_descriptor_set(0, camera_set);
+      encoder.draw(vertices, 0..1);
+    }
+  }
+
+  /// projects the labels on the screen and draws them as screen space text
+  fn render_labels(&mut self, graphics: &mut GraphicsContext) {
+    if self.labels.is_empty() {
+      return;
+    }
+
+    if self.text.is_none() {
+      match self.font.take() {
+        Some(font) => match TextRenderer::new(graphics) {
+          Ok(mut text) => {
+            let font = text.add_font(font);
+            self.text = Some((text, font));
+          }
+          Err(e) => error!("Failed to create the debug text renderer: {}", e),
+        },
+        None if !self.warned_font => {
+          warn!("Skipping the debug labels, no font was set");
+          self.warned_font = true;
+        }
+        None => (),
+      }
+    }
+    let (text, font) = match &mut self.text {
+      Some((text, font)) => (text, *font),
+      None => return,
+    };
+
+    let view_projection = graphics.camera().view_projection();
+    let [width, height] = graphics.device().surface_size();
+    let half_size = Vec2::new(width as f32, height as f32) / 2.0;
+    for label in &self.labels {
+      let clip = view_projection * label.position.extend(1.0);
+      // behind the camera or out of its depth range
+      if clip.w <= 0.0 || !(0.0..=1.0).contains(&(clip.z / clip.w)) {
+        continue;
+      }
+
+      let position = Vec2::new(clip.x, clip.y) / clip.w * half_size;
+      text.draw(
+        &Text::new(font, &label.text)
+          .with_position(position + Vec2::new(0.0, LABEL_SIZE * 1.5))
+          .with_size(LABEL_SIZE)
+          .with_align(Align::Center)
+          .with_color(label.color.extend(1.0)),
+      );
+    }
+    text.render(graphics);
+  }
+
+  /// writes the frame's vertices, the buffer is replaced if they don't fit
+  fn upload(&mut self, device: &mut dyn GraphicsDevice) -> Result<BufferHandle, GraphicsError> {
+    let buffer = match self.vertex_buffer {
+      Some((buffer, capacity)) if capacity >= self.frame_vertices.len() => buffer,
+      previous => {
+        // the frames that used it were submitted already
+        if let Some((buffer, _)) = previous {
+          device.destroy_buffer(buffer);
+        }
+        self.vertex_buffer = None;
+
+        let capacity = self.frame_vertices.len().next_power_of_two().max(1024);
+        let buffer = device.create_buffer(
+          &BufferDesc {
+            size: (capacity * std::mem::size_of::<ColorVertex>()) as u64,
+            usage: BufferUsage::Vertex,
+          },
+          None,
+        )?;
+        self.vertex_buffer = Some((buffer, capacity));
+        buffer
+      }
+    };
+
+    device.write_buffer(buffer, 0, bytemuck::cast_slice(&self.frame_vertices))?;
+    Ok(buffer)
+  }
+
+  /// keeps the shapes and labels for which `keep` is true
+  fn retain(&mut self, mut keep: impl FnMut(&mut Lifetime) -> bool) {
+    let mut kept = 0;
+    let vertices = &mut self.vertices;
+    self.shapes.retain_mut(|shape| {
+      if !keep(&mut shape.lifetime) {
+        return false;
+      }
+      let len = shape.vertices.len();
+      vertices.copy_within(shape.vertices.clone(), kept);
+      shape.vertices = kept..kept + len;
+      kept += len;
+      true
+    });
+    vertices.truncate(kept);
+
+    self.labels.retain_mut(|label| keep(&mut label.lifetime));
+  }
+}
+
+/// the segments of a circle facing `normal`
+fn circle(center: Vec3, normal: Vec3, radius: f32) -> impl Iterator<Item = [Vec3; 2]> {
+  let (u, v) = normal.normalize_or_zero().any_orthonormal_pair();
+  let point = move |i: usize| {
+    let (sin, cos) = (i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU).sin_cos();
+    center + (u * cos + v * sin) * radius
+  };
+  (0..CIRCLE_SEGMENTS).map(move |i| [point(i), point(i + 1)])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::{GegBackend, GraphicsOptions};
+  use crate::traits::PipelineHandle;
+
+  fn graphics() -> GraphicsContext {
+    GraphicsContext::new(GegBackend::Null, None, [64, 64], GraphicsOptions::default())
+  }
+
+  /// the pipelines and vertex counts of the debug draws in the last frame
+  fn draws(graphics: &mut GraphicsContext) -> Vec<(Option<PipelineHandle>, u32)> {
+    graphics
+      .log()
+      .unwrap()
+      .last_frame_draws()
+      .into_iter()
+      .map(|draw| (draw.pipeline, draw.elements.len() as u32))
+      .collect()
+  }
+
+  fn pipeline(graphics: &mut GraphicsContext, pipeline: BuiltinPipeline) -> Option<PipelineHandle> {
+    Some(graphics.device().builtin_pipeline(pipeline))
+  }
+
+  #[test]
+  fn lasting_shapes_are_drawn_until_their_time_runs_out() {
+    let mut graphics = graphics();
+    let lines = pipeline(&mut graphics, BuiltinPipeline::Lines);
+    let on_top = pipeline(&mut graphics, BuiltinPipeline::LinesOnTop);
+
+    let debug = graphics.debug_draw();
+    debug.line(Vec3::ZERO, Vec3::X, Vec3::ONE);
+    debug
+      .aabb(Vec3::ZERO, Vec3::ONE, Vec3::ONE)
+      .lasting(Duration::from_secs(1));
+    debug
+      .line(Vec3::ZERO, Vec3::Y, Vec3::ONE)
+      .lasting(Duration::from_millis(1500))
+      .on_top();
+    graphics.update();
+    assert_eq!(draws(&mut graphics), [(lines, 26), (on_top, 2)]);
+
+    graphics.debug_draw().advance(0.6);
+    graphics.update();
+    assert_eq!(draws(&mut graphics), [(lines, 24), (on_top, 2)]);
+
+    graphics.debug_draw().advance(0.6);
+    graphics.update();
+    assert_eq!(draws(&mut graphics), [(on_top, 2)]);
+
+    graphics.debug_draw().advance(0.6);
+    graphics.update();
+    assert_eq!(draws(&mut graphics), []);
+  }
+
+  #[test]
+  fn lines_queued_after_the_scene_pass_are_dropped() {
+    let mut graphics = graphics();
+    let lines = pipeline(&mut graphics, BuiltinPipeline::Lines);
+
+    graphics.encoder().begin_pass(PassTarget::Screen, [0.0; 4]);
+    graphics.debug_draw().line(Vec3::ZERO, Vec3::X, Vec3::ONE);
+    graphics.render_debug_lines();
+    graphics.debug_draw().aabb(Vec3::ZERO, Vec3::ONE, Vec3::ONE);
+    graphics.encoder().end_pass();
+    graphics.update();
+    assert_eq!(draws(&mut graphics), [(lines, 2)]);
+
+    graphics.update();
+    assert_eq!(draws(&mut graphics), []);
+  }
+}
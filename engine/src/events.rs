@@ -1,13 +1,44 @@
-use crate::io::{Key, MouseButton};
-use glam::{DVec2};
+use crate::gamepad::{GamepadAxis, GamepadButton, GamepadId};
+use crate::io::{ImeEvent, Key, MouseButton, ScrollDelta};
+use glam::{DVec2, IVec2, UVec2};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 /// A event that occured.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GegEvent {
   KeyDown(Key),
   KeyUp(Key),
+  /// A unicode character was typed, use this for text input instead of `KeyDown`.
+  CharReceived(char),
+  Ime(ImeEvent),
   MouseButtonDown(MouseButton),
   MouseButtonUp(MouseButton),
   MouseMoved(DVec2),
   MouseRaw(DVec2),
+  MouseWheel(ScrollDelta),
+  /// A gamepad was connected, with its name.
+  GamepadConnected(GamepadId, String),
+  GamepadDisconnected(GamepadId),
+  GamepadButtonDown(GamepadId, GamepadButton),
+  GamepadButtonUp(GamepadId, GamepadButton),
+  /// The raw value of a gamepad axis, without any deadzone.
+  GamepadAxis(GamepadId, GamepadAxis, f32),
+  CursorEntered,
+  CursorLeft,
+  FocusGained,
+  FocusLost,
+  /// The new size of the window in physical pixels.
+  Resized(UVec2),
+  /// The new position of the window in physical pixels.
+  Moved(IVec2),
+  /// The new scale factor of the window, the window is resized after it.
+  ScaleFactorChanged(f64),
+  FileHovered(PathBuf),
+  FileHoverCancelled,
+  FileDropped(PathBuf),
+  /// The user asked to close the window.
+  /// if a layer handles this event the application keeps running,
+  /// the layer can call `GegContext::request_exit` later to close it.
+  CloseRequested,
 }
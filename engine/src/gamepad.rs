@@ -0,0 +1,456 @@
+use crate::events::GegEvent;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// identifies a connected gamepad, ids can be reused after a gamepad disconnects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct GamepadId(pub usize);
+
+/// gamepad buttons, named after the SDL game controller mapping
+/// (`A` is the bottom face button whatever is printed on it)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+  A,
+  B,
+  X,
+  Y,
+  Back,
+  Guide,
+  Start,
+  LeftStick,
+  RightStick,
+  LeftShoulder,
+  RightShoulder,
+  DPadUp,
+  DPadDown,
+  DPadLeft,
+  DPadRight,
+  Misc1,
+  Paddle1,
+  Paddle2,
+  Paddle3,
+  Paddle4,
+  Touchpad,
+}
+
+/// gamepad axes, named after the SDL game controller mapping
+/// sticks are in `[-1, 1]` with positive y up, triggers are in `[0, 1]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+  LeftX,
+  LeftY,
+  RightX,
+  RightY,
+  LeftTrigger,
+  RightTrigger,
+}
+
+impl GamepadAxis {
+  const COUNT: usize = 6;
+
+  fn index(self) -> usize {
+    self as usize
+  }
+}
+
+/// what an input source reports
+#[derive(Debug, Clone, PartialEq)]
+pub enum GamepadEvent {
+  /// a gamepad was connected, with its name
+  Connected(GamepadId, String),
+  Disconnected(GamepadId),
+  ButtonDown(GamepadId, GamepadButton),
+  ButtonUp(GamepadId, GamepadButton),
+  /// the raw value of an axis, without any deadzone
+  AxisMoved(GamepadId, GamepadAxis, f32),
+}
+
+pub fn to_geg_event(event: GamepadEvent) -> GegEvent {
+  match event {
+    GamepadEvent::Connected(id, name) => GegEvent::GamepadConnected(id, name),
+    GamepadEvent::Disconnected(id) => GegEvent::GamepadDisconnected(id),
+    GamepadEvent::ButtonDown(id, button) => GegEvent::GamepadButtonDown(id, button),
+    GamepadEvent::ButtonUp(id, button) => GegEvent::GamepadButtonUp(id, button),
+    GamepadEvent::AxisMoved(id, axis, value) => GegEvent::GamepadAxis(id, axis, value),
+  }
+}
+
+/// where gamepad events come from, polled once per frame
+pub trait GamepadSource {
+  /// appends the events that happened since the last poll to `events`
+  fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+}
+
+/// which `GamepadSource` the application uses
+pub enum GamepadBackend {
+  Disabled,
+  /// the platform gamepads through gilrs
+  #[cfg(feature = "gilrs")]
+  Gilrs,
+  Custom(Box<dyn GamepadSource>),
+}
+
+impl Default for GamepadBackend {
+  #[cfg(feature = "gilrs")]
+  fn default() -> Self {
+    GamepadBackend::Gilrs
+  }
+
+  #[cfg(not(feature = "gilrs"))]
+  fn default() -> Self {
+    GamepadBackend::Disabled
+  }
+}
+
+impl GamepadBackend {
+  pub(crate) fn into_source(self) -> Option<Box<dyn GamepadSource>> {
+    match self {
+      GamepadBackend::Disabled => None,
+      #[cfg(feature = "gilrs")]
+      GamepadBackend::Gilrs => gilrs_source::GilrsSource::new()
+        .map(|source| Box::new(source) as Box<dyn GamepadSource>),
+      GamepadBackend::Custom(source) => Some(source),
+    }
+  }
+}
+
+/// a gamepad source that only reports the events pushed into it
+/// clones share the same queue, so a test can keep one and give the other to the app
+#[derive(Debug, Clone, Default)]
+pub struct MockGamepadSource {
+  events: Arc<Mutex<Vec<GamepadEvent>>>,
+}
+
+impl MockGamepadSource {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push(&self, event: GamepadEvent) {
+    self.events.lock().unwrap().push(event);
+  }
+
+  pub fn connect(&self, id: GamepadId, name: &str) {
+    self.push(GamepadEvent::Connected(id, name.to_string()));
+  }
+
+  pub fn disconnect(&self, id: GamepadId) {
+    self.push(GamepadEvent::Disconnected(id));
+  }
+
+  pub fn press(&self, id: GamepadId, button: GamepadButton) {
+    self.push(GamepadEvent::ButtonDown(id, button));
+  }
+
+  pub fn release(&self, id: GamepadId, button: GamepadButton) {
+    self.push(GamepadEvent::ButtonUp(id, button));
+  }
+
+  pub fn move_axis(&self, id: GamepadId, axis: GamepadAxis, value: f32) {
+    self.push(GamepadEvent::AxisMoved(id, axis, value));
+  }
+}
+
+impl GamepadSource for MockGamepadSource {
+  fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+    events.append(&mut self.events.lock().unwrap());
+  }
+}
+
+/// the state of a connected gamepad
+#[derive(Debug, Clone)]
+pub struct GamepadState {
+  name: String,
+  deadzone: f32,
+  buttons_down: HashSet<GamepadButton>,
+  buttons_pressed: HashSet<GamepadButton>,
+  buttons_released: HashSet<GamepadButton>,
+  axes: [f32; GamepadAxis::COUNT],
+}
+
+impl GamepadState {
+  pub(crate) fn new(name: String, deadzone: f32) -> Self {
+    Self {
+      name,
+      deadzone: deadzone.clamp(0.0, 0.99),
+      buttons_down: HashSet::new(),
+      buttons_pressed: HashSet::new(),
+      buttons_released: HashSet::new(),
+      axes: [0.0; GamepadAxis::COUNT],
+    }
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn is_button_down(&self, button: GamepadButton) -> bool {
+    self.buttons_down.contains(&button)
+  }
+
+  pub fn was_button_pressed_this_frame(&self, button: GamepadButton) -> bool {
+    self.buttons_pressed.contains(&button)
+  }
+
+  pub fn was_button_released_this_frame(&self, button: GamepadButton) -> bool {
+    self.buttons_released.contains(&button)
+  }
+
+  /// the axis value with the deadzone applied, sticks use a radial deadzone
+  pub fn axis(&self, axis: GamepadAxis) -> f32 {
+    match axis {
+      GamepadAxis::LeftX => self.left_stick().x,
+      GamepadAxis::LeftY => self.left_stick().y,
+      GamepadAxis::RightX => self.right_stick().x,
+      GamepadAxis::RightY => self.right_stick().y,
+      GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => {
+        apply_deadzone(self.raw_axis(axis), self.deadzone)
+      }
+    }
+  }
+
+  /// the axis value as reported by the gamepad
+  pub fn raw_axis(&self, axis: GamepadAxis) -> f32 {
+    self.axes[axis.index()]
+  }
+
+  pub fn left_stick(&self) -> Vec2 {
+    self.stick(GamepadAxis::LeftX, GamepadAxis::LeftY)
+  }
+
+  pub fn right_stick(&self) -> Vec2 {
+    self.stick(GamepadAxis::RightX, GamepadAxis::RightY)
+  }
+
+  pub fn deadzone(&self) -> f32 {
+    self.deadzone
+  }
+
+  pub fn set_deadzone(&mut self, deadzone: f32) {
+    self.deadzone = deadzone.clamp(0.0, 0.99);
+  }
+
+  fn stick(&self, x: GamepadAxis, y: GamepadAxis) -> Vec2 {
+    let raw = Vec2::new(self.raw_axis(x), self.raw_axis(y));
+    let len = raw.length();
+    if len <= self.deadzone {
+      return Vec2::ZERO;
+    }
+
+    // rescale so the output starts from 0 at the edge of the deadzone
+    let scaled = apply_deadzone(len.min(1.0), self.deadzone);
+    raw / len * scaled
+  }
+
+  pub(crate) fn process_event(&mut self, event: &GegEvent) {
+    match event {
+      GegEvent::GamepadButtonDown(_, button) => {
+        if self.buttons_down.insert(*button) {
+          self.buttons_pressed.insert(*button);
+        }
+      }
+
+      GegEvent::GamepadButtonUp(_, button) => {
+        if self.buttons_down.remove(button) {
+          self.buttons_released.insert(*button);
+        }
+      }
+
+      GegEvent::GamepadAxis(_, axis, value) => {
+        self.axes[axis.index()] = *value;
+      }
+
+      _ => (),
+    }
+  }
+
+  pub(crate) fn end_frame(&mut self) {
+    self.buttons_pressed.clear();
+    self.buttons_released.clear();
+  }
+}
+
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+  if value.abs() <= deadzone {
+    return 0.0;
+  }
+
+  value.signum() * (value.abs() - deadzone) / (1.0 - deadzone)
+}
+
+#[cfg(feature = "gilrs")]
+mod gilrs_source {
+  use super::{GamepadAxis, GamepadButton, GamepadEvent, GamepadId, GamepadSource};
+  use gilrs::{Axis, Button, EventType, Gilrs};
+  use spdlog::prelude::*;
+
+  /// the platform gamepads, gilrs maps them with the SDL mapping database
+  pub(super) struct GilrsSource {
+    gilrs: Gilrs,
+    initial_events: Vec<GamepadEvent>,
+  }
+
+  impl GilrsSource {
+    pub fn new() -> Option<Self> {
+      let gilrs = match Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(e) => {
+          warn!("Gamepads are disabled: {}", e);
+          return None;
+        }
+      };
+
+      // gilrs doesn't report the gamepads that were connected before it started
+      let initial_events = gilrs
+        .gamepads()
+        .map(|(id, gamepad)| {
+          GamepadEvent::Connected(GamepadId(id.into()), gamepad.name().to_string())
+        })
+        .collect();
+
+      debug!("Gilrs gamepad source created");
+      Some(Self {
+        gilrs,
+        initial_events,
+      })
+    }
+  }
+
+  impl GamepadSource for GilrsSource {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+      events.append(&mut self.initial_events);
+
+      while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+        let pad = GamepadId(id.into());
+        let event = match event {
+          EventType::Connected => Some(GamepadEvent::Connected(
+            pad,
+            self.gilrs.gamepad(id).name().to_string(),
+          )),
+          EventType::Disconnected => Some(GamepadEvent::Disconnected(pad)),
+          EventType::ButtonPressed(button, _) => {
+            to_geg_button(button).map(|button| GamepadEvent::ButtonDown(pad, button))
+          }
+          EventType::ButtonReleased(button, _) => {
+            to_geg_button(button).map(|button| GamepadEvent::ButtonUp(pad, button))
+          }
+          // analog triggers are reported as buttons with a value
+          EventType::ButtonChanged(Button::LeftTrigger2, value, _) => Some(
+            GamepadEvent::AxisMoved(pad, GamepadAxis::LeftTrigger, value),
+          ),
+          EventType::ButtonChanged(Button::RightTrigger2, value, _) => Some(
+            GamepadEvent::AxisMoved(pad, GamepadAxis::RightTrigger, value),
+          ),
+          EventType::AxisChanged(axis, value, _) => {
+            to_geg_axis(axis).map(|axis| GamepadEvent::AxisMoved(pad, axis, value))
+          }
+          _ => None,
+        };
+
+        if let Some(event) = event {
+          events.push(event);
+        }
+      }
+    }
+  }
+
+  fn to_geg_button(button: Button) -> Option<GamepadButton> {
+    match button {
+      Button::South => Some(GamepadButton::A),
+      Button::East => Some(GamepadButton::B),
+      Button::West => Some(GamepadButton::X),
+      Button::North => Some(GamepadButton::Y),
+      Button::Select => Some(GamepadButton::Back),
+      Button::Mode => Some(GamepadButton::Guide),
+      Button::Start => Some(GamepadButton::Start),
+      Button::LeftThumb => Some(GamepadButton::LeftStick),
+      Button::RightThumb => Some(GamepadButton::RightStick),
+      Button::LeftTrigger => Some(GamepadButton::LeftShoulder),
+      Button::RightTrigger => Some(GamepadButton::RightShoulder),
+      Button::DPadUp => Some(GamepadButton::DPadUp),
+      Button::DPadDown => Some(GamepadButton::DPadDown),
+      Button::DPadLeft => Some(GamepadButton::DPadLeft),
+      Button::DPadRight => Some(GamepadButton::DPadRight),
+      // triggers are reported as axes
+      _ => None,
+    }
+  }
+
+  fn to_geg_axis(axis: Axis) -> Option<GamepadAxis> {
+    match axis {
+      Axis::LeftStickX => Some(GamepadAxis::LeftX),
+      Axis::LeftStickY => Some(GamepadAxis::LeftY),
+      Axis::RightStickX => Some(GamepadAxis::RightX),
+      Axis::RightStickY => Some(GamepadAxis::RightY),
+      Axis::LeftZ => Some(GamepadAxis::LeftTrigger),
+      Axis::RightZ => Some(GamepadAxis::RightTrigger),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::input::InputState;
+
+  /// polls `source` into a fresh input state with a deadzone of `0.2`
+  fn poll(source: &MockGamepadSource) -> InputState {
+    let mut input = InputState::default();
+    input.set_gamepad_deadzone(0.2);
+    let mut events = Vec::new();
+    source.clone().poll(&mut events);
+    for event in events {
+      input.process_event(&to_geg_event(event));
+    }
+    input
+  }
+
+  #[test]
+  fn sticks_use_a_radial_deadzone() {
+    let id = GamepadId(0);
+    let source = MockGamepadSource::new();
+    source.connect(id, "mock");
+    source.move_axis(id, GamepadAxis::LeftX, 0.15);
+    source.move_axis(id, GamepadAxis::LeftY, 0.1);
+    source.move_axis(id, GamepadAxis::RightX, 0.15);
+    source.move_axis(id, GamepadAxis::RightY, 0.15);
+
+    let input = poll(&source);
+    let pad = input.gamepad(id).unwrap();
+    assert_eq!(pad.left_stick(), Vec2::ZERO);
+    assert_eq!(pad.raw_axis(GamepadAxis::LeftX), 0.15);
+    // both axes are under the deadzone but the stick isn't
+    assert!(pad.right_stick().x > 0.0);
+    assert_eq!(pad.right_stick().x, pad.right_stick().y);
+  }
+
+  #[test]
+  fn values_are_rescaled_past_the_deadzone() {
+    let id = GamepadId(0);
+    let source = MockGamepadSource::new();
+    source.connect(id, "mock");
+    source.move_axis(id, GamepadAxis::LeftX, -1.0);
+    source.move_axis(id, GamepadAxis::LeftTrigger, 0.6);
+    source.move_axis(id, GamepadAxis::RightTrigger, 0.2);
+
+    let input = poll(&source);
+    let pad = input.gamepad(id).unwrap();
+    assert_eq!(pad.axis(GamepadAxis::LeftX), -1.0);
+    assert!((pad.axis(GamepadAxis::LeftTrigger) - 0.5).abs() < 1e-6);
+    assert_eq!(pad.axis(GamepadAxis::RightTrigger), 0.0);
+  }
+
+  #[test]
+  fn the_queue_is_drained_by_a_poll() {
+    let source = MockGamepadSource::new();
+    source.connect(GamepadId(1), "mock");
+    source.press(GamepadId(1), GamepadButton::A);
+
+    let input = poll(&source);
+    assert!(input.is_gamepad_button_down(GamepadButton::A));
+    assert!(poll(&source).gamepad(GamepadId(1)).is_none());
+  }
+}
@@ -0,0 +1,276 @@
+use crate::events::GegEvent;
+use crate::gamepad::{GamepadButton, GamepadId, GamepadState};
+use crate::io::{Key, ModifiersState, MouseButton, ScrollDelta};
+use glam::{DVec2, Vec2};
+use std::collections::{BTreeMap, HashSet};
+
+/// The state of the input devices, kept up to date by the application.
+///
+/// The "this frame" queries cover the events received since the previous update,
+/// they are reset after every `Layer::on_update`.
+#[derive(Debug, Clone)]
+pub struct InputState {
+  pub(crate) modifiers: ModifiersState,
+  cursor_position: DVec2,
+  keys_down: HashSet<Key>,
+  keys_pressed: HashSet<Key>,
+  keys_released: HashSet<Key>,
+  buttons_down: HashSet<MouseButton>,
+  buttons_pressed: HashSet<MouseButton>,
+  buttons_released: HashSet<MouseButton>,
+  mouse_delta: DVec2,
+  scroll_delta: Vec2,
+  scroll_delta_pixels: DVec2,
+  gamepads: BTreeMap<GamepadId, GamepadState>,
+  gamepad_deadzone: f32,
+}
+
+impl Default for InputState {
+  fn default() -> Self {
+    Self {
+      modifiers: ModifiersState::default(),
+      cursor_position: DVec2::ZERO,
+      keys_down: HashSet::new(),
+      keys_pressed: HashSet::new(),
+      keys_released: HashSet::new(),
+      buttons_down: HashSet::new(),
+      buttons_pressed: HashSet::new(),
+      buttons_released: HashSet::new(),
+      mouse_delta: DVec2::ZERO,
+      scroll_delta: Vec2::ZERO,
+      scroll_delta_pixels: DVec2::ZERO,
+      gamepads: BTreeMap::new(),
+      gamepad_deadzone: 0.15,
+    }
+  }
+}
+
+impl InputState {
+  pub fn modifiers(&self) -> ModifiersState {
+    self.modifiers
+  }
+
+  pub fn is_key_down(&self, key: Key) -> bool {
+    self.keys_down.contains(&key)
+  }
+
+  /// `true` if the key went down this frame, key repeats don't count.
+  pub fn was_pressed_this_frame(&self, key: Key) -> bool {
+    self.keys_pressed.contains(&key)
+  }
+
+  pub fn was_released_this_frame(&self, key: Key) -> bool {
+    self.keys_released.contains(&key)
+  }
+
+  pub fn keys_down(&self) -> impl Iterator<Item = &Key> {
+    self.keys_down.iter()
+  }
+
+  pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+    self.buttons_down.contains(&button)
+  }
+
+  pub fn was_mouse_button_pressed_this_frame(&self, button: MouseButton) -> bool {
+    self.buttons_pressed.contains(&button)
+  }
+
+  pub fn was_mouse_button_released_this_frame(&self, button: MouseButton) -> bool {
+    self.buttons_released.contains(&button)
+  }
+
+  /// The cursor position in physical pixels relative to the top left of the window.
+  pub fn cursor_position(&self) -> DVec2 {
+    self.cursor_position
+  }
+
+  /// The raw mouse motion accumulated this frame, not affected by the cursor
+  /// being grabbed or reaching the window edges.
+  pub fn mouse_delta(&self) -> DVec2 {
+    self.mouse_delta
+  }
+
+  /// The scrolled lines accumulated this frame.
+  pub fn scroll_delta(&self) -> Vec2 {
+    self.scroll_delta
+  }
+
+  /// The scrolled pixels accumulated this frame, usually from a touchpad.
+  pub fn scroll_delta_pixels(&self) -> DVec2 {
+    self.scroll_delta_pixels
+  }
+
+  pub fn gamepad(&self, id: GamepadId) -> Option<&GamepadState> {
+    self.gamepads.get(&id)
+  }
+
+  pub fn gamepad_mut(&mut self, id: GamepadId) -> Option<&mut GamepadState> {
+    self.gamepads.get_mut(&id)
+  }
+
+  /// The connected gamepads, ordered by id.
+  pub fn gamepads(&self) -> impl Iterator<Item = (GamepadId, &GamepadState)> {
+    self.gamepads.iter().map(|(id, state)| (*id, state))
+  }
+
+  /// `true` if the button is held on any connected gamepad.
+  pub fn is_gamepad_button_down(&self, button: GamepadButton) -> bool {
+    self.gamepads.values().any(|pad| pad.is_button_down(button))
+  }
+
+  /// The deadzone given to gamepads when they connect.
+  pub fn set_gamepad_deadzone(&mut self, deadzone: f32) {
+    self.gamepad_deadzone = deadzone;
+    for pad in self.gamepads.values_mut() {
+      pad.set_deadzone(deadzone);
+    }
+  }
+
+  /// Updates the state from an event, before the layers see it.
+  pub(crate) fn process_event(&mut self, event: &GegEvent) {
+    match event {
+      GegEvent::KeyDown(key) => {
+        if self.keys_down.insert(*key) {
+          self.keys_pressed.insert(*key);
+        }
+      }
+
+      GegEvent::KeyUp(key) => {
+        if self.keys_down.remove(key) {
+          self.keys_released.insert(*key);
+        }
+      }
+
+      GegEvent::MouseButtonDown(button) => {
+        if self.buttons_down.insert(*button) {
+          self.buttons_pressed.insert(*button);
+        }
+      }
+
+      GegEvent::MouseButtonUp(button) => {
+        if self.buttons_down.remove(button) {
+          self.buttons_released.insert(*button);
+        }
+      }
+
+      GegEvent::MouseMoved(pos) => {
+        self.cursor_position = *pos;
+      }
+
+      GegEvent::MouseRaw(delta) => {
+        self.mouse_delta += *delta;
+      }
+
+      GegEvent::MouseWheel(ScrollDelta::Lines(delta)) => {
+        self.scroll_delta += *delta;
+      }
+
+      GegEvent::MouseWheel(ScrollDelta::Pixels(delta)) => {
+        self.scroll_delta_pixels += *delta;
+      }
+
+      GegEvent::GamepadConnected(id, name) => {
+        let pad = GamepadState::new(name.clone(), self.gamepad_deadzone);
+        self.gamepads.insert(*id, pad);
+      }
+
+      GegEvent::GamepadDisconnected(id) => {
+        self.gamepads.remove(id);
+      }
+
+      GegEvent::GamepadButtonDown(id, _)
+      | GegEvent::GamepadButtonUp(id, _)
+      | GegEvent::GamepadAxis(id, _, _) => {
+        if let Some(pad) = self.gamepads.get_mut(id) {
+          pad.process_event(event);
+        }
+      }
+
+      // the key and button up events are lost while the window is unfocused
+      GegEvent::FocusLost => {
+        self.keys_released.extend(self.keys_down.drain());
+        self.buttons_released.extend(self.buttons_down.drain());
+      }
+
+      _ => (),
+    }
+  }
+
+  /// Resets the per frame state, called after the layers are updated.
+  pub(crate) fn end_frame(&mut self) {
+    self.keys_pressed.clear();
+    self.keys_released.clear();
+    self.buttons_pressed.clear();
+    self.buttons_released.clear();
+    self.mouse_delta = DVec2::ZERO;
+    self.scroll_delta = Vec2::ZERO;
+    self.scroll_delta_pixels = DVec2::ZERO;
+    for pad in self.gamepads.values_mut() {
+      pad.end_frame();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pressed_and_released_last_one_frame() {
+    let mut input = InputState::default();
+    input.process_event(&GegEvent::KeyDown(Key::Space));
+    // a key repeat isn't another press
+    input.process_event(&GegEvent::KeyDown(Key::Space));
+    input.process_event(&GegEvent::MouseButtonDown(MouseButton::Left));
+    assert!(input.is_key_down(Key::Space));
+    assert!(input.was_pressed_this_frame(Key::Space));
+    assert!(input.was_mouse_button_pressed_this_frame(MouseButton::Left));
+
+    input.end_frame();
+    assert!(input.is_key_down(Key::Space));
+    assert!(!input.was_pressed_this_frame(Key::Space));
+    assert!(!input.was_mouse_button_pressed_this_frame(MouseButton::Left));
+
+    input.process_event(&GegEvent::KeyUp(Key::Space));
+    input.process_event(&GegEvent::KeyUp(Key::A));
+    assert!(!input.is_key_down(Key::Space));
+    assert!(input.was_released_this_frame(Key::Space));
+    // it was never down
+    assert!(!input.was_released_this_frame(Key::A));
+
+    input.end_frame();
+    assert!(!input.was_released_this_frame(Key::Space));
+  }
+
+  #[test]
+  fn losing_focus_releases_what_is_held() {
+    let mut input = InputState::default();
+    input.process_event(&GegEvent::KeyDown(Key::W));
+    input.process_event(&GegEvent::MouseButtonDown(MouseButton::Right));
+    input.end_frame();
+
+    input.process_event(&GegEvent::FocusLost);
+    assert!(input.keys_down().next().is_none());
+    assert!(input.was_released_this_frame(Key::W));
+    assert!(!input.is_mouse_button_down(MouseButton::Right));
+    assert!(input.was_mouse_button_released_this_frame(MouseButton::Right));
+  }
+
+  #[test]
+  fn deltas_add_up_over_a_frame() {
+    let mut input = InputState::default();
+    input.process_event(&GegEvent::MouseRaw(DVec2::new(1.0, 2.0)));
+    input.process_event(&GegEvent::MouseRaw(DVec2::new(3.0, -1.0)));
+    input.process_event(&GegEvent::MouseWheel(ScrollDelta::Lines(Vec2::new(0.0, 1.0))));
+    input.process_event(&GegEvent::MouseWheel(ScrollDelta::Lines(Vec2::new(0.0, 2.0))));
+    input.process_event(&GegEvent::MouseMoved(DVec2::new(10.0, 20.0)));
+    assert_eq!(input.mouse_delta(), DVec2::new(4.0, 1.0));
+    assert_eq!(input.scroll_delta(), Vec2::new(0.0, 3.0));
+
+    input.end_frame();
+    assert_eq!(input.mouse_delta(), DVec2::ZERO);
+    assert_eq!(input.scroll_delta(), Vec2::ZERO);
+    // the cursor stays where it is
+    assert_eq!(input.cursor_position(), DVec2::new(10.0, 20.0));
+  }
+}
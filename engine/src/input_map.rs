@@ -0,0 +1,408 @@
+use crate::gamepad::{GamepadAxis, GamepadButton};
+use crate::input::InputState;
+use crate::io::{Key, ModifiersState, MouseButton};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// A physical input that can be bound to an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputSource {
+  Key(Key),
+  MouseButton(MouseButton),
+  /// A button on any connected gamepad.
+  GamepadButton(GamepadButton),
+}
+
+impl InputSource {
+  fn is_down(&self, input: &InputState) -> bool {
+    match self {
+      InputSource::Key(key) => input.is_key_down(*key),
+      InputSource::MouseButton(button) => input.is_mouse_button_down(*button),
+      InputSource::GamepadButton(button) => input.is_gamepad_button_down(*button),
+    }
+  }
+
+  fn was_pressed(&self, input: &InputState) -> bool {
+    match self {
+      InputSource::Key(key) => input.was_pressed_this_frame(*key),
+      InputSource::MouseButton(button) => input.was_mouse_button_pressed_this_frame(*button),
+      InputSource::GamepadButton(button) => input
+        .gamepads()
+        .any(|(_, pad)| pad.was_button_pressed_this_frame(*button)),
+    }
+  }
+
+  fn was_released(&self, input: &InputState) -> bool {
+    match self {
+      InputSource::Key(key) => input.was_released_this_frame(*key),
+      InputSource::MouseButton(button) => input.was_mouse_button_released_this_frame(*button),
+      InputSource::GamepadButton(button) => input
+        .gamepads()
+        .any(|(_, pad)| pad.was_button_released_this_frame(*button)),
+    }
+  }
+}
+
+impl From<Key> for InputSource {
+  fn from(key: Key) -> Self {
+    InputSource::Key(key)
+  }
+}
+
+impl From<MouseButton> for InputSource {
+  fn from(button: MouseButton) -> Self {
+    InputSource::MouseButton(button)
+  }
+}
+
+impl From<GamepadButton> for InputSource {
+  fn from(button: GamepadButton) -> Self {
+    InputSource::GamepadButton(button)
+  }
+}
+
+/// An input bound to an action, it only triggers while exactly the `modifiers` are held,
+/// so `S` and `Ctrl+S` can be bound to different actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+  pub input: InputSource,
+  #[serde(default)]
+  pub modifiers: ModifiersState,
+  /// Triggers whatever modifiers are held, e.g. to keep moving while sprinting with shift.
+  #[serde(default)]
+  pub ignore_modifiers: bool,
+}
+
+impl Binding {
+  pub fn new(input: impl Into<InputSource>) -> Self {
+    Self::with_modifiers(input, ModifiersState::default())
+  }
+
+  pub fn with_modifiers(input: impl Into<InputSource>, modifiers: ModifiersState) -> Self {
+    Self {
+      input: input.into(),
+      modifiers,
+      ignore_modifiers: false,
+    }
+  }
+
+  /// A binding that triggers whatever modifiers are held.
+  pub fn ignoring_modifiers(input: impl Into<InputSource>) -> Self {
+    Self {
+      ignore_modifiers: true,
+      ..Self::new(input)
+    }
+  }
+
+  fn modifiers_held(&self, input: &InputState) -> bool {
+    self.ignore_modifiers || input.modifiers() == self.modifiers
+  }
+}
+
+/// A source of values for an axis.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+  /// `-1` while `negative` is held, `1` while `positive` is held.
+  Digital {
+    negative: InputSource,
+    positive: InputSource,
+  },
+  /// The horizontal raw mouse motion this frame, scaled by `sensitivity`.
+  MouseX { sensitivity: f32 },
+  /// The vertical raw mouse motion this frame, scaled by `sensitivity`.
+  MouseY { sensitivity: f32 },
+  /// The scrolled lines this frame, scaled by `sensitivity`.
+  Scroll { sensitivity: f32 },
+  /// A gamepad axis after its deadzone, scaled by `scale`.
+  /// the gamepad pushed the furthest wins when several are connected.
+  Gamepad { axis: GamepadAxis, scale: f32 },
+}
+
+impl AxisBinding {
+  fn value(&self, input: &InputState) -> f32 {
+    match self {
+      AxisBinding::Digital { negative, positive } => {
+        let mut value = 0.0;
+        if negative.is_down(input) {
+          value -= 1.0;
+        }
+        if positive.is_down(input) {
+          value += 1.0;
+        }
+        value
+      }
+      AxisBinding::MouseX { sensitivity } => input.mouse_delta().x as f32 * sensitivity,
+      AxisBinding::MouseY { sensitivity } => input.mouse_delta().y as f32 * sensitivity,
+      AxisBinding::Scroll { sensitivity } => input.scroll_delta().y * sensitivity,
+      AxisBinding::Gamepad { axis, scale } => {
+        let value = input
+          .gamepads()
+          .map(|(_, pad)| pad.axis(*axis))
+          .fold(0.0_f32, |a, b| if b.abs() > a.abs() { b } else { a });
+        value * scale
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum InputMapError {
+  Io(std::io::Error),
+  Parse(ron::error::SpannedError),
+  Serialize(ron::Error),
+}
+
+impl fmt::Display for InputMapError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      InputMapError::Io(e) => write!(f, "failed to access the bindings file: {}", e),
+      InputMapError::Parse(e) => write!(f, "failed to parse the bindings: {}", e),
+      InputMapError::Serialize(e) => write!(f, "failed to serialize the bindings: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for InputMapError {}
+
+/// Maps named actions (e.g. "jump") and axes (e.g. "move_x") to inputs.
+///
+/// Every action and axis can have multiple bindings, an action is down if any of its
+/// bindings is and an axis is the sum of its bindings.
+/// Bindings can be changed at runtime and saved to or loaded from a RON file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMap {
+  actions: BTreeMap<String, Vec<Binding>>,
+  axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, InputMapError> {
+    let src = std::fs::read_to_string(path).map_err(InputMapError::Io)?;
+    Self::from_ron(&src)
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), InputMapError> {
+    std::fs::write(path, self.to_ron()?).map_err(InputMapError::Io)
+  }
+
+  pub fn from_ron(src: &str) -> Result<Self, InputMapError> {
+    ron::from_str(src).map_err(InputMapError::Parse)
+  }
+
+  pub fn to_ron(&self) -> Result<String, InputMapError> {
+    ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+      .map_err(InputMapError::Serialize)
+  }
+
+  /// Adds a binding to an action, creating the action if needed.
+  pub fn bind_action(&mut self, action: &str, binding: Binding) {
+    let bindings = self.actions.entry(action.to_string()).or_default();
+    if !bindings.contains(&binding) {
+      bindings.push(binding);
+    }
+  }
+
+  /// Replaces every binding of an action, used to rebind it.
+  pub fn set_action_bindings(&mut self, action: &str, bindings: Vec<Binding>) {
+    self.actions.insert(action.to_string(), bindings);
+  }
+
+  pub fn unbind_action(&mut self, action: &str, binding: Binding) {
+    if let Some(bindings) = self.actions.get_mut(action) {
+      bindings.retain(|b| *b != binding);
+    }
+  }
+
+  pub fn remove_action(&mut self, action: &str) {
+    self.actions.remove(action);
+  }
+
+  pub fn action_bindings(&self, action: &str) -> &[Binding] {
+    self.actions.get(action).map_or(&[], |bindings| bindings)
+  }
+
+  pub fn actions(&self) -> impl Iterator<Item = &str> {
+    self.actions.keys().map(|action| action.as_str())
+  }
+
+  /// Adds a binding to an axis, creating the axis if needed.
+  pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+    let bindings = self.axes.entry(axis.to_string()).or_default();
+    if !bindings.contains(&binding) {
+      bindings.push(binding);
+    }
+  }
+
+  /// Replaces every binding of an axis, used to rebind it.
+  pub fn set_axis_bindings(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+    self.axes.insert(axis.to_string(), bindings);
+  }
+
+  pub fn remove_axis(&mut self, axis: &str) {
+    self.axes.remove(axis);
+  }
+
+  pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+    self.axes.get(axis).map_or(&[], |bindings| bindings)
+  }
+
+  pub fn axes(&self) -> impl Iterator<Item = &str> {
+    self.axes.keys().map(|axis| axis.as_str())
+  }
+
+  /// `true` while any binding of the action is held.
+  pub fn is_action_down(&self, input: &InputState, action: &str) -> bool {
+    self
+      .action_bindings(action)
+      .iter()
+      .any(|b| b.input.is_down(input) && b.modifiers_held(input))
+  }
+
+  /// `true` if a binding of the action went down this frame.
+  pub fn was_action_pressed(&self, input: &InputState, action: &str) -> bool {
+    self
+      .action_bindings(action)
+      .iter()
+      .any(|b| b.input.was_pressed(input) && b.modifiers_held(input))
+  }
+
+  /// `true` if a binding of the action went up this frame.
+  pub fn was_action_released(&self, input: &InputState, action: &str) -> bool {
+    self
+      .action_bindings(action)
+      .iter()
+      .any(|b| b.input.was_released(input))
+  }
+
+  /// The sum of every binding of the axis, digital and gamepad bindings together are
+  /// clamped to `[-1, 1]`, mouse bindings aren't.
+  pub fn axis_value(&self, input: &InputState, axis: &str) -> f32 {
+    let mut digital = 0.0_f32;
+    let mut analog = 0.0;
+    for binding in self.axis_bindings(axis) {
+      match binding {
+        AxisBinding::Digital { .. } | AxisBinding::Gamepad { .. } => {
+          digital += binding.value(input)
+        }
+        _ => analog += binding.value(input),
+      }
+    }
+
+    digital.clamp(-1.0, 1.0) + analog
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::events::GegEvent;
+
+  fn ctrl() -> ModifiersState {
+    ModifiersState {
+      ctrl: true,
+      ..ModifiersState::default()
+    }
+  }
+
+  #[test]
+  fn ron_round_trip() {
+    let mut map = InputMap::new();
+    map.bind_action("jump", Binding::new(Key::Space));
+    map.bind_action("jump", Binding::new(GamepadButton::A));
+    map.bind_action("save", Binding::with_modifiers(Key::S, ctrl()));
+    map.bind_action("fire", Binding::new(MouseButton::Left));
+    map.bind_axis(
+      "move_x",
+      AxisBinding::Digital {
+        negative: Key::A.into(),
+        positive: Key::D.into(),
+      },
+    );
+    map.bind_axis(
+      "move_x",
+      AxisBinding::Gamepad {
+        axis: GamepadAxis::LeftX,
+        scale: 1.0,
+      },
+    );
+    map.bind_axis("look_x", AxisBinding::MouseX { sensitivity: 0.1 });
+
+    let loaded = InputMap::from_ron(&map.to_ron().unwrap()).unwrap();
+    for action in map.actions() {
+      assert_eq!(loaded.action_bindings(action), map.action_bindings(action));
+    }
+    for axis in map.axes() {
+      assert_eq!(loaded.axis_bindings(axis), map.axis_bindings(axis));
+    }
+    assert_eq!(loaded.actions().count(), 3);
+    assert_eq!(loaded.axes().count(), 2);
+  }
+
+  #[test]
+  fn bindings_without_modifiers_default_to_none() {
+    let map = InputMap::from_ron("(actions: { \"jump\": [(input: Key(Space))] })").unwrap();
+    assert_eq!(map.action_bindings("jump"), &[Binding::new(Key::Space)]);
+  }
+
+  #[test]
+  fn modifiers_must_be_held() {
+    let mut map = InputMap::new();
+    map.bind_action("save", Binding::with_modifiers(Key::S, ctrl()));
+    map.bind_action("down", Binding::new(Key::S));
+
+    let mut input = InputState::default();
+    input.process_event(&GegEvent::KeyDown(Key::S));
+    assert!(!map.is_action_down(&input, "save"));
+    assert!(!map.was_action_pressed(&input, "save"));
+    assert!(map.is_action_down(&input, "down"));
+
+    // a binding only triggers with exactly its modifiers
+    input.modifiers = ctrl();
+    assert!(map.is_action_down(&input, "save"));
+    assert!(map.was_action_pressed(&input, "save"));
+    assert!(!map.is_action_down(&input, "down"));
+
+    input.modifiers = ModifiersState {
+      shift: true,
+      ..ctrl()
+    };
+    assert!(!map.is_action_down(&input, "save"));
+    map.bind_action("down", Binding::ignoring_modifiers(Key::S));
+    assert!(map.is_action_down(&input, "down"));
+
+    input.end_frame();
+    input.modifiers = ModifiersState::default();
+    input.process_event(&GegEvent::KeyUp(Key::S));
+    assert!(map.was_action_released(&input, "save"));
+  }
+
+  #[test]
+  fn digital_axes_are_clamped() {
+    let mut map = InputMap::new();
+    for (negative, positive) in [(Key::A, Key::D), (Key::Left, Key::Right)] {
+      map.bind_axis(
+        "move_x",
+        AxisBinding::Digital {
+          negative: negative.into(),
+          positive: positive.into(),
+        },
+      );
+    }
+
+    let mut input = InputState::default();
+    input.process_event(&GegEvent::KeyDown(Key::D));
+    input.process_event(&GegEvent::KeyDown(Key::Right));
+    assert_eq!(map.axis_value(&input, "move_x"), 1.0);
+    input.process_event(&GegEvent::KeyDown(Key::A));
+    assert_eq!(map.axis_value(&input, "move_x"), 1.0);
+    assert_eq!(map.axis_value(&input, "unbound"), 0.0);
+  }
+}
@@ -1,6 +1,9 @@
-use winit::event::{VirtualKeyCode};
+use glam::{DVec2, Vec2};
+use serde::{Deserialize, Serialize};
+use winit::event::{Ime, MouseScrollDelta, VirtualKeyCode};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ModifiersState {
   pub ctrl: bool,
   pub shift: bool,
@@ -19,8 +22,18 @@ impl Default for ModifiersState {
   }
 }
 
+impl ModifiersState {
+  /// `true` if every modifier held in `other` is also held in `self`
+  pub fn contains(&self, other: ModifiersState) -> bool {
+    (self.ctrl || !other.ctrl)
+      && (self.shift || !other.shift)
+      && (self.alt || !other.alt)
+      && (self.logo || !other.logo)
+  }
+}
+
 /// keyboard keys
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Key {
   Key1,
   Key2,
@@ -356,7 +369,7 @@ pub fn to_geg_keycode(key: VirtualKeyCode) -> Key {
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MouseButton {
   Left,
   Right,
@@ -372,3 +385,39 @@ pub fn to_geg_mousebtn(button: winit::event::MouseButton) -> MouseButton {
     winit::event::MouseButton::Other(n) => MouseButton::Other(n),
   }
 }
+
+/// how much the mouse wheel or the touchpad scrolled
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ScrollDelta {
+  /// scrolled lines or rows, positive y is scrolling up
+  Lines(Vec2),
+  /// scrolled pixels, usually from a touchpad, positive y is scrolling up
+  Pixels(DVec2),
+}
+
+pub fn to_geg_scroll_delta(delta: MouseScrollDelta) -> ScrollDelta {
+  match delta {
+    MouseScrollDelta::LineDelta(x, y) => ScrollDelta::Lines(Vec2::new(x, y)),
+    MouseScrollDelta::PixelDelta(pos) => ScrollDelta::Pixels(DVec2::new(pos.x, pos.y)),
+  }
+}
+
+/// input method editor events, used for composing text (e.g. CJK input)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ImeEvent {
+  Enabled,
+  /// the text being composed and the byte range of the cursor in it, if any
+  Preedit(String, Option<(usize, usize)>),
+  /// the composed text is done and should be inserted
+  Commit(String),
+  Disabled,
+}
+
+pub fn to_geg_ime(ime: Ime) -> ImeEvent {
+  match ime {
+    Ime::Enabled => ImeEvent::Enabled,
+    Ime::Preedit(text, cursor) => ImeEvent::Preedit(text, cursor),
+    Ime::Commit(text) => ImeEvent::Commit(text),
+    Ime::Disabled => ImeEvent::Disabled,
+  }
+}
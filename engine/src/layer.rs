@@ -1,3 +1,4 @@
+use crate::context::GegContext;
 use crate::io::ModifiersState;
 pub use crate::events::GegEvent;
 
@@ -5,25 +6,34 @@ pub use crate::events::GegEvent;
 pub trait Layer {
   /// Called when the layer is attached to the application.
   /// usful for initializing resources used by the layer.
-  fn on_attach(&mut self) -> () {}
+  fn on_attach(&mut self, ctx: &mut GegContext) -> () {}
 
   /// Called when the layer is detached from the application.
   /// usful for cleaning up resources used by the layer.
-  fn on_detach(&mut self) -> () {}
+  fn on_detach(&mut self, ctx: &mut GegContext) -> () {}
 
   /// Called when the layer is updated.
   /// # Arguments
+  /// * `ctx` - The engine context.
   /// * `dt` - The time in seconds since the last update(delta time).
-  fn on_update(&mut self, dt: f32) -> ();
+  fn on_update(&mut self, ctx: &mut GegContext, dt: f32) -> ();
+
+  /// Called at a fixed rate, before `on_update`, zero or more times per frame.
+  /// use it for physics and simulation that need deterministic ticks.
+  /// # Arguments
+  /// * `ctx` - The engine context.
+  /// * `fixed_dt` - The time in seconds between two fixed updates.
+  fn on_fixed_update(&mut self, ctx: &mut GegContext, fixed_dt: f32) -> () {}
 
   /// Called with each event.
   /// # Arguments
+  /// * `ctx` - The engine context.
   /// * `event` - The event that occured.
   ///
   /// # Returns
   /// * `true` - If the event was handled by the layer and shouldn't be passed down to other layers.
   /// * `false` - If the event wasn't handled by the layer and should be passed down to other layers.
-  fn on_event(&mut self, event: GegEvent, modifiers: ModifiersState) -> bool {
+  fn on_event(&mut self, ctx: &mut GegContext, event: GegEvent, modifiers: ModifiersState) -> bool {
     false
   }
 }
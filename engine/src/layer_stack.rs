@@ -0,0 +1,319 @@
+use crate::context::{GegContext, LayerCommand};
+use crate::events::GegEvent;
+use crate::io::ModifiersState;
+use crate::layer::Layer;
+
+use spdlog::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A handle to a layer in a `LayerStack`, used to remove that specific layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerId(u64);
+
+impl LayerId {
+  pub(crate) fn next() -> Self {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+  }
+}
+
+struct LayerEntry {
+  id: LayerId,
+  layer: Box<dyn Layer>,
+}
+
+/// An ordered stack of layers.
+///
+/// Layers are stored bottom to top, normal layers first and overlays after them,
+/// so an overlay is always above every normal layer.
+/// Updates are dispatched bottom-up and events top-down.
+pub struct LayerStack {
+  layers: Vec<LayerEntry>,
+  // index of the first overlay
+  overlay_start: usize,
+}
+
+impl Default for LayerStack {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl LayerStack {
+  pub fn new() -> Self {
+    Self {
+      layers: Vec::new(),
+      overlay_start: 0,
+    }
+  }
+
+  /// Pushes a layer on top of the normal layers, below every overlay.
+  pub fn push_layer(&mut self, layer: Box<dyn Layer>, ctx: &mut GegContext) -> LayerId {
+    let id = LayerId::next();
+    self.insert_layer(id, layer, ctx);
+    id
+  }
+
+  /// Pushes an overlay on top of everything.
+  pub fn push_overlay(&mut self, layer: Box<dyn Layer>, ctx: &mut GegContext) -> LayerId {
+    let id = LayerId::next();
+    self.insert_overlay(id, layer, ctx);
+    id
+  }
+
+  /// Removes the top most normal layer.
+  pub fn pop_layer(&mut self, ctx: &mut GegContext) -> Option<Box<dyn Layer>> {
+    if self.overlay_start == 0 {
+      return None;
+    }
+
+    self.overlay_start -= 1;
+    let entry = self.layers.remove(self.overlay_start);
+    Some(Self::detach(entry, ctx))
+  }
+
+  /// Removes the top most overlay.
+  pub fn pop_overlay(&mut self, ctx: &mut GegContext) -> Option<Box<dyn Layer>> {
+    if self.layers.len() == self.overlay_start {
+      return None;
+    }
+
+    let entry = self.layers.pop().unwrap();
+    Some(Self::detach(entry, ctx))
+  }
+
+  /// Removes a specific layer or overlay.
+  pub fn remove(&mut self, id: LayerId, ctx: &mut GegContext) -> Option<Box<dyn Layer>> {
+    let index = self.layers.iter().position(|entry| entry.id == id)?;
+    if index < self.overlay_start {
+      self.overlay_start -= 1;
+    }
+
+    let entry = self.layers.remove(index);
+    Some(Self::detach(entry, ctx))
+  }
+
+  /// Detaches and drops every layer, top to bottom.
+  pub fn clear(&mut self, ctx: &mut GegContext) {
+    while let Some(entry) = self.layers.pop() {
+      Self::detach(entry, ctx);
+    }
+    self.overlay_start = 0;
+  }
+
+  pub fn contains(&self, id: LayerId) -> bool {
+    self.layers.iter().any(|entry| entry.id == id)
+  }
+
+  pub fn len(&self) -> usize {
+    self.layers.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.layers.is_empty()
+  }
+
+  /// Updates every layer, bottom to top.
+  pub fn on_update(&mut self, ctx: &mut GegContext, dt: f32) {
+    for entry in self.layers.iter_mut() {
+      entry.layer.on_update(ctx, dt);
+    }
+    self.apply_commands(ctx);
+  }
+
+  /// Runs a fixed update on every layer, bottom to top.
+  pub fn on_fixed_update(&mut self, ctx: &mut GegContext, fixed_dt: f32) {
+    for entry in self.layers.iter_mut() {
+      entry.layer.on_fixed_update(ctx, fixed_dt);
+    }
+    self.apply_commands(ctx);
+  }
+
+  /// Passes an event to the layers, top to bottom, until one of them handles it.
+  ///
+  /// # Returns
+  /// * `true` - If a layer handled the event.
+  pub fn on_event(
+    &mut self,
+    ctx: &mut GegContext,
+    event: GegEvent,
+    modifiers: ModifiersState,
+  ) -> bool {
+    let handled = self
+      .layers
+      .iter_mut()
+      .rev()
+      .any(|entry| entry.layer.on_event(ctx, event.clone(), modifiers));
+    self.apply_commands(ctx);
+    handled
+  }
+
+  /// Applies the layer changes requested through the context.
+  pub(crate) fn apply_commands(&mut self, ctx: &mut GegContext) {
+    // attaching a layer can request more changes
+    while !ctx.layer_commands.is_empty() {
+      let commands = std::mem::take(&mut ctx.layer_commands);
+      for command in commands {
+        match command {
+          LayerCommand::PushLayer(id, layer) => self.insert_layer(id, layer, ctx),
+          LayerCommand::PushOverlay(id, layer) => self.insert_overlay(id, layer, ctx),
+          LayerCommand::PopLayer => {
+            self.pop_layer(ctx);
+          }
+          LayerCommand::PopOverlay => {
+            self.pop_overlay(ctx);
+          }
+          LayerCommand::Remove(id) => {
+            self.remove(id, ctx);
+          }
+        }
+      }
+    }
+  }
+
+  fn insert_layer(&mut self, id: LayerId, layer: Box<dyn Layer>, ctx: &mut GegContext) {
+    let entry = Self::attach(id, layer, ctx);
+    self.layers.insert(self.overlay_start, entry);
+    self.overlay_start += 1;
+  }
+
+  fn insert_overlay(&mut self, id: LayerId, layer: Box<dyn Layer>, ctx: &mut GegContext) {
+    let entry = Self::attach(id, layer, ctx);
+    self.layers.push(entry);
+  }
+
+  fn attach(id: LayerId, mut layer: Box<dyn Layer>, ctx: &mut GegContext) -> LayerEntry {
+    layer.on_attach(ctx);
+    debug!("Layer {:?} attached", id);
+
+    LayerEntry { id, layer }
+  }
+
+  fn detach(mut entry: LayerEntry, ctx: &mut GegContext) -> Box<dyn Layer> {
+    entry.layer.on_detach(ctx);
+    debug!("Layer {:?} detached", entry.id);
+
+    entry.layer
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::{GegBackend, GraphicsContext, GraphicsOptions};
+  use crate::io::Key;
+  use crate::time::Time;
+  use crate::window::GegWindow;
+  use glam::UVec2;
+  use std::sync::{Arc, Mutex};
+
+  type Log = Arc<Mutex<Vec<String>>>;
+
+  /// logs its callbacks, handles events when `handles` is set and can push a layer and pop
+  /// itself on its first update
+  struct Probe {
+    name: &'static str,
+    log: Log,
+    handles: bool,
+    push_on_update: Option<Box<dyn Layer>>,
+  }
+
+  impl Probe {
+    fn new(name: &'static str, log: &Log) -> Box<Self> {
+      Box::new(Self {
+        name,
+        log: log.clone(),
+        handles: false,
+        push_on_update: None,
+      })
+    }
+
+    fn push(&self, what: &str) {
+      self.log.lock().unwrap().push(format!("{} {}", what, self.name));
+    }
+  }
+
+  impl Layer for Probe {
+    fn on_attach(&mut self, _ctx: &mut GegContext) {
+      self.push("attach");
+    }
+
+    fn on_detach(&mut self, _ctx: &mut GegContext) {
+      self.push("detach");
+    }
+
+    fn on_update(&mut self, ctx: &mut GegContext, _dt: f32) {
+      self.push("update");
+      if let Some(layer) = self.push_on_update.take() {
+        ctx.push_overlay(layer);
+        ctx.pop_layer();
+      }
+    }
+
+    fn on_event(&mut self, _ctx: &mut GegContext, _event: GegEvent, _: ModifiersState) -> bool {
+      self.push("event");
+      self.handles
+    }
+  }
+
+  fn context() -> GegContext {
+    let size = UVec2::new(64, 64);
+    let graphics =
+      GraphicsContext::new(GegBackend::Null, None, size.into(), GraphicsOptions::default());
+    GegContext::new(GegWindow::new(None, "test".to_string(), size), graphics, Time::default())
+  }
+
+  fn take(log: &Log) -> Vec<String> {
+    std::mem::take(&mut *log.lock().unwrap())
+  }
+
+  #[test]
+  fn updates_go_up_and_events_go_down() {
+    let log = Log::default();
+    let mut ctx = context();
+    let mut stack = LayerStack::new();
+    stack.push_overlay(Probe::new("overlay", &log), &mut ctx);
+    stack.push_layer(Probe::new("bottom", &log), &mut ctx);
+    let mut top = Probe::new("top", &log);
+    top.handles = true;
+    stack.push_layer(top, &mut ctx);
+    take(&log);
+
+    stack.on_update(&mut ctx, 0.0);
+    assert_eq!(take(&log), ["update bottom", "update top", "update overlay"]);
+
+    let event = GegEvent::KeyDown(Key::Space);
+    assert!(stack.on_event(&mut ctx, event, ModifiersState::default()));
+    // the top layer handles it, the bottom one never sees it
+    assert_eq!(take(&log), ["event overlay", "event top"]);
+  }
+
+  #[test]
+  fn changes_requested_by_layers_wait_for_the_callback() {
+    let log = Log::default();
+    let mut ctx = context();
+    let mut stack = LayerStack::new();
+    let mut pusher = Probe::new("pusher", &log);
+    pusher.push_on_update = Some(Probe::new("pushed", &log));
+    stack.push_layer(pusher, &mut ctx);
+    let other = stack.push_layer(Probe::new("other", &log), &mut ctx);
+    take(&log);
+
+    // the pushed overlay isn't updated in the frame it was pushed in, the popped layer is
+    // still updated after the pusher asked for it
+    stack.on_update(&mut ctx, 0.0);
+    assert_eq!(
+      take(&log),
+      ["update pusher", "update other", "attach pushed", "detach other"]
+    );
+    assert!(!stack.contains(other));
+    assert_eq!(stack.len(), 2);
+
+    stack.on_update(&mut ctx, 0.0);
+    assert_eq!(take(&log), ["update pusher", "update pushed"]);
+
+    stack.clear(&mut ctx);
+    assert_eq!(take(&log), ["detach pushed", "detach pusher"]);
+    assert!(stack.is_empty());
+  }
+}
@@ -1,8 +1,26 @@
 pub mod app;
 pub mod backend;
+pub mod camera;
+pub mod context;
+pub mod debug_draw;
 pub mod layer;
+pub mod layer_stack;
+pub mod mesh;
+pub mod post_process;
+pub mod render_graph;
+pub mod replay;
+pub mod shader;
+pub mod sprite;
+pub mod text;
+pub mod texture;
 pub mod events;
 pub mod io;
+pub mod gamepad;
+pub mod input;
+pub mod input_map;
+pub mod time;
+pub mod traits;
+pub mod window;
 
 pub use spdlog::prelude::*;
 
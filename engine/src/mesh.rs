@@ -0,0 +1,411 @@
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+
+use crate::traits::{
+  BufferDesc, BufferHandle, BufferUsage, GraphicsDevice, GraphicsError, IndexFormat,
+  VertexFormat, VertexLayout,
+};
+
+/// The index data of a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indices<'a> {
+  U16(&'a [u16]),
+  U32(&'a [u32]),
+}
+
+impl<'a> Indices<'a> {
+  pub fn format(&self) -> IndexFormat {
+    match self {
+      Indices::U16(_) => IndexFormat::U16,
+      Indices::U32(_) => IndexFormat::U32,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    match self {
+      Indices::U16(indices) => indices.len(),
+      Indices::U32(indices) => indices.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn as_bytes(&self) -> &'a [u8] {
+    match self {
+      Indices::U16(indices) => bytemuck::cast_slice(indices),
+      Indices::U32(indices) => bytemuck::cast_slice(indices),
+    }
+  }
+
+  fn to_vec(self) -> Vec<u32> {
+    match self {
+      Indices::U16(indices) => indices.iter().map(|&index| index as u32).collect(),
+      Indices::U32(indices) => indices.to_vec(),
+    }
+  }
+}
+
+/// A part of a mesh that can be drawn on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submesh {
+  /// The indices to draw, or the vertices if the mesh has no indices.
+  pub range: Range<u32>,
+  /// Added to every index before reading the vertex.
+  pub base_vertex: i32,
+}
+
+/// Everything needed to create a mesh.
+#[derive(Debug, Clone)]
+pub struct MeshDesc<'a> {
+  pub layout: VertexLayout,
+  /// Tightly packed vertices following `layout`.
+  pub vertices: &'a [u8],
+  pub indices: Option<Indices<'a>>,
+  /// If empty, the whole mesh is a single submesh.
+  pub submeshes: Vec<Submesh>,
+}
+
+/// The transform of a mesh draw, read per instance by mesh pipelines.
+///
+/// Its columns are bound to the locations starting at `MeshInstance::LOCATION`
+/// in the vertex buffer slot after the mesh vertices.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Zeroable, Pod)]
+pub struct MeshInstance {
+  pub transform: [[f32; 4]; 4],
+}
+
+impl MeshInstance {
+  /// The location of the first column, the mesh attributes must stay below it.
+  pub const LOCATION: u32 = 12;
+
+  pub fn new(transform: Mat4) -> Self {
+    Self {
+      transform: transform.to_cols_array_2d(),
+    }
+  }
+
+  pub fn layout() -> VertexLayout {
+    VertexLayout::packed(&[VertexFormat::Float32x4; 4])
+      .with_location_offset(Self::LOCATION)
+      .per_instance()
+  }
+}
+
+/// Vertices and optional indices on the gpu, split into submeshes.
+///
+/// Draw it with `GraphicsContext::draw_mesh` and free it with `Mesh::destroy`.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+  layout: VertexLayout,
+  vertex_buffer: BufferHandle,
+  vertex_count: u32,
+  index_buffer: Option<(BufferHandle, IndexFormat)>,
+  index_count: u32,
+  // kept to check that the submeshes only read existing vertices
+  indices: Vec<u32>,
+  submeshes: Vec<Submesh>,
+}
+
+impl Mesh {
+  pub fn new(device: &mut dyn GraphicsDevice, desc: &MeshDesc) -> Result<Self, GraphicsError> {
+    let stride = desc.layout.stride as usize;
+    let vertex_count = desc.vertices.len() / stride.max(1);
+    if stride == 0 || vertex_count * stride != desc.vertices.len() {
+      return Err(GraphicsError::InvalidSize {
+        expected: (vertex_count * stride) as u64,
+        actual: desc.vertices.len() as u64,
+      });
+    }
+
+    let vertex_buffer = device.create_buffer(
+      &BufferDesc {
+        size: desc.vertices.len() as u64,
+        usage: BufferUsage::Vertex,
+      },
+      Some(desc.vertices),
+    )?;
+
+    let index_buffer = match desc.indices {
+      Some(indices) => {
+        let format = indices.format();
+        let buffer = device.create_buffer(
+          &BufferDesc {
+            size: indices.as_bytes().len() as u64,
+            usage: BufferUsage::Index(format),
+          },
+          Some(indices.as_bytes()),
+        );
+
+        match buffer {
+          Ok(buffer) => Some((buffer, format)),
+          Err(e) => {
+            device.destroy_buffer(vertex_buffer);
+            return Err(e);
+          }
+        }
+      }
+      None => None,
+    };
+
+    let mut mesh = Self {
+      layout: desc.layout.clone(),
+      vertex_buffer,
+      vertex_count: vertex_count as u32,
+      index_buffer,
+      index_count: desc.indices.map_or(0, |indices| indices.len() as u32),
+      indices: desc.indices.map_or_else(Vec::new, Indices::to_vec),
+      submeshes: Vec::new(),
+    };
+    if let Err(e) = mesh.set_submeshes(desc.submeshes.clone()) {
+      mesh.destroy(device);
+      return Err(e);
+    }
+    Ok(mesh)
+  }
+
+  /// Overwrites the vertices starting at `first_vertex`, the vertex count can't change.
+  pub fn update_vertices(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    first_vertex: u32,
+    vertices: &[u8],
+  ) -> Result<(), GraphicsError> {
+    let offset = first_vertex as u64 * self.layout.stride as u64;
+    device.write_buffer(self.vertex_buffer, offset, vertices)
+  }
+
+  /// Overwrites the indices starting at `first_index`, they must have the mesh's index format.
+  ///
+  /// Fails without changing anything if a submesh would read a vertex that doesn't exist.
+  pub fn update_indices(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    first_index: u32,
+    indices: Indices,
+  ) -> Result<(), GraphicsError> {
+    let (buffer, format) = match self.index_buffer {
+      Some((buffer, format)) if format == indices.format() => (buffer, format),
+      _ => return Err(GraphicsError::InvalidHandle),
+    };
+
+    let start = first_index as usize;
+    let end = start + indices.len();
+    if end > self.indices.len() {
+      return Err(GraphicsError::InvalidSize {
+        expected: self.indices.len().saturating_sub(start) as u64,
+        actual: indices.len() as u64,
+      });
+    }
+
+    let mut updated = self.indices.clone();
+    updated.splice(start..end, indices.to_vec());
+    for submesh in &self.submeshes {
+      self.check_submesh(submesh, &updated)?;
+    }
+
+    device.write_buffer(buffer, first_index as u64 * format.size(), indices.as_bytes())?;
+    self.indices = updated;
+    Ok(())
+  }
+
+  /// Replaces the submeshes, an empty list makes the whole mesh a single submesh.
+  ///
+  /// Fails without changing the submeshes if one is outside of the indices, or of the vertices
+  /// for meshes without indices, or if its indices plus `base_vertex` aren't vertices.
+  pub fn set_submeshes(&mut self, submeshes: Vec<Submesh>) -> Result<(), GraphicsError> {
+    let submeshes = if submeshes.is_empty() {
+      vec![Submesh {
+        range: 0..self.element_count(),
+        base_vertex: 0,
+      }]
+    } else {
+      submeshes
+    };
+
+    for submesh in &submeshes {
+      self.check_submesh(submesh, &self.indices)?;
+    }
+    self.submeshes = submeshes;
+    Ok(())
+  }
+
+  fn check_submesh(&self, submesh: &Submesh, indices: &[u32]) -> Result<(), GraphicsError> {
+    let Range { start, end } = submesh.range;
+    if start > end || end > self.element_count() {
+      return Err(GraphicsError::InvalidUsage(format!(
+        "the submesh {}..{} is outside of the {} elements of the mesh",
+        start,
+        end,
+        self.element_count()
+      )));
+    }
+
+    if !self.is_indexed() {
+      return Ok(());
+    }
+    let vertices = 0..self.vertex_count as i64;
+    let outside = indices[start as usize..end as usize]
+      .iter()
+      .map(|&index| index as i64 + submesh.base_vertex as i64)
+      .find(|vertex| !vertices.contains(vertex));
+    match outside {
+      Some(vertex) => Err(GraphicsError::InvalidUsage(format!(
+        "the submesh {}..{} reads the vertex {} but the mesh has {}",
+        start, end, vertex, self.vertex_count
+      ))),
+      None => Ok(()),
+    }
+  }
+
+  /// Frees the gpu buffers.
+  pub fn destroy(self, device: &mut dyn GraphicsDevice) {
+    device.destroy_buffer(self.vertex_buffer);
+    if let Some((buffer, _)) = self.index_buffer {
+      device.destroy_buffer(buffer);
+    }
+  }
+
+  pub fn layout(&self) -> &VertexLayout {
+    &self.layout
+  }
+
+  pub fn vertex_buffer(&self) -> BufferHandle {
+    self.vertex_buffer
+  }
+
+  pub fn index_buffer(&self) -> Option<BufferHandle> {
+    self.index_buffer.map(|(buffer, _)| buffer)
+  }
+
+  pub fn index_format(&self) -> Option<IndexFormat> {
+    self.index_buffer.map(|(_, format)| format)
+  }
+
+  pub fn vertex_count(&self) -> u32 {
+    self.vertex_count
+  }
+
+  pub fn index_count(&self) -> u32 {
+    self.index_count
+  }
+
+  pub fn is_indexed(&self) -> bool {
+    self.index_buffer.is_some()
+  }
+
+  pub fn submeshes(&self) -> &[Submesh] {
+    &self.submeshes
+  }
+
+  /// The number of indices, or vertices if the mesh has no indices.
+  fn element_count(&self) -> u32 {
+    if self.is_indexed() {
+      self.index_count
+    } else {
+      self.vertex_count
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::null::{GraphicsCall, GraphicsLog, NullDevice};
+  use crate::backend::GraphicsOptions;
+
+  const QUAD: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 0.0],
+  ];
+  const INDICES: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+  fn device() -> (NullDevice, GraphicsLog) {
+    let log = GraphicsLog::new();
+    let device = NullDevice::new(log.clone(), [64, 64], GraphicsOptions::default());
+    (device, log)
+  }
+
+  fn desc(indices: Option<Indices>, submeshes: Vec<Submesh>) -> MeshDesc {
+    MeshDesc {
+      layout: VertexLayout::packed(&[VertexFormat::Float32x3]),
+      vertices: bytemuck::cast_slice(&QUAD),
+      indices,
+      submeshes,
+    }
+  }
+
+  fn submesh(range: Range<u32>, base_vertex: i32) -> Submesh {
+    Submesh { range, base_vertex }
+  }
+
+  #[test]
+  fn the_whole_mesh_is_the_default_submesh() {
+    let (mut device, _) = device();
+    let indexed = Mesh::new(&mut device, &desc(Some(Indices::U16(&INDICES)), vec![])).unwrap();
+    assert_eq!(indexed.submeshes(), &[submesh(0..6, 0)]);
+    assert_eq!(indexed.vertex_count(), 4);
+    assert_eq!(indexed.index_format(), Some(IndexFormat::U16));
+
+    let vertices = Mesh::new(&mut device, &desc(None, vec![])).unwrap();
+    assert_eq!(vertices.submeshes(), &[submesh(0..4, 0)]);
+  }
+
+  #[test]
+  fn submeshes_only_read_existing_elements() {
+    let (mut device, _) = device();
+    let mut mesh = Mesh::new(&mut device, &desc(Some(Indices::U16(&INDICES)), vec![])).unwrap();
+
+    assert!(mesh.set_submeshes(vec![submesh(0..3, 0), submesh(3..6, 0)]).is_ok());
+    assert!(mesh.set_submeshes(vec![submesh(0..7, 0)]).is_err());
+    let reversed = submesh(Range { start: 4, end: 2 }, 0);
+    assert!(mesh.set_submeshes(vec![reversed]).is_err());
+    // the first triangle moved by one vertex still fits, the second one doesn't
+    assert!(mesh.set_submeshes(vec![submesh(0..3, 1)]).is_ok());
+    assert!(mesh.set_submeshes(vec![submesh(3..6, 1)]).is_err());
+    assert!(mesh.set_submeshes(vec![submesh(0..3, -1)]).is_err());
+    assert_eq!(mesh.submeshes(), &[submesh(0..3, 1)]);
+
+    let mut vertices = Mesh::new(&mut device, &desc(None, vec![])).unwrap();
+    assert!(vertices.set_submeshes(vec![submesh(1..4, 0)]).is_ok());
+    assert!(vertices.set_submeshes(vec![submesh(1..5, 0)]).is_err());
+  }
+
+  #[test]
+  fn index_updates_keep_the_submeshes_valid() {
+    let (mut device, _) = device();
+    let mut mesh = Mesh::new(
+      &mut device,
+      &desc(Some(Indices::U16(&INDICES)), vec![submesh(0..3, 1)]),
+    )
+    .unwrap();
+
+    assert!(mesh.update_indices(&mut device, 0, Indices::U16(&[3])).is_err());
+    assert!(mesh.update_indices(&mut device, 3, Indices::U16(&[3])).is_ok());
+    assert!(mesh.update_indices(&mut device, 5, Indices::U16(&[0, 0])).is_err());
+    assert!(matches!(
+      mesh.update_indices(&mut device, 0, Indices::U32(&[0])),
+      Err(GraphicsError::InvalidHandle)
+    ));
+  }
+
+  #[test]
+  fn a_failed_mesh_frees_its_buffers() {
+    let (mut device, log) = device();
+    let desc = desc(Some(Indices::U16(&INDICES)), vec![submesh(0..6, 4)]);
+    assert!(Mesh::new(&mut device, &desc).is_err());
+
+    let destroyed = log
+      .calls()
+      .into_iter()
+      .filter(|call| matches!(call, GraphicsCall::DestroyBuffer(_)))
+      .count();
+    assert_eq!(destroyed, 2);
+  }
+}
@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+use spdlog::prelude::*;
+
+use crate::backend::GraphicsContext;
+use crate::render_graph::{GraphTarget, RenderGraph, TargetSize};
+use crate::texture::Texture;
+use crate::traits::{
+  BlendMode, BufferDesc, BufferHandle, BufferUsage, CommandEncoder, CullMode, DepthMode,
+  DescriptorSetDesc, DescriptorSetHandle, DescriptorSetLayout, GraphicsDevice, GraphicsError,
+  PipelineDesc, PipelineHandle, PrimitiveTopology, SamplerDesc, SamplerHandle, ShaderDesc,
+  ShaderSource, TargetFormat, TextureHandle,
+};
+
+const POST_SHADER: &str = "
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+struct Params {
+  values: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> params: Params;
+
+@group(2) @binding(0)
+var extra: texture_2d<f32>;
+@group(2) @binding(1)
+var extra_sampler: sampler;
+
+// a triangle covering the screen, the uvs start at the top left like the textures
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let x = f32(i32(index & 1u) * 4 - 1);
+  let y = f32(i32(index & 2u) * 2 - 1);
+  var out: VertexOutput;
+  out.position = vec4<f32>(x, y, 0.0, 1.0);
+  out.uv = vec2<f32>(x * 0.5 + 0.5, 0.5 - y * 0.5);
+  return out;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec4<f32> {
+  return textureSampleLevel(source, source_sampler, uv, 0.0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+  return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4<f32> {
+  return sample_source(in.uv);
+}
+
+@fragment
+fn fs_exposure(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = sample_source(in.uv);
+  return vec4<f32>(color.rgb * params.values.x, color.a);
+}
+
+// keeps what's brighter than the threshold, clamped so single pixels don't flicker
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = min(sample_source(in.uv).rgb, vec3<f32>(64.0));
+  let brightness = luminance(color);
+  let bright = max(brightness - params.values.x, 0.0) / max(brightness, 0.0001);
+  return vec4<f32>(color * bright, 1.0);
+}
+
+// a 9 tap gaussian folded into 5 linear samples
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+  let texel = direction * params.values.x / vec2<f32>(textureDimensions(source));
+  var color = sample_source(uv) * 0.2270270270;
+  color += (sample_source(uv + texel * 1.3846153846) + sample_source(uv - texel * 1.3846153846))
+    * 0.3162162162;
+  color += (sample_source(uv + texel * 3.2307692308) + sample_source(uv - texel * 3.2307692308))
+    * 0.0702702703;
+  return color;
+}
+
+@fragment
+fn fs_blur_h(in: VertexOutput) -> @location(0) vec4<f32> {
+  return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_v(in: VertexOutput) -> @location(0) vec4<f32> {
+  return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+@fragment
+fn fs_bloom(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = sample_source(in.uv);
+  let bloom = textureSampleLevel(extra, extra_sampler, in.uv, 0.0).rgb;
+  return vec4<f32>(color.rgb + bloom * params.values.x, color.a);
+}
+
+// the fit of the ACES filmic curve by Krzysztof Narkowicz
+@fragment
+fn fs_aces(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = sample_source(in.uv);
+  let x = max(color.rgb, vec3<f32>(0.0));
+  let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+  return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);
+}
+
+@fragment
+fn fs_reinhard(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = sample_source(in.uv);
+  let x = max(color.rgb, vec3<f32>(0.0));
+  return vec4<f32>(x / (x + 1.0), color.a);
+}
+
+// fxaa works on the perceived brightness, roughly the square root of the linear one
+fn luma(color: vec3<f32>) -> f32 {
+  return sqrt(max(dot(color, vec3<f32>(0.299, 0.587, 0.114)), 0.0));
+}
+
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = 1.0 / vec2<f32>(textureDimensions(source));
+  let center = sample_source(in.uv);
+  let nw = luma(sample_source(in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+  let ne = luma(sample_source(in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+  let sw = luma(sample_source(in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+  let se = luma(sample_source(in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+  let m = luma(center.rgb);
+  let luma_min = min(m, min(min(nw, ne), min(sw, se)));
+  let luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+  // blurs along the edge, across the direction the brightness changes in
+  var direction = vec2<f32>(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+  let reduce = max((nw + ne + sw + se) * 0.25 * 0.125, 1.0 / 128.0);
+  let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+  direction = clamp(direction * scale, vec2<f32>(-8.0), vec2<f32>(8.0)) * texel;
+
+  let a = 0.5 * (
+    sample_source(in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+      + sample_source(in.uv + direction * (2.0 / 3.0 - 0.5)).rgb
+  );
+  let b = a * 0.5 + 0.25 * (
+    sample_source(in.uv - direction * 0.5).rgb + sample_source(in.uv + direction * 0.5).rgb
+  );
+
+  // the wider blur went past the edge
+  let luma_b = luma(b);
+  if luma_b < luma_min || luma_b > luma_max {
+    return vec4<f32>(a, center.a);
+  }
+  return vec4<f32>(b, center.a);
+}
+
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = sample_source(in.uv);
+  // 1 in the corners
+  let distance = length(in.uv - 0.5) * 1.4142135;
+  let radius = params.values.y;
+  let smoothness = max(params.values.z, 0.001);
+  let shade = 1.0 - smoothstep(radius - smoothness, radius, distance);
+  return vec4<f32>(color.rgb * mix(1.0, shade, params.values.x), color.a);
+}
+
+fn to_srgb(color: vec3<f32>) -> vec3<f32> {
+  let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return select(high, color * 12.92, color <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(color: vec3<f32>) -> vec3<f32> {
+  let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+  return select(high, color / 12.92, color <= vec3<f32>(0.04045));
+}
+
+// the lut is a strip of `size` slices of `size` by `size` texels, blue picks the slice,
+// red grows to the right and green downwards in each slice
+fn lookup(color: vec3<f32>) -> vec3<f32> {
+  let size = f32(textureDimensions(extra).y);
+  let scaled = color * (size - 1.0);
+  let slice = min(floor(scaled.b), size - 2.0);
+  let x = (scaled.r + 0.5) / (size * size);
+  let y = (scaled.g + 0.5) / size;
+  let first = textureSampleLevel(extra, extra_sampler, vec2<f32>(x + slice / size, y), 0.0);
+  let second =
+    textureSampleLevel(extra, extra_sampler, vec2<f32>(x + (slice + 1.0) / size, y), 0.0);
+  return mix(first.rgb, second.rgb, scaled.b - slice);
+}
+
+@fragment
+fn fs_grading(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = sample_source(in.uv);
+  let srgb = to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+  let graded = to_linear(lookup(srgb));
+  return vec4<f32>(mix(color.rgb, graded, params.values.x), color.a);
+}
+";
+
+/// The curve mapping HDR colors into the 0 to 1 range of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+  /// A fit of the ACES filmic curve, with more contrast and saturated highlights.
+  Aces,
+  /// `color / (color + 1)`, softer and never fully white.
+  Reinhard,
+}
+
+/// A full-screen effect of a `PostStack`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+  /// Scales the colors by 2 to the power of `ev`.
+  Exposure { ev: f32 },
+  /// Blurs what's brighter than `threshold` at half resolution and adds it back scaled by
+  /// `intensity`. `radius` spreads the blur, 1 is a few pixels.
+  Bloom {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+  },
+  Tonemap(Tonemap),
+  /// Smooths the aliased edges, it expects tonemapped colors.
+  Fxaa,
+  /// Darkens the image towards the corners by `intensity`. `radius` is where the darkening
+  /// ends, 1 being the corners, and `smoothness` how far in it starts.
+  Vignette {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+  },
+  /// Replaces the tonemapped colors by the ones in `lut`, mixed by `intensity`.
+  ///
+  /// The lut is laid out like `neutral_lut` and loaded without `srgb`. It has to be kept
+  /// until the effect is removed from the stack and the stack is added to a graph again.
+  ColorGrading { lut: Texture, intensity: f32 },
+}
+
+impl Effect {
+  /// A short name for logs and settings menus.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Effect::Exposure { .. } => "exposure",
+      Effect::Bloom { .. } => "bloom",
+      Effect::Tonemap(Tonemap::Aces) => "aces",
+      Effect::Tonemap(Tonemap::Reinhard) => "reinhard",
+      Effect::Fxaa => "fxaa",
+      Effect::Vignette { .. } => "vignette",
+      Effect::ColorGrading { .. } => "color grading",
+    }
+  }
+}
+
+/// An effect of a `PostStack`, it keeps its settings while disabled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostEffect {
+  pub effect: Effect,
+  pub enabled: bool,
+}
+
+impl PostEffect {
+  /// An enabled effect.
+  pub fn new(effect: Effect) -> Self {
+    Self {
+      effect,
+      enabled: true,
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+struct PostPipelines {
+  copy: PipelineHandle,
+  exposure: PipelineHandle,
+  bright: PipelineHandle,
+  blur_h: PipelineHandle,
+  blur_v: PipelineHandle,
+  bloom: PipelineHandle,
+  aces: PipelineHandle,
+  reinhard: PipelineHandle,
+  fxaa: PipelineHandle,
+  vignette: PipelineHandle,
+  grading: PipelineHandle,
+}
+
+impl PostPipelines {
+  fn new(device: &mut dyn GraphicsDevice) -> Result<Self, GraphicsError> {
+    Ok(Self {
+      copy: create_pipeline(device, "fs_copy", 1)?,
+      exposure: create_pipeline(device, "fs_exposure", 2)?,
+      bright: create_pipeline(device, "fs_bright", 2)?,
+      blur_h: create_pipeline(device, "fs_blur_h", 2)?,
+      blur_v: create_pipeline(device, "fs_blur_v", 2)?,
+      bloom: create_pipeline(device, "fs_bloom", 3)?,
+      aces: create_pipeline(device, "fs_aces", 1)?,
+      reinhard: create_pipeline(device, "fs_reinhard", 1)?,
+      fxaa: create_pipeline(device, "fs_fxaa", 1)?,
+      vignette: create_pipeline(device, "fs_vignette", 2)?,
+      grading: create_pipeline(device, "fs_grading", 3)?,
+    })
+  }
+
+  fn all(&self) -> [PipelineHandle; 11] {
+    [
+      self.copy,
+      self.exposure,
+      self.bright,
+      self.blur_h,
+      self.blur_v,
+      self.bloom,
+      self.aces,
+      self.reinhard,
+      self.fxaa,
+      self.vignette,
+      self.grading,
+    ]
+  }
+}
+
+/// A full-screen pipeline sampling the source at set 0, with its parameters at set 1
+/// and a second texture at set 2 if it has that many sets.
+fn create_pipeline(
+  device: &mut dyn GraphicsDevice,
+  entry_point: &str,
+  sets: usize,
+) -> Result<PipelineHandle, GraphicsError> {
+  let shader = |entry_point: &str| ShaderDesc {
+    source: ShaderSource::Wgsl(POST_SHADER.to_string()),
+    entry_point: entry_point.to_string(),
+    path: None,
+  };
+
+  let layouts = [
+    DescriptorSetLayout::texture(),
+    DescriptorSetLayout::uniform_buffers(1),
+    DescriptorSetLayout::texture(),
+  ];
+
+  device.create_pipeline(&PipelineDesc {
+    vertex_shader: shader("vs_fullscreen"),
+    fragment_shader: shader(entry_point),
+    vertex_layouts: Vec::new(),
+    descriptor_set_layouts: layouts[..sets].to_vec(),
+    topology: PrimitiveTopology::TriangleList,
+    blend: BlendMode::Opaque,
+    cull_mode: CullMode::None,
+    depth: DepthMode::Disabled,
+  })
+}
+
+/// What a post pass binds at set 2.
+#[derive(Clone, Copy)]
+enum Extra {
+  None,
+  Target(GraphTarget),
+  Set(DescriptorSetHandle),
+}
+
+/// The parameters of a pass, their meaning depends on the shader.
+type Params = [f32; 4];
+
+/// A full-screen draw sampling the previous target.
+#[derive(Clone, Copy)]
+struct PostPass {
+  name: &'static str,
+  pipeline: PipelineHandle,
+  params: Option<DescriptorSetHandle>,
+  extra: Extra,
+}
+
+impl PostPass {
+  fn new(name: &'static str, pipeline: PipelineHandle) -> Self {
+    Self {
+      name,
+      pipeline,
+      params: None,
+      extra: Extra::None,
+    }
+  }
+
+  fn params(self, params: DescriptorSetHandle) -> Self {
+    Self {
+      params: Some(params),
+      ..self
+    }
+  }
+
+  fn extra(self, extra: Extra) -> Self {
+    Self { extra, ..self }
+  }
+}
+
+/// A chain of full-screen effects turning an HDR image into the final one.
+///
+/// The effects run in the order of `effects`, which can be changed at any time, e.g. to
+/// turn one off or move it. Tonemapping maps the colors into the range of the screen,
+/// the effects after it work on those. The default chain is exposure, bloom, ACES
+/// tonemapping, FXAA and a vignette.
+///
+/// The stack is added to a `RenderGraph` every frame with `add_passes`, which creates an
+/// HDR target between each effect. The graph reuses them so only a couple exist at once.
+pub struct PostStack {
+  effects: Vec<PostEffect>,
+  pipelines: PostPipelines,
+  sampler: SamplerHandle,
+  // a uniform buffer and its set per pass, reused every frame
+  params: Vec<(BufferHandle, DescriptorSetHandle)>,
+  used_params: usize,
+  frame: u64,
+  lut_sets: HashMap<TextureHandle, DescriptorSetHandle>,
+}
+
+impl PostStack {
+  pub fn new(graphics: &mut GraphicsContext) -> Result<Self, GraphicsError> {
+    let frame = graphics.frame_count();
+    let device = graphics.device();
+    let pipelines = PostPipelines::new(device)?;
+    let sampler = device.create_sampler(&SamplerDesc::linear())?;
+
+    Ok(Self {
+      effects: vec![
+        PostEffect::new(Effect::Exposure { ev: 0.0 }),
+        PostEffect::new(Effect::Bloom {
+          threshold: 1.0,
+          intensity: 0.6,
+          radius: 1.0,
+        }),
+        PostEffect::new(Effect::Tonemap(Tonemap::Aces)),
+        PostEffect::new(Effect::Fxaa),
+        PostEffect::new(Effect::Vignette {
+          intensity: 0.25,
+          radius: 0.75,
+          smoothness: 0.45,
+        }),
+      ],
+      pipelines,
+      sampler,
+      params: Vec::new(),
+      used_params: 0,
+      frame,
+      lut_sets: HashMap::new(),
+    })
+  }
+
+  pub fn effects(&self) -> &[PostEffect] {
+    &self.effects
+  }
+
+  /// The effects in the order they run, they can be added, removed, toggled and reordered.
+  pub fn effects_mut(&mut self) -> &mut Vec<PostEffect> {
+    &mut self.effects
+  }
+
+  /// Adds the passes of the enabled effects, reading `input` and drawing the result into
+  /// `output`. The image is only copied if no effect is enabled.
+  ///
+  /// The passes are added where the graph is, a pass added to `output` after them draws
+  /// over the result.
+  pub fn add_passes(
+    &mut self,
+    graphics: &mut GraphicsContext,
+    graph: &mut RenderGraph,
+    input: GraphTarget,
+    output: GraphTarget,
+  ) -> Result<(), GraphicsError> {
+    // the previous frames were submitted, their parameters can be written again
+    if self.frame != graphics.frame_count() {
+      self.frame = graphics.frame_count();
+      self.used_params = 0;
+    }
+    self.release_luts(graphics.device());
+
+    let effects: Vec<Effect> = self
+      .effects
+      .iter()
+      .filter(|effect| effect.enabled)
+      .map(|effect| effect.effect)
+      .collect();
+
+    if effects.is_empty() {
+      let copy = PostPass::new("copy", self.pipelines.copy);
+      self.add_pass(graph, input, output, copy);
+      return Ok(());
+    }
+
+    let mut source = input;
+    for (index, effect) in effects.iter().enumerate() {
+      let target = if index + 1 == effects.len() {
+        output
+      } else {
+        graph.create_target_with_format(effect.name(), TargetSize::Screen, TargetFormat::Hdr)
+      };
+
+      let pipelines = self.pipelines;
+      let pass = match *effect {
+        Effect::Exposure { ev } => {
+          let params = self.params(graphics.device(), [ev.exp2(), 0.0, 0.0, 0.0])?;
+          PostPass::new("exposure", pipelines.exposure).params(params)
+        }
+        Effect::Bloom {
+          threshold,
+          intensity,
+          radius,
+        } => {
+          let device = graphics.device();
+          let bright_params = self.params(device, [threshold, 0.0, 0.0, 0.0])?;
+          let blur_params = self.params(device, [radius, 0.0, 0.0, 0.0])?;
+          let bloom_params = self.params(device, [intensity, 0.0, 0.0, 0.0])?;
+
+          // blurred at half resolution, it's smooth anyway
+          let half = TargetSize::Scaled(0.5);
+          let passes = [
+            PostPass::new("bloom bright", pipelines.bright).params(bright_params),
+            PostPass::new("bloom blur h", pipelines.blur_h).params(blur_params),
+            PostPass::new("bloom blur v", pipelines.blur_v).params(blur_params),
+          ];
+          let mut blurred = source;
+          for pass in passes {
+            let next = graph.create_target_with_format(pass.name, half, TargetFormat::Hdr);
+            self.add_pass(graph, blurred, next, pass);
+            blurred = next;
+          }
+
+          PostPass::new("bloom", pipelines.bloom)
+            .params(bloom_params)
+            .extra(Extra::Target(blurred))
+        }
+        Effect::Tonemap(Tonemap::Aces) => PostPass::new("aces", pipelines.aces),
+        Effect::Tonemap(Tonemap::Reinhard) => PostPass::new("reinhard", pipelines.reinhard),
+        Effect::Fxaa => PostPass::new("fxaa", pipelines.fxaa),
+        Effect::Vignette {
+          intensity,
+          radius,
+          smoothness,
+        } => {
+          let params = self.params(graphics.device(), [intensity, radius, smoothness, 0.0])?;
+          PostPass::new("vignette", pipelines.vignette).params(params)
+        }
+        Effect::ColorGrading { lut, intensity } => {
+          let device = graphics.device();
+          let params = self.params(device, [intensity, 0.0, 0.0, 0.0])?;
+          let lut = self.lut_set(device, lut)?;
+          PostPass::new("color grading", pipelines.grading)
+            .params(params)
+            .extra(Extra::Set(lut))
+        }
+      };
+
+      self.add_pass(graph, source, target, pass);
+      source = target;
+    }
+
+    Ok(())
+  }
+
+  fn add_pass(
+    &self,
+    graph: &mut RenderGraph,
+    source: GraphTarget,
+    target: GraphTarget,
+    pass: PostPass,
+  ) {
+    let sampler = self.sampler;
+    let mut builder = graph.add_pass(pass.name, target).read(source);
+    if let Extra::Target(extra) = pass.extra {
+      builder = builder.read(extra);
+    }
+
+    builder.record(move |graphics, resources| {
+      let source = resources.texture_set(graphics, source, sampler);
+      let extra = match pass.extra {
+        Extra::None => Ok(None),
+        Extra::Target(extra) => resources.texture_set(graphics, extra, sampler).map(Some),
+        Extra::Set(set) => Ok(Some(set)),
+      };
+      let (source, extra) = match (source, extra) {
+        (Ok(source), Ok(extra)) => (source, extra),
+        (Err(e), _) | (_, Err(e)) => {
+          error!("Skipping the {} pass: {}", pass.name, e);
+          return;
+        }
+      };
+
+      let encoder = graphics.encoder();
+      encoder.set_pipeline(pass.pipeline);
+      encoder.set_descriptor_set(0, source);
+      if let Some(params) = pass.params {
+        encoder.set_descriptor_set(1, params);
+      }
+      if let Some(extra) = extra {
+        encoder.set_descriptor_set(2, extra);
+      }
+      encoder.draw(0..3, 0..1);
+    });
+  }
+
+  /// A set with `params` for a pass of this frame.
+  fn params(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    params: Params,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    if self.used_params == self.params.len() {
+      let buffer = device.create_buffer(
+        &BufferDesc {
+          size: std::mem::size_of::<Params>() as u64,
+          usage: BufferUsage::Uniform,
+        },
+        None,
+      )?;
+      let set = device.create_descriptor_set(&DescriptorSetDesc::uniform_buffers(&[buffer]))?;
+      self.params.push((buffer, set));
+    }
+
+    let (buffer, set) = self.params[self.used_params];
+    device.write_buffer(buffer, 0, bytemuck::bytes_of(&params))?;
+    self.used_params += 1;
+    Ok(set)
+  }
+
+  fn lut_set(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    lut: Texture,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    if let Some(set) = self.lut_sets.get(&lut.handle()) {
+      return Ok(*set);
+    }
+
+    let desc = DescriptorSetDesc::texture(lut.handle(), self.sampler);
+    let set = device.create_descriptor_set(&desc)?;
+    self.lut_sets.insert(lut.handle(), set);
+    Ok(set)
+  }
+
+  /// Destroys the sets of the luts no effect uses anymore.
+  fn release_luts(&mut self, device: &mut dyn GraphicsDevice) {
+    let effects = &self.effects;
+    self.lut_sets.retain(|texture, set| {
+      let used = effects.iter().any(|effect| match effect.effect {
+        Effect::ColorGrading { lut, .. } => lut.handle() == *texture,
+        _ => false,
+      });
+      if !used {
+        device.destroy_descriptor_set(*set);
+      }
+      used
+    });
+  }
+
+  pub fn destroy(self, device: &mut dyn GraphicsDevice) {
+    for set in self.lut_sets.into_values() {
+      device.destroy_descriptor_set(set);
+    }
+    for (buffer, set) in self.params {
+      device.destroy_descriptor_set(set);
+      device.destroy_buffer(buffer);
+    }
+    for pipeline in self.pipelines.all() {
+      device.destroy_pipeline(pipeline);
+    }
+    device.destroy_sampler(self.sampler);
+  }
+}
+
+/// A lut for `Effect::ColorGrading` that leaves the colors as they are, `size` texels
+/// per channel. It's a starting point to grade in an image editor.
+pub fn neutral_lut(size: u32) -> RgbaImage {
+  let size = size.max(2);
+  let level = |value: u32| (value * 255 / (size - 1)) as u8;
+  RgbaImage::from_fn(size * size, size, |x, y| {
+    image::Rgba([level(x % size), level(y), level(x / size), 255])
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::null::DrawCall;
+  use crate::backend::{GegBackend, GraphicsOptions};
+  use crate::render_graph::TargetPool;
+  use crate::traits::PassTarget;
+
+  fn graphics() -> GraphicsContext {
+    GraphicsContext::new(GegBackend::Null, None, [64, 64], GraphicsOptions::default())
+  }
+
+  /// the draws of a frame post processing an empty hdr scene onto the screen
+  fn render(
+    post: &mut PostStack,
+    graphics: &mut GraphicsContext,
+    pool: &mut TargetPool,
+  ) -> Vec<DrawCall> {
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let scene = graph.create_target_with_format("scene", TargetSize::Screen, TargetFormat::Hdr);
+    graph.add_pass("scene", scene);
+    post.add_passes(graphics, &mut graph, scene, screen).unwrap();
+    graph.execute(graphics, pool).unwrap();
+    graphics.update();
+    graphics.log().unwrap().last_frame_draws()
+  }
+
+  fn pipelines(draws: &[DrawCall]) -> Vec<PipelineHandle> {
+    draws.iter().filter_map(|draw| draw.pipeline).collect()
+  }
+
+  #[test]
+  fn the_image_is_copied_without_effects() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+    let mut post = PostStack::new(&mut graphics).unwrap();
+    for effect in post.effects_mut() {
+      effect.enabled = false;
+    }
+
+    let draws = render(&mut post, &mut graphics, &mut pool);
+    assert_eq!(pipelines(&draws), [post.pipelines.copy]);
+    assert_eq!(draws[0].target, PassTarget::Screen);
+    assert_eq!(pool.len(), 1);
+  }
+
+  #[test]
+  fn the_passes_follow_the_enabled_effects() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+    let mut post = PostStack::new(&mut graphics).unwrap();
+    let p = post.pipelines;
+
+    let draws = render(&mut post, &mut graphics, &mut pool);
+    let chain = [
+      p.exposure, p.bright, p.blur_h, p.blur_v, p.bloom, p.aces, p.fxaa, p.vignette,
+    ];
+    assert_eq!(pipelines(&draws), chain);
+    let (last, effects) = draws.split_last().unwrap();
+    assert_eq!(last.target, PassTarget::Screen);
+    assert!(effects
+      .iter()
+      .all(|draw| matches!(draw.target, PassTarget::Offscreen(_))));
+
+    // without bloom, fxaa before tonemapping
+    post.effects_mut()[1].enabled = false;
+    post.effects_mut().swap(2, 3);
+    let draws = render(&mut post, &mut graphics, &mut pool);
+    assert_eq!(pipelines(&draws), [p.exposure, p.fxaa, p.aces, p.vignette]);
+    assert_eq!(draws[3].target, PassTarget::Screen);
+
+    // the settings of a disabled effect are kept
+    post.effects_mut()[1].enabled = true;
+    let bloom = post.effects()[1].effect;
+    assert!(matches!(bloom, Effect::Bloom { intensity, .. } if intensity == 0.6));
+    assert_eq!(pipelines(&render(&mut post, &mut graphics, &mut pool)).len(), 8);
+  }
+}
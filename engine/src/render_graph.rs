@@ -0,0 +1,846 @@
+use std::collections::HashMap;
+
+use spdlog::prelude::*;
+
+use crate::backend::GraphicsContext;
+use crate::traits::{
+  BufferHandle, CommandEncoder, DescriptorSetDesc, DescriptorSetHandle, GraphicsDevice,
+  GraphicsError, PassTarget, RenderTargetDesc, RenderTargetHandle, SamplerHandle, TargetFormat,
+  TextureHandle,
+};
+
+/// A target of a `RenderGraph`, only valid in the graph that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GraphTarget(usize);
+
+/// The size of a target created by the graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetSize {
+  /// The size of the screen.
+  Screen,
+  /// The size of the screen times a factor, e.g. 0.5 for half resolution.
+  Scaled(f32),
+  Fixed { width: u32, height: u32 },
+}
+
+impl TargetSize {
+  fn resolve(&self, [width, height]: [u32; 2], format: TargetFormat) -> RenderTargetDesc {
+    let (width, height) = match *self {
+      TargetSize::Screen => (width, height),
+      TargetSize::Scaled(factor) => (
+        (width as f32 * factor).round() as u32,
+        (height as f32 * factor).round() as u32,
+      ),
+      TargetSize::Fixed { width, height } => (width, height),
+    };
+
+    RenderTargetDesc {
+      width: width.max(1),
+      height: height.max(1),
+      format,
+    }
+  }
+}
+
+enum TargetKind {
+  Screen,
+  /// Created by the graph for the frame, it can share its memory with the other targets
+  /// created by the graph that aren't used at the same time.
+  Transient(TargetSize, TargetFormat),
+  /// Created outside of the graph. Like every target it's cleared by the first pass drawing
+  /// into it, what was drawn stays readable until then, e.g. by the next frame.
+  Imported(RenderTargetHandle),
+}
+
+struct Target {
+  name: String,
+  kind: TargetKind,
+}
+
+type RecordPass<'a> = Box<dyn FnOnce(&mut GraphicsContext, &mut PassResources) + 'a>;
+
+struct Pass<'a> {
+  name: String,
+  target: usize,
+  reads: Vec<usize>,
+  buffer_reads: Vec<BufferHandle>,
+  buffer_writes: Vec<BufferHandle>,
+  reads_depth: bool,
+  writes_depth: bool,
+  clear_color: [f32; 4],
+  record: Option<RecordPass<'a>>,
+}
+
+/// The passes writing a single target, they are recorded into the same backend pass.
+struct PassGroup {
+  target: usize,
+  passes: Vec<usize>,
+}
+
+/// The passes of a frame and the targets they draw into and sample.
+///
+/// Passes declare the color target they draw into, the targets they sample, the buffers
+/// they read and write and whether they use the depth of their target. The graph orders
+/// them, skips the ones nothing on screen depends on and creates the targets they draw into.
+/// A graph is built every frame and recorded with `execute`, the targets it creates are kept
+/// in a `TargetPool` between frames.
+///
+/// The passes drawing into the same target are recorded into one pass of the device, in
+/// the order they were added, so the target is only cleared once. The backends take care
+/// of the barriers between passes.
+///
+/// A pass draws into a single color target. Its depth attachment belongs to the target and
+/// is only kept while the passes drawing into the target are recorded, so it can't be
+/// sampled and only the passes added after the one writing it can read it.
+///
+/// Buffers are written by the device before the frame is submitted, so a buffer holds what
+/// was last written to it during the whole frame. Passes writing a buffer are recorded before
+/// the ones reading it and a buffer can only be written by one pass of a graph.
+pub struct RenderGraph<'a> {
+  targets: Vec<Target>,
+  passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+  pub fn new() -> Self {
+    Self {
+      targets: vec![Target {
+        name: "screen".to_string(),
+        kind: TargetKind::Screen,
+      }],
+      passes: Vec::new(),
+    }
+  }
+
+  /// The window, or the offscreen image when running headless.
+  pub fn screen(&self) -> GraphTarget {
+    GraphTarget(0)
+  }
+
+  /// A target created for this frame, it can't be read before a pass draws into it.
+  pub fn create_target(&mut self, name: &str, size: TargetSize) -> GraphTarget {
+    self.create_target_with_format(name, size, TargetFormat::Screen)
+  }
+
+  /// Like `create_target`, `TargetFormat::Hdr` keeps colors brighter than 1.
+  pub fn create_target_with_format(
+    &mut self,
+    name: &str,
+    size: TargetSize,
+    format: TargetFormat,
+  ) -> GraphTarget {
+    self.add_target(name, TargetKind::Transient(size, format))
+  }
+
+  /// A render target created with the device, e.g. one that is read in the next frame.
+  ///
+  /// Passes drawing into it clear it, to read the previous frame draw into another
+  /// imported target and swap the two every frame.
+  pub fn import_target(&mut self, name: &str, target: RenderTargetHandle) -> GraphTarget {
+    self.add_target(name, TargetKind::Imported(target))
+  }
+
+  fn add_target(&mut self, name: &str, kind: TargetKind) -> GraphTarget {
+    self.targets.push(Target {
+      name: name.to_string(),
+      kind,
+    });
+    GraphTarget(self.targets.len() - 1)
+  }
+
+  /// Adds a pass drawing into `target`, see `PassBuilder` for what it reads and draws.
+  pub fn add_pass(&mut self, name: &str, target: GraphTarget) -> PassBuilder<'_, 'a> {
+    self.passes.push(Pass {
+      name: name.to_string(),
+      target: target.0,
+      reads: Vec::new(),
+      buffer_reads: Vec::new(),
+      buffer_writes: Vec::new(),
+      reads_depth: false,
+      writes_depth: false,
+      clear_color: [0.0, 0.0, 0.0, 1.0],
+      record: None,
+    });
+    PassBuilder {
+      pass: self.passes.last_mut().unwrap(),
+    }
+  }
+
+  /// Orders the passes and records them into the frame of `graphics`.
+  ///
+  /// Nothing is recorded if the graph can't be ordered, i.e. two targets are drawn with
+  /// each other, a target is read before anything draws into it, a buffer is written by
+  /// two passes or a pass reads a depth or buffer that is written after it.
+  pub fn execute(
+    self,
+    graphics: &mut GraphicsContext,
+    pool: &mut TargetPool,
+  ) -> Result<(), GraphicsError> {
+    let live = self.live_targets();
+    let groups = self.order(&live)?;
+
+    let frame = graphics.frame_count();
+    pool.begin_frame(frame, graphics.device());
+    let resolved = self.allocate(graphics, pool, &groups)?;
+
+    let RenderGraph { targets, passes } = self;
+    let mut passes: Vec<Option<Pass>> = passes.into_iter().map(Some).collect();
+
+    for group in &groups {
+      let target = match targets[group.target].kind {
+        TargetKind::Screen => PassTarget::Screen,
+        _ => PassTarget::Offscreen(resolved[group.target].unwrap().0),
+      };
+      let clear_color = passes[group.passes[0]].as_ref().unwrap().clear_color;
+
+      graphics.encoder().begin_pass(target, clear_color);
+      for &index in &group.passes {
+        let pass = passes[index].take().unwrap();
+        let mut resources = PassResources {
+          targets: &targets,
+          resolved: &resolved,
+          reads: &pass.reads,
+          pool,
+        };
+        if let Some(record) = pass.record {
+          record(graphics, &mut resources);
+        }
+      }
+      graphics.encoder().end_pass();
+    }
+
+    Ok(())
+  }
+
+  /// The targets the screen or an imported target depends on, through the targets and
+  /// buffers their passes read. The passes drawing into the others are skipped.
+  fn live_targets(&self) -> Vec<bool> {
+    let mut live: Vec<bool> = self
+      .targets
+      .iter()
+      .map(|target| !matches!(target.kind, TargetKind::Transient(..)))
+      .collect();
+
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for pass in &self.passes {
+        if !live[pass.target] {
+          continue;
+        }
+        let writers = pass.buffer_reads.iter().flat_map(|buffer| self.writers(*buffer));
+        for read in pass.reads.iter().copied().chain(writers.map(|writer| writer.target)) {
+          if !live[read] {
+            live[read] = true;
+            changed = true;
+          }
+        }
+      }
+    }
+
+    live
+  }
+
+  /// The passes writing `buffer`, live or not.
+  fn writers(&self, buffer: BufferHandle) -> impl Iterator<Item = &Pass<'a>> {
+    self
+      .passes
+      .iter()
+      .filter(move |pass| pass.buffer_writes.contains(&buffer))
+  }
+
+  /// Groups the live passes by target and sorts the groups so every target is drawn
+  /// before the passes reading it. Ties keep the order the passes were added in.
+  fn order(&self, live: &[bool]) -> Result<Vec<PassGroup>, GraphicsError> {
+    let mut groups: Vec<PassGroup> = Vec::new();
+    for (index, pass) in self.passes.iter().enumerate() {
+      if !live[pass.target] {
+        debug!("Skipping the {} pass, nothing reads {}", pass.name, self.target_name(pass.target));
+        continue;
+      }
+
+      match groups.iter_mut().find(|group| group.target == pass.target) {
+        Some(group) => group.passes.push(index),
+        None => groups.push(PassGroup {
+          target: pass.target,
+          passes: vec![index],
+        }),
+      }
+    }
+
+    // the group of every live pass and the live pass writing each buffer
+    let mut group_of = vec![None; self.passes.len()];
+    let mut writers: HashMap<BufferHandle, usize> = HashMap::new();
+    for (position, group) in groups.iter().enumerate() {
+      for &index in &group.passes {
+        group_of[index] = Some(position);
+        for &buffer in &self.passes[index].buffer_writes {
+          if let Some(other) = writers.insert(buffer, index).filter(|&other| other != index) {
+            return Err(GraphicsError::InvalidUsage(format!(
+              "the {} and {} passes both write {:?}",
+              self.passes[other].name, self.passes[index].name, buffer
+            )));
+          }
+        }
+      }
+    }
+
+    // the groups each group has to wait for
+    let mut dependencies = Vec::with_capacity(groups.len());
+    for (position, group) in groups.iter().enumerate() {
+      let mut waits_for = Vec::new();
+      let mut depth_written = false;
+      for &index in &group.passes {
+        let pass = &self.passes[index];
+        if pass.reads_depth && !depth_written {
+          return Err(GraphicsError::InvalidUsage(format!(
+            "the {} pass reads the depth of {} but no pass before it writes it",
+            pass.name,
+            self.target_name(group.target)
+          )));
+        }
+        depth_written |= pass.writes_depth;
+
+        for buffer in &pass.buffer_reads {
+          // buffers nothing writes in this graph keep what was written to them outside of it
+          let writer = match writers.get(buffer) {
+            Some(&writer) => writer,
+            None => continue,
+          };
+          match group_of[writer] {
+            Some(other) if other != position => waits_for.push(other),
+            // the passes of a group are recorded in the order they were added
+            _ if writer > index => {
+              return Err(GraphicsError::InvalidUsage(format!(
+                "the {} pass reads {:?} before the {} pass writes it",
+                pass.name, buffer, self.passes[writer].name
+              )));
+            }
+            _ => (),
+          }
+        }
+
+        for &read in &self.passes[index].reads {
+          if read == group.target {
+            return Err(GraphicsError::InvalidUsage(format!(
+              "the {} pass reads {} while drawing into it",
+              self.passes[index].name,
+              self.target_name(read)
+            )));
+          }
+
+          match groups.iter().position(|other| other.target == read) {
+            Some(other) => waits_for.push(other),
+            None if matches!(self.targets[read].kind, TargetKind::Transient(..)) => {
+              return Err(GraphicsError::InvalidUsage(format!(
+                "the {} pass reads {} but no pass draws into it",
+                self.passes[index].name,
+                self.target_name(read)
+              )));
+            }
+            // nothing draws into the imported target this frame, it keeps the last content
+            None => (),
+          }
+        }
+      }
+      dependencies.push(waits_for);
+    }
+
+    let mut ordered = Vec::with_capacity(groups.len());
+    let mut done = vec![false; groups.len()];
+    while ordered.len() < groups.len() {
+      let next = (0..groups.len())
+        .find(|&group| !done[group] && dependencies[group].iter().all(|&other| done[other]));
+
+      match next {
+        Some(group) => {
+          done[group] = true;
+          ordered.push(group);
+        }
+        None => {
+          let cycle: Vec<&str> = (0..groups.len())
+            .filter(|&group| !done[group])
+            .map(|group| self.target_name(groups[group].target))
+            .collect();
+          return Err(GraphicsError::InvalidUsage(format!(
+            "the passes drawing into {} wait on each other",
+            cycle.join(", ")
+          )));
+        }
+      }
+    }
+
+    let mut groups: Vec<Option<PassGroup>> = groups.into_iter().map(Some).collect();
+    Ok(
+      ordered
+        .into_iter()
+        .map(|group| groups[group].take().unwrap())
+        .collect(),
+    )
+  }
+
+  /// Finds the render target and texture of every target drawn or read this frame.
+  /// Transient targets share a pooled target with the ones that were last read before
+  /// they are drawn.
+  fn allocate(
+    &self,
+    graphics: &mut GraphicsContext,
+    pool: &mut TargetPool,
+    groups: &[PassGroup],
+  ) -> Result<Vec<Option<(RenderTargetHandle, TextureHandle)>>, GraphicsError> {
+    // the last group reading each target
+    let mut last_read = vec![0; self.targets.len()];
+    for (position, group) in groups.iter().enumerate() {
+      for &index in &group.passes {
+        for &read in &self.passes[index].reads {
+          last_read[read] = position;
+        }
+      }
+    }
+
+    let screen_size = graphics.device().surface_size();
+    let mut resolved = vec![None; self.targets.len()];
+    // the pooled targets used this frame and the last group reading them
+    let mut busy_until: HashMap<usize, usize> = HashMap::new();
+
+    for (position, group) in groups.iter().enumerate() {
+      resolved[group.target] = match &self.targets[group.target].kind {
+        TargetKind::Screen => None,
+        TargetKind::Imported(target) => Some(imported(graphics.device(), *target)?),
+        TargetKind::Transient(size, format) => {
+          let desc = size.resolve(screen_size, *format);
+          let entry = pool.acquire(graphics.device(), &desc, |entry| {
+            match busy_until.get(&entry) {
+              Some(&until) => until < position,
+              None => true,
+            }
+          })?;
+          busy_until.insert(entry, last_read[group.target].max(position));
+          let pooled = &pool.targets[entry];
+          Some((pooled.target, pooled.texture))
+        }
+      };
+    }
+
+    // imported targets that are only read
+    for (index, target) in self.targets.iter().enumerate() {
+      if let (TargetKind::Imported(handle), None) = (&target.kind, resolved[index]) {
+        resolved[index] = Some(imported(graphics.device(), *handle)?);
+      }
+    }
+
+    Ok(resolved)
+  }
+
+  fn target_name(&self, target: usize) -> &str {
+    &self.targets[target].name
+  }
+}
+
+impl<'a> Default for RenderGraph<'a> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn imported(
+  device: &mut dyn GraphicsDevice,
+  target: RenderTargetHandle,
+) -> Result<(RenderTargetHandle, TextureHandle), GraphicsError> {
+  let texture = device
+    .render_target_texture(target)
+    .ok_or(GraphicsError::InvalidHandle)?;
+  Ok((target, texture))
+}
+
+/// Declares what a pass reads and writes and records its draws.
+pub struct PassBuilder<'g, 'a> {
+  pass: &'g mut Pass<'a>,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+  /// The pass samples `target`, it's drawn before the pass.
+  pub fn read(self, target: GraphTarget) -> Self {
+    self.pass.reads.push(target.0);
+    self
+  }
+
+  /// The pass reads `buffer`, the pass writing it is recorded before this one.
+  pub fn read_buffer(self, buffer: BufferHandle) -> Self {
+    self.pass.buffer_reads.push(buffer);
+    self
+  }
+
+  /// The pass writes `buffer` while it's recorded, it's kept if a pass that isn't skipped
+  /// reads the buffer.
+  pub fn write_buffer(self, buffer: BufferHandle) -> Self {
+    self.pass.buffer_writes.push(buffer);
+    self
+  }
+
+  /// The pass tests against the depth written by the passes drawing into the same target
+  /// before it.
+  pub fn read_depth(self) -> Self {
+    self.pass.reads_depth = true;
+    self
+  }
+
+  /// The pass writes the depth of its target, for the passes after it to read.
+  pub fn write_depth(self) -> Self {
+    self.pass.writes_depth = true;
+    self
+  }
+
+  /// The color the target is cleared to, only the first pass drawing into a target
+  /// clears it. Black by default.
+  pub fn clear_color(self, color: [f32; 4]) -> Self {
+    self.pass.clear_color = color;
+    self
+  }
+
+  /// Records the draws of the pass, it's called inside the pass when the graph is executed.
+  pub fn record(self, record: impl FnOnce(&mut GraphicsContext, &mut PassResources) + 'a) {
+    self.pass.record = Some(Box::new(record));
+  }
+}
+
+/// The targets a pass has access to while it's recorded.
+pub struct PassResources<'p> {
+  targets: &'p [Target],
+  resolved: &'p [Option<(RenderTargetHandle, TextureHandle)>],
+  reads: &'p [usize],
+  pool: &'p mut TargetPool,
+}
+
+impl<'p> PassResources<'p> {
+  /// The texture of a target the pass reads, `None` for the screen or a target the pass
+  /// didn't declare with `PassBuilder::read`.
+  pub fn texture(&self, target: GraphTarget) -> Option<TextureHandle> {
+    if !self.reads.contains(&target.0) {
+      warn!(
+        "Reading {} from a pass that doesn't declare it",
+        self.targets[target.0].name
+      );
+      return None;
+    }
+
+    self.resolved[target.0].map(|(_, texture)| texture)
+  }
+
+  /// A descriptor set matching `DescriptorSetLayout::texture` with the texture of `target`,
+  /// it's kept in the pool as long as the target is read every frame.
+  pub fn texture_set(
+    &mut self,
+    graphics: &mut GraphicsContext,
+    target: GraphTarget,
+    sampler: SamplerHandle,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    let texture = self.texture(target).ok_or(GraphicsError::InvalidUsage(format!(
+      "{} can't be sampled by this pass",
+      self.targets[target.0].name
+    )))?;
+    self.pool.texture_set(graphics.device(), texture, sampler)
+  }
+}
+
+struct PooledTarget {
+  desc: RenderTargetDesc,
+  target: RenderTargetHandle,
+  texture: TextureHandle,
+  used: bool,
+}
+
+/// The targets created by the render graphs, kept between frames.
+///
+/// A pooled target that isn't used for a whole frame is destroyed when a graph is executed
+/// in the next frame, after the frame that last used it was submitted. Graphs executed in
+/// the same frame can share a pool.
+#[derive(Default)]
+pub struct TargetPool {
+  targets: Vec<PooledTarget>,
+  texture_sets: HashMap<(TextureHandle, SamplerHandle), (DescriptorSetHandle, bool)>,
+  // the frame the pool was last used in
+  frame: Option<u64>,
+}
+
+impl TargetPool {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The number of render targets created by the pool.
+  pub fn len(&self) -> usize {
+    self.targets.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.targets.is_empty()
+  }
+
+  /// Destroys what the last frame didn't use, once per frame.
+  fn begin_frame(&mut self, frame: u64, device: &mut dyn GraphicsDevice) {
+    if self.frame.replace(frame) == Some(frame) {
+      return;
+    }
+
+    self.texture_sets.retain(|_, (set, used)| {
+      if !*used {
+        device.destroy_descriptor_set(*set);
+      }
+      std::mem::replace(used, false)
+    });
+
+    self.targets.retain_mut(|pooled| {
+      if !pooled.used {
+        debug!("Destroying a graph target {:?}", pooled.desc);
+        device.destroy_render_target(pooled.target);
+      }
+      std::mem::replace(&mut pooled.used, false)
+    });
+  }
+
+  /// A target of `desc` for which `free` is true, created if there is none.
+  fn acquire(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    desc: &RenderTargetDesc,
+    free: impl Fn(usize) -> bool,
+  ) -> Result<usize, GraphicsError> {
+    let found = (0..self.targets.len())
+      .find(|&entry| self.targets[entry].desc == *desc && free(entry));
+
+    let entry = match found {
+      Some(entry) => entry,
+      None => {
+        let target = device.create_render_target(desc)?;
+        let texture = device
+          .render_target_texture(target)
+          .ok_or(GraphicsError::InvalidHandle)?;
+        debug!("Created a graph target {:?}", desc);
+        self.targets.push(PooledTarget {
+          desc: desc.clone(),
+          target,
+          texture,
+          used: false,
+        });
+        self.targets.len() - 1
+      }
+    };
+
+    self.targets[entry].used = true;
+    Ok(entry)
+  }
+
+  fn texture_set(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    texture: TextureHandle,
+    sampler: SamplerHandle,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    if let Some((set, used)) = self.texture_sets.get_mut(&(texture, sampler)) {
+      *used = true;
+      return Ok(*set);
+    }
+
+    let set = device.create_descriptor_set(&DescriptorSetDesc::texture(texture, sampler))?;
+    self.texture_sets.insert((texture, sampler), (set, true));
+    Ok(set)
+  }
+
+  pub fn destroy(self, device: &mut dyn GraphicsDevice) {
+    for (set, _) in self.texture_sets.into_values() {
+      device.destroy_descriptor_set(set);
+    }
+    for pooled in self.targets {
+      device.destroy_render_target(pooled.target);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::{GegBackend, GraphicsOptions};
+  use crate::traits::{BufferDesc, BufferUsage, RenderCommand};
+  use std::cell::RefCell;
+
+  fn graphics() -> GraphicsContext {
+    GraphicsContext::new(GegBackend::Null, None, [64, 64], GraphicsOptions::default())
+  }
+
+  /// the targets of the passes in the last submitted frame
+  fn pass_targets(graphics: &GraphicsContext) -> Vec<PassTarget> {
+    let frame = graphics.log().unwrap().last_frame().unwrap();
+    frame
+      .into_iter()
+      .filter_map(|command| match command {
+        RenderCommand::BeginPass { target, .. } => Some(target),
+        _ => None,
+      })
+      .collect()
+  }
+
+  #[test]
+  fn targets_are_drawn_before_they_are_read() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+    let recorded = RefCell::new(Vec::new());
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let a = graph.create_target("a", TargetSize::Screen);
+    let b = graph.create_target("b", TargetSize::Scaled(0.5));
+    let unused = graph.create_target("unused", TargetSize::Screen);
+    let recorded = &recorded;
+    let record = |name: &'static str| {
+      move |_: &mut GraphicsContext, _: &mut PassResources| recorded.borrow_mut().push(name)
+    };
+    graph.add_pass("composite", screen).read(b).read(a).record(record("composite"));
+    graph.add_pass("unused", unused).read(a).record(record("unused"));
+    graph.add_pass("blur", b).read(a).record(record("blur"));
+    graph.add_pass("scene", a).record(record("scene"));
+    graph.add_pass("overlay", screen).record(record("overlay"));
+    graph.execute(&mut graphics, &mut pool).unwrap();
+
+    // the passes drawing into the screen are recorded together, in the order they were added
+    assert_eq!(*recorded.borrow(), ["scene", "blur", "composite", "overlay"]);
+    graphics.update();
+    let targets = pass_targets(&graphics);
+    assert_eq!(targets.len(), 3);
+    assert_eq!(targets[2], PassTarget::Screen);
+    assert_eq!(pool.len(), 2);
+  }
+
+  #[test]
+  fn buffers_are_written_before_they_are_read() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+    let recorded = RefCell::new(Vec::new());
+    let lights = graphics
+      .device()
+      .create_buffer(
+        &BufferDesc {
+          size: 64,
+          usage: BufferUsage::Uniform,
+        },
+        None,
+      )
+      .unwrap();
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let shadows = graph.create_target("shadows", TargetSize::Fixed { width: 32, height: 32 });
+    let unused = graph.create_target("unused", TargetSize::Screen);
+    let recorded = &recorded;
+    let record = |name: &'static str| {
+      move |_: &mut GraphicsContext, _: &mut PassResources| recorded.borrow_mut().push(name)
+    };
+    graph.add_pass("scene", screen).write_depth().read_buffer(lights).record(record("scene"));
+    graph.add_pass("lines", screen).read_depth().record(record("lines"));
+    graph.add_pass("unused", unused).write_buffer(BufferHandle(u64::MAX)).record(record("unused"));
+    // nothing samples the shadows, the pass is kept for the buffer it writes
+    graph.add_pass("shadows", shadows).write_buffer(lights).record(record("shadows"));
+    graph.execute(&mut graphics, &mut pool).unwrap();
+
+    assert_eq!(*recorded.borrow(), ["shadows", "scene", "lines"]);
+    assert_eq!(pool.len(), 1);
+  }
+
+  #[test]
+  fn buffers_and_depth_read_before_they_are_written_are_errors() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+    let buffer = BufferHandle(0);
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let a = graph.create_target("a", TargetSize::Screen);
+    graph.add_pass("a", a).write_buffer(buffer);
+    graph.add_pass("b", screen).write_buffer(buffer);
+    graph.add_pass("screen", screen).read_buffer(buffer);
+    assert!(graph.execute(&mut graphics, &mut pool).is_err());
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    graph.add_pass("reads", screen).read_buffer(buffer);
+    graph.add_pass("writes", screen).write_buffer(buffer);
+    assert!(graph.execute(&mut graphics, &mut pool).is_err());
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    graph.add_pass("lines", screen).read_depth();
+    graph.add_pass("scene", screen).write_depth();
+    assert!(graph.execute(&mut graphics, &mut pool).is_err());
+
+    // the depth of another target doesn't count
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let scene = graph.create_target("scene", TargetSize::Screen);
+    graph.add_pass("scene", scene).write_depth();
+    graph.add_pass("lines", screen).read(scene).read_depth();
+    assert!(graph.execute(&mut graphics, &mut pool).is_err());
+  }
+
+  #[test]
+  fn cycles_are_errors() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+    let recorded = RefCell::new(0);
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let a = graph.create_target("a", TargetSize::Screen);
+    let b = graph.create_target("b", TargetSize::Screen);
+    graph.add_pass("a", a).read(b).record(|_, _| *recorded.borrow_mut() += 1);
+    graph.add_pass("b", b).read(a).record(|_, _| *recorded.borrow_mut() += 1);
+    graph.add_pass("screen", screen).read(a).record(|_, _| *recorded.borrow_mut() += 1);
+    assert!(matches!(
+      graph.execute(&mut graphics, &mut pool),
+      Err(GraphicsError::InvalidUsage(_))
+    ));
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let a = graph.create_target("a", TargetSize::Screen);
+    graph.add_pass("a", a).read(a);
+    graph.add_pass("screen", screen).read(a);
+    assert!(graph.execute(&mut graphics, &mut pool).is_err());
+
+    let mut graph = RenderGraph::new();
+    let screen = graph.screen();
+    let never_drawn = graph.create_target("never drawn", TargetSize::Screen);
+    graph.add_pass("screen", screen).read(never_drawn);
+    assert!(graph.execute(&mut graphics, &mut pool).is_err());
+
+    assert_eq!(*recorded.borrow(), 0);
+    assert!(pool.is_empty());
+  }
+
+  #[test]
+  fn targets_that_are_done_with_share_a_pooled_target() {
+    let mut graphics = graphics();
+    let mut pool = TargetPool::new();
+
+    for _ in 0..2 {
+      let mut graph = RenderGraph::new();
+      let screen = graph.screen();
+      let a = graph.create_target("a", TargetSize::Screen);
+      let b = graph.create_target("b", TargetSize::Screen);
+      let c = graph.create_target("c", TargetSize::Screen);
+      let half = graph.create_target("half", TargetSize::Scaled(0.5));
+      graph.add_pass("a", a);
+      graph.add_pass("b", b).read(a);
+      graph.add_pass("c", c).read(b);
+      graph.add_pass("half", half).read(c);
+      graph.add_pass("screen", screen).read(half);
+      graph.execute(&mut graphics, &mut pool).unwrap();
+      graphics.update();
+
+      // c is drawn once a was read, b is still read while c is drawn
+      let targets = pass_targets(&graphics);
+      assert_eq!(targets[0], targets[2]);
+      assert_ne!(targets[1], targets[2]);
+      assert_eq!(pool.len(), 3);
+    }
+  }
+}
@@ -0,0 +1,193 @@
+use crate::events::GegEvent;
+use crate::io::ModifiersState;
+
+use serde::{Deserialize, Serialize};
+use spdlog::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// An event and the modifiers that were held when it happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+  pub event: GegEvent,
+  pub modifiers: ModifiersState,
+}
+
+/// The events that were passed to the layers before a frame's update and its delta time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+  pub index: u64,
+  pub dt: f32,
+  pub events: Vec<RecordedEvent>,
+}
+
+/// A recorded input stream, saved and loaded as RON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputRecording {
+  /// The fixed update rate the recording was made with.
+  pub tick_rate: u32,
+  /// The most fixed updates a frame could run when the recording was made.
+  pub max_fixed_steps: u32,
+  pub frames: Vec<RecordedFrame>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+  Io(std::io::Error),
+  Parse(ron::error::SpannedError),
+  Serialize(ron::Error),
+  /// The recording was made with another fixed update rate, it wouldn't replay the same.
+  TickRate { recorded: u32, app: u32 },
+  /// The recording was made with another limit of fixed updates per frame.
+  MaxFixedSteps { recorded: u32, app: u32 },
+}
+
+impl fmt::Display for ReplayError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ReplayError::Io(e) => write!(f, "failed to access the recording file: {}", e),
+      ReplayError::Parse(e) => write!(f, "failed to parse the recording: {}", e),
+      ReplayError::Serialize(e) => write!(f, "failed to serialize the recording: {}", e),
+      ReplayError::TickRate { recorded, app } => write!(
+        f,
+        "the recording was made with a tick rate of {} but the app uses {}",
+        recorded, app
+      ),
+      ReplayError::MaxFixedSteps { recorded, app } => write!(
+        f,
+        "the recording was made with at most {} fixed steps per frame but the app uses {}",
+        recorded, app
+      ),
+    }
+  }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl InputRecording {
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+    let src = std::fs::read_to_string(path).map_err(ReplayError::Io)?;
+    ron::from_str(&src).map_err(ReplayError::Parse)
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+    let src = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+      .map_err(ReplayError::Serialize)?;
+    std::fs::write(path, src).map_err(ReplayError::Io)
+  }
+}
+
+/// Collects the events of every frame and writes them to a file when the app is dropped.
+pub(crate) struct Recorder {
+  path: PathBuf,
+  recording: InputRecording,
+  events: Vec<RecordedEvent>,
+}
+
+impl Recorder {
+  pub fn new(path: PathBuf, tick_rate: u32, max_fixed_steps: u32) -> Self {
+    info!("Recording input to {}", path.display());
+    Self {
+      path,
+      recording: InputRecording {
+        tick_rate,
+        max_fixed_steps,
+        frames: Vec::new(),
+      },
+      events: Vec::new(),
+    }
+  }
+
+  pub fn record_event(&mut self, event: &GegEvent, modifiers: ModifiersState) {
+    self.events.push(RecordedEvent {
+      event: event.clone(),
+      modifiers,
+    });
+  }
+
+  pub fn end_frame(&mut self, index: u64, dt: f32) {
+    self.recording.frames.push(RecordedFrame {
+      index,
+      dt,
+      events: std::mem::take(&mut self.events),
+    });
+  }
+
+  pub fn save(&self) {
+    match self.recording.save(&self.path) {
+      Ok(()) => info!(
+        "Saved {} recorded frames to {}",
+        self.recording.frames.len(),
+        self.path.display()
+      ),
+      Err(e) => error!("Failed to save the input recording: {}", e),
+    }
+  }
+}
+
+/// Hands out the recorded frames in order.
+pub(crate) struct Replayer {
+  frames: VecDeque<RecordedFrame>,
+}
+
+impl Replayer {
+  /// Fails if the recording was made with another tick rate or limit of fixed steps, they
+  /// decide how many fixed updates run every frame.
+  pub fn new(
+    recording: InputRecording,
+    tick_rate: u32,
+    max_fixed_steps: u32,
+  ) -> Result<Self, ReplayError> {
+    if recording.tick_rate != tick_rate {
+      return Err(ReplayError::TickRate {
+        recorded: recording.tick_rate,
+        app: tick_rate,
+      });
+    }
+    if recording.max_fixed_steps != max_fixed_steps {
+      return Err(ReplayError::MaxFixedSteps {
+        recorded: recording.max_fixed_steps,
+        app: max_fixed_steps,
+      });
+    }
+
+    info!("Replaying {} recorded frames", recording.frames.len());
+    Ok(Self {
+      frames: recording.frames.into(),
+    })
+  }
+
+  pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+    self.frames.pop_front()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn recordings_of_other_fixed_steps_are_rejected() {
+    let recording = InputRecording {
+      tick_rate: 60,
+      max_fixed_steps: 5,
+      frames: Vec::new(),
+    };
+    assert!(Replayer::new(recording.clone(), 60, 5).is_ok());
+    assert!(matches!(
+      Replayer::new(recording.clone(), 30, 5),
+      Err(ReplayError::TickRate {
+        recorded: 60,
+        app: 30
+      })
+    ));
+    assert!(matches!(
+      Replayer::new(recording, 60, 8),
+      Err(ReplayError::MaxFixedSteps {
+        recorded: 5,
+        app: 8
+      })
+    ));
+  }
+}
@@ -0,0 +1,375 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use spdlog::prelude::*;
+
+use crate::traits::{
+  GraphicsError, PipelineDesc, PipelineHandle, ShaderDesc, ShaderSource, ShaderStage,
+};
+
+/// Reads a shader from a file, the source type is picked from the extension.
+///
+/// `.spv` files are SPIR-V binaries, `.wgsl` files are WGSL and `.glsl`, `.vert`
+/// and `.frag` files are GLSL. GLSL shaders always use `main` as their entry point.
+pub fn load_shader(path: impl AsRef<Path>, entry_point: &str) -> Result<ShaderDesc, GraphicsError> {
+  let path = path.as_ref();
+  let read_error = |e: std::io::Error| {
+    GraphicsError::InvalidShader(format!("failed to read {}: {}", path.display(), e))
+  };
+
+  let extension = path
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .unwrap_or_default();
+
+  let source = match extension {
+    "spv" => {
+      let bytes = fs::read(path).map_err(read_error)?;
+      if bytes.len() % 4 != 0 {
+        return Err(GraphicsError::InvalidShader(format!(
+          "{} isn't a SPIR-V binary",
+          path.display()
+        )));
+      }
+
+      ShaderSource::SpirV(
+        bytes
+          .chunks_exact(4)
+          .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+          .collect(),
+      )
+    }
+    "wgsl" => ShaderSource::Wgsl(fs::read_to_string(path).map_err(read_error)?),
+    "glsl" | "vert" | "frag" => ShaderSource::Glsl(fs::read_to_string(path).map_err(read_error)?),
+    _ => {
+      return Err(GraphicsError::InvalidShader(format!(
+        "{} isn't a .spv, .wgsl or .glsl file",
+        path.display()
+      )))
+    }
+  };
+
+  Ok(ShaderDesc {
+    source,
+    entry_point: entry_point.to_string(),
+    path: Some(path.to_path_buf()),
+  })
+}
+
+/// parses and validates a shader, the errors point into the source
+pub(crate) fn to_naga(
+  desc: &ShaderDesc,
+  stage: ShaderStage,
+) -> Result<(naga::Module, naga::valid::ModuleInfo), GraphicsError> {
+  let name = desc
+    .path
+    .as_ref()
+    .map_or_else(|| "shader".to_string(), |path| path.display().to_string());
+
+  let module = match &desc.source {
+    ShaderSource::SpirV(words) => {
+      let options = naga::front::spv::Options {
+        adjust_coordinate_space: false,
+        strict_capabilities: true,
+        block_ctx_dump_prefix: None,
+      };
+      naga::front::spv::Parser::new(words.iter().copied(), &options)
+        .parse()
+        .map_err(|e| GraphicsError::InvalidShader(format!("{}: {}", name, e)))?
+    }
+
+    ShaderSource::Wgsl(source) => naga::front::wgsl::parse_str(source)
+      .map_err(|e| GraphicsError::InvalidShader(e.emit_to_string_with_path(source, &name)))?,
+
+    ShaderSource::Glsl(source) => {
+      let stage = match stage {
+        ShaderStage::Vertex => naga::ShaderStage::Vertex,
+        ShaderStage::Fragment => naga::ShaderStage::Fragment,
+      };
+
+      naga::front::glsl::Parser::default()
+        .parse(&naga::front::glsl::Options::from(stage), source)
+        .map_err(|errors| {
+          let errors: Vec<String> = errors
+            .iter()
+            .map(|e| {
+              let location = e.meta.location(source);
+              format!(
+                "{}:{}:{}: {}",
+                name, location.line_number, location.line_position, e.kind
+              )
+            })
+            .collect();
+          GraphicsError::InvalidShader(errors.join("\n"))
+        })?
+    }
+  };
+
+  let info = naga::valid::Validator::new(
+    naga::valid::ValidationFlags::all(),
+    naga::valid::Capabilities::empty(),
+  )
+  .validate(&module)
+  .map_err(|e| match &desc.source {
+    ShaderSource::Wgsl(source) | ShaderSource::Glsl(source) => {
+      GraphicsError::InvalidShader(e.emit_to_string_with_path(source, &name))
+    }
+    ShaderSource::SpirV(_) => GraphicsError::InvalidShader(format!("{}: {}", name, e)),
+  })?;
+
+  Ok((module, info))
+}
+
+/// compiles a shader to SPIR-V, only the entry point of the shader is kept
+pub(crate) fn to_spirv(desc: &ShaderDesc, stage: ShaderStage) -> Result<Vec<u32>, GraphicsError> {
+  if let ShaderSource::SpirV(words) = &desc.source {
+    return Ok(words.clone());
+  }
+
+  let (module, info) = to_naga(desc, stage)?;
+  let options = naga::back::spv::Options {
+    // clip space already has y pointing up on every backend
+    flags: naga::back::spv::WriterFlags::empty(),
+    ..Default::default()
+  };
+  let pipeline_options = naga::back::spv::PipelineOptions {
+    shader_stage: match stage {
+      ShaderStage::Vertex => naga::ShaderStage::Vertex,
+      ShaderStage::Fragment => naga::ShaderStage::Fragment,
+    },
+    entry_point: desc.entry_point.clone(),
+  };
+
+  naga::back::spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+    .map_err(|e| GraphicsError::InvalidShader(e.to_string()))
+}
+
+/// watches the shader files of pipelines and reloads them when they change
+pub(crate) struct ShaderWatcher {
+  // `None` if the platform watcher couldn't be created
+  watcher: Option<RecommendedWatcher>,
+  events: Receiver<notify::Result<notify::Event>>,
+  watched_dirs: HashSet<PathBuf>,
+  pipelines: HashMap<PipelineHandle, PipelineDesc>,
+}
+
+impl ShaderWatcher {
+  pub fn new() -> Self {
+    let (sender, events) = channel();
+    let watcher = notify::recommended_watcher(sender)
+      .map_err(|e| warn!("Shader hot reload is disabled: {}", e))
+      .ok();
+
+    Self {
+      watcher,
+      events,
+      watched_dirs: HashSet::new(),
+      pipelines: HashMap::new(),
+    }
+  }
+
+  /// starts watching the shader files of `pipeline`, if it has any
+  pub fn watch(&mut self, pipeline: PipelineHandle, desc: &PipelineDesc) {
+    let watcher = match &mut self.watcher {
+      Some(watcher) => watcher,
+      None => return,
+    };
+
+    let paths = shader_paths(desc);
+    if paths.is_empty() {
+      return;
+    }
+
+    // editors often replace the file on save, so the directory is watched instead
+    for path in paths {
+      let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+      };
+      let dir = dir.canonicalize().unwrap_or(dir);
+
+      if !self.watched_dirs.contains(&dir) {
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+          Ok(()) => {
+            self.watched_dirs.insert(dir);
+          }
+          Err(e) => warn!("Can't watch {}: {}", dir.display(), e),
+        }
+      }
+    }
+
+    self.pipelines.insert(pipeline, desc.clone());
+  }
+
+  pub fn unwatch(&mut self, pipeline: PipelineHandle) {
+    self.pipelines.remove(&pipeline);
+  }
+
+  /// the pipelines whose shader files changed, with their shaders read again
+  /// shaders that can't be read are logged and their pipeline is skipped
+  pub fn changed(&mut self) -> Vec<(PipelineHandle, PipelineDesc)> {
+    let mut changed_files = HashSet::new();
+    while let Ok(event) = self.events.try_recv() {
+      match event {
+        Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+          changed_files.extend(
+            event
+              .paths
+              .into_iter()
+              .map(|path| path.canonicalize().unwrap_or(path)),
+          );
+        }
+        Ok(_) => (),
+        Err(e) => warn!("Shader watcher error: {}", e),
+      }
+    }
+
+    if changed_files.is_empty() {
+      return Vec::new();
+    }
+
+    let mut reloaded = Vec::new();
+    for (pipeline, desc) in &self.pipelines {
+      let is_changed = shader_paths(desc).iter().any(|path| {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        changed_files.contains(&path)
+      });
+      if !is_changed {
+        continue;
+      }
+
+      match reload_shaders(desc) {
+        Ok(desc) => reloaded.push((*pipeline, desc)),
+        Err(e) => error!("Failed to reload the shaders of {:?}: {}", pipeline, e),
+      }
+    }
+
+    // the next reload starts from the latest sources
+    for (pipeline, desc) in &reloaded {
+      self.pipelines.insert(*pipeline, desc.clone());
+    }
+    reloaded
+  }
+}
+
+fn shader_paths(desc: &PipelineDesc) -> Vec<&Path> {
+  [&desc.vertex_shader, &desc.fragment_shader]
+    .into_iter()
+    .filter_map(|shader| shader.path.as_deref())
+    .collect()
+}
+
+fn reload_shaders(desc: &PipelineDesc) -> Result<PipelineDesc, GraphicsError> {
+  let reload = |shader: &ShaderDesc| match &shader.path {
+    Some(path) => load_shader(path, &shader.entry_point),
+    None => Ok(shader.clone()),
+  };
+
+  Ok(PipelineDesc {
+    vertex_shader: reload(&desc.vertex_shader)?,
+    fragment_shader: reload(&desc.fragment_shader)?,
+    ..desc.clone()
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::traits::{BlendMode, CullMode, DepthMode, PrimitiveTopology};
+
+  const WGSL: &str = "@vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(); }";
+
+  /// a fresh directory for the files of one test
+  fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("geg-shader-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn the_source_type_follows_the_extension() {
+    let dir = dir("extensions");
+    for (file, contents) in [
+      ("a.wgsl", WGSL.as_bytes()),
+      ("a.vert", b"void main() {}".as_slice()),
+      ("a.glsl", b"void main() {}".as_slice()),
+      ("a.spv", &[1, 0, 0, 0, 2, 0, 0, 0]),
+      ("b.spv", &[1, 0, 0]),
+      ("a.txt", b"text".as_slice()),
+    ] {
+      fs::write(dir.join(file), contents).unwrap();
+    }
+
+    let wgsl = load_shader(dir.join("a.wgsl"), "vs_main").unwrap();
+    assert_eq!(wgsl.source, ShaderSource::Wgsl(WGSL.to_string()));
+    assert_eq!(wgsl.entry_point, "vs_main");
+    assert_eq!(wgsl.path, Some(dir.join("a.wgsl")));
+    assert!(matches!(
+      load_shader(dir.join("a.vert"), "main").unwrap().source,
+      ShaderSource::Glsl(_)
+    ));
+    assert!(matches!(
+      load_shader(dir.join("a.glsl"), "main").unwrap().source,
+      ShaderSource::Glsl(_)
+    ));
+    assert_eq!(
+      load_shader(dir.join("a.spv"), "main").unwrap().source,
+      ShaderSource::SpirV(vec![1, 2])
+    );
+
+    for file in ["b.spv", "a.txt", "missing.wgsl"] {
+      assert!(matches!(
+        load_shader(dir.join(file), "main"),
+        Err(GraphicsError::InvalidShader(_))
+      ));
+    }
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn reloads_read_the_files_again_or_fail() {
+    let dir = dir("reload");
+    let path = dir.join("shader.wgsl");
+    fs::write(&path, WGSL).unwrap();
+    let shader = load_shader(&path, "vs_main").unwrap();
+    let desc = PipelineDesc {
+      vertex_shader: shader.clone(),
+      fragment_shader: ShaderDesc::wgsl(WGSL, "fs_main"),
+      vertex_layouts: Vec::new(),
+      descriptor_set_layouts: Vec::new(),
+      topology: PrimitiveTopology::TriangleList,
+      blend: BlendMode::Opaque,
+      cull_mode: CullMode::None,
+      depth: DepthMode::Disabled,
+    };
+    assert_eq!(shader_paths(&desc), [path.as_path()]);
+
+    let edited = format!("{}\n", WGSL);
+    fs::write(&path, &edited).unwrap();
+    let reloaded = reload_shaders(&desc).unwrap();
+    assert_eq!(reloaded.vertex_shader.source, ShaderSource::Wgsl(edited));
+    assert_eq!(reloaded.fragment_shader, desc.fragment_shader);
+
+    fs::remove_file(&path).unwrap();
+    assert!(reload_shaders(&desc).is_err());
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn broken_shaders_name_their_file() {
+    let desc = ShaderDesc {
+      source: ShaderSource::Wgsl("fn broken(".to_string()),
+      entry_point: "vs_main".to_string(),
+      path: Some(PathBuf::from("broken.wgsl")),
+    };
+    match to_naga(&desc, ShaderStage::Vertex) {
+      Err(GraphicsError::InvalidShader(message)) => assert!(message.contains("broken.wgsl")),
+      other => panic!("expected a shader error, got {:?}", other.map(|_| ())),
+    }
+    assert!(to_naga(&ShaderDesc::wgsl(WGSL, "vs_main"), ShaderStage::Vertex).is_ok());
+  }
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec4};
+use spdlog::prelude::*;
+
+use crate::backend::GraphicsContext;
+use crate::camera::{Camera, CameraUniform, Projection};
+use crate::texture::Texture;
+use crate::traits::{
+  BufferDesc, BufferHandle, BufferUsage, BuiltinPipeline, CommandEncoder, DescriptorSetDesc,
+  DescriptorSetHandle, GraphicsDevice, GraphicsError, IndexFormat, PipelineHandle, SamplerDesc,
+  SamplerHandle, TextureHandle, VertexFormat, VertexLayout,
+};
+
+/// A rectangle of a texture in pixels, starting at its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+}
+
+impl Region {
+  pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+    Self {
+      x,
+      y,
+      width,
+      height,
+    }
+  }
+
+  /// The whole texture.
+  pub fn full(texture: &Texture) -> Self {
+    Self::new(0.0, 0.0, texture.width() as f32, texture.height() as f32)
+  }
+}
+
+/// A textured quad drawn by a `SpriteBatch`.
+///
+/// Its size is the size of its region in pixels times its scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+  pub texture: Texture,
+  /// Where the pivot ends up.
+  pub position: Vec2,
+  /// Counterclockwise around the pivot, in radians.
+  pub rotation: f32,
+  pub scale: Vec2,
+  /// Multiplied with the texture color.
+  pub tint: Vec4,
+  pub region: Region,
+  /// The point the sprite is placed and rotated around,
+  /// from 0, 0 at its bottom left to 1, 1 at its top right.
+  pub pivot: Vec2,
+  /// Sprites on higher layers are drawn over the ones below. Within a layer sprites are drawn
+  /// in the order they were added in, consecutive sprites sharing a texture share a draw call.
+  pub layer: f32,
+}
+
+impl Sprite {
+  /// The whole texture, centered on the origin.
+  pub fn new(texture: Texture) -> Self {
+    Self {
+      texture,
+      position: Vec2::ZERO,
+      rotation: 0.0,
+      scale: Vec2::ONE,
+      tint: Vec4::ONE,
+      region: Region::full(&texture),
+      pivot: Vec2::splat(0.5),
+      layer: 0.0,
+    }
+  }
+
+  pub fn with_position(self, position: Vec2) -> Self {
+    Self { position, ..self }
+  }
+
+  pub fn with_rotation(self, rotation: f32) -> Self {
+    Self { rotation, ..self }
+  }
+
+  pub fn with_scale(self, scale: Vec2) -> Self {
+    Self { scale, ..self }
+  }
+
+  pub fn with_tint(self, tint: Vec4) -> Self {
+    Self { tint, ..self }
+  }
+
+  pub fn with_region(self, region: Region) -> Self {
+    Self { region, ..self }
+  }
+
+  pub fn with_pivot(self, pivot: Vec2) -> Self {
+    Self { pivot, ..self }
+  }
+
+  pub fn with_layer(self, layer: f32) -> Self {
+    Self { layer, ..self }
+  }
+
+  /// The corners in counterclockwise order, starting at the bottom left.
+  fn vertices(&self) -> [SpriteVertex; 4] {
+    let size = Vec2::new(self.region.width, self.region.height) * self.scale;
+    let min = -self.pivot * size;
+    let max = min + size;
+    let rotation = Vec2::from_angle(self.rotation);
+
+    let texture_size = Vec2::new(self.texture.width() as f32, self.texture.height() as f32);
+    let uv_min = Vec2::new(self.region.x, self.region.y) / texture_size;
+    let uv_max = uv_min + Vec2::new(self.region.width, self.region.height) / texture_size;
+
+    // the texture rows go down while y goes up
+    let corners = [
+      (Vec2::new(min.x, min.y), Vec2::new(uv_min.x, uv_max.y)),
+      (Vec2::new(max.x, min.y), Vec2::new(uv_max.x, uv_max.y)),
+      (Vec2::new(max.x, max.y), Vec2::new(uv_max.x, uv_min.y)),
+      (Vec2::new(min.x, max.y), Vec2::new(uv_min.x, uv_min.y)),
+    ];
+    corners.map(|(corner, uv)| SpriteVertex {
+      position: (self.position + rotation.rotate(corner)).to_array(),
+      uv: uv.to_array(),
+      color: self.tint.to_array(),
+    })
+  }
+}
+
+/// The vertex used by `BuiltinPipeline::Sprite`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Zeroable, Pod)]
+pub struct SpriteVertex {
+  pub position: [f32; 2],
+  pub uv: [f32; 2],
+  pub color: [f32; 4],
+}
+
+impl SpriteVertex {
+  pub fn layout() -> VertexLayout {
+    VertexLayout::packed(&[
+      VertexFormat::Float32x2,
+      VertexFormat::Float32x2,
+      VertexFormat::Float32x4,
+    ])
+  }
+}
+
+/// Draws many sprites with a draw call per run of consecutive sprites sharing a texture.
+///
+/// Sprites are queued with `SpriteBatch::draw` and recorded into the current pass by
+/// `SpriteBatch::render`. Their vertices go into a single buffer that grows as needed,
+/// it can be rendered more than once per frame but always uses the camera of the last render.
+pub struct SpriteBatch {
+  camera: Camera,
+  // the orthographic height follows the surface height
+  fit_camera: bool,
+  camera_buffer: BufferHandle,
+  camera_set: DescriptorSetHandle,
+  pipeline: PipelineHandle,
+  sampler: SamplerHandle,
+  // a set per texture drawn so far, made with the batch's sampler
+  texture_sets: HashMap<TextureHandle, DescriptorSetHandle>,
+  sprites: Vec<Sprite>,
+  // the vertices of the current frame, rewritten when the buffers grow
+  vertices: Vec<SpriteVertex>,
+  frame: u64,
+  vertex_buffer: BufferHandle,
+  index_buffer: BufferHandle,
+  // in sprites
+  capacity: usize,
+}
+
+impl SpriteBatch {
+  /// `sampler` is used for every texture, nearest filtering keeps pixel art sharp.
+  ///
+  /// The camera is orthographic and shows the surface height in pixels around the origin.
+  /// It's fitted to the surface on every render, so sprites are drawn at the size of their
+  /// texture even after the window is resized.
+  pub fn new(
+    graphics: &mut GraphicsContext,
+    sampler: &SamplerDesc,
+  ) -> Result<Self, GraphicsError> {
+    let frame = graphics.frame_count();
+    let device = graphics.device();
+    let [width, height] = device.surface_size();
+    let camera = Camera::orthographic(height.max(1) as f32, 1.0, -1.0, 1.0);
+
+    let camera_buffer = device.create_buffer(
+      &BufferDesc {
+        size: std::mem::size_of::<CameraUniform>() as u64,
+        usage: BufferUsage::Uniform,
+      },
+      None,
+    )?;
+    let camera_set =
+      device.create_descriptor_set(&DescriptorSetDesc::uniform_buffers(&[camera_buffer]))?;
+    let sampler = device.create_sampler(sampler)?;
+
+    let capacity = 256;
+    let (vertex_buffer, index_buffer) = create_buffers(device, capacity)?;
+
+    let mut batch = Self {
+      camera,
+      fit_camera: true,
+      camera_buffer,
+      camera_set,
+      pipeline: device.builtin_pipeline(BuiltinPipeline::Sprite),
+      sampler,
+      texture_sets: HashMap::new(),
+      sprites: Vec::new(),
+      vertices: Vec::new(),
+      frame,
+      vertex_buffer,
+      index_buffer,
+      capacity,
+    };
+    batch.camera.resize(width, height);
+    Ok(batch)
+  }
+
+  pub fn camera(&self) -> &Camera {
+    &self.camera
+  }
+
+  /// The view can be moved freely, the orthographic height is reset to the surface height on
+  /// every render unless `SpriteBatch::set_fit_camera` turned that off.
+  pub fn camera_mut(&mut self) -> &mut Camera {
+    &mut self.camera
+  }
+
+  /// Whether the height of an orthographic camera is set to the surface height on every render,
+  /// on by default. Turn it off to drive the projection yourself, e.g. for world space sprites.
+  pub fn set_fit_camera(&mut self, fit: bool) {
+    self.fit_camera = fit;
+  }
+
+  /// Queues a sprite for the next render.
+  pub fn draw(&mut self, sprite: Sprite) {
+    self.sprites.push(sprite);
+  }
+
+  /// The number of queued sprites.
+  pub fn len(&self) -> usize {
+    self.sprites.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.sprites.is_empty()
+  }
+
+  /// Records the queued sprites into the current pass and empties the queue.
+  ///
+  /// Returns the number of draw calls.
+  pub fn render(&mut self, graphics: &mut GraphicsContext) -> usize {
+    if self.sprites.is_empty() {
+      return 0;
+    }
+
+    // the previous frames were submitted, their vertices aren't needed anymore
+    if self.frame != graphics.frame_count() {
+      self.frame = graphics.frame_count();
+      self.vertices.clear();
+    }
+
+    let mut sprites = std::mem::take(&mut self.sprites);
+    // stable, so the sprites of a layer keep their order and overlap the same way whatever
+    // their textures are
+    sprites.sort_by(|a, b| a.layer.total_cmp(&b.layer));
+
+    let first = self.vertices.len() / 4;
+    self
+      .vertices
+      .extend(sprites.iter().flat_map(|sprite| sprite.vertices()));
+    if let Err(e) = self.upload(graphics, first) {
+      error!("Failed to upload the sprites: {}", e);
+      return 0;
+    }
+
+    let [width, height] = graphics.device().surface_size();
+    self.camera.resize(width, height);
+    if let Projection::Orthographic {
+      height: visible, ..
+    } = &mut self.camera.projection
+    {
+      // minimized windows have no size, the last height is kept like the aspect ratio
+      if self.fit_camera && height > 0 {
+        *visible = height as f32;
+      }
+    }
+    if let Err(e) = graphics.device().write_buffer(
+      self.camera_buffer,
+      0,
+      bytemuck::bytes_of(&self.camera.uniform()),
+    ) {
+      error!("Failed to upload the sprite camera: {}", e);
+    }
+
+    let encoder = graphics.encoder();
+    encoder.set_pipeline(self.pipeline);
+    encoder.set_vertex_buffer(0, self.vertex_buffer);
+    encoder.set_index_buffer(self.index_buffer);
+    encoder.set_descriptor_set(0, self.camera_set);
+
+    let mut draws = 0;
+    let mut start = 0;
+    while start < sprites.len() {
+      let texture = sprites[start].texture.handle();
+      let end = start
+        + sprites[start..]
+          .iter()
+          .take_while(|sprite| sprite.texture.handle() == texture)
+          .count();
+
+      match self.texture_set(graphics.device(), texture) {
+        Ok(set) => {
+          let encoder = graphics.encoder();
+          encoder.set_descriptor_set(1, set);
+          let indices = ((first + start) * 6) as u32..((first + end) * 6) as u32;
+          encoder.draw_indexed(indices, 0, 0..1);
+          draws += 1;
+        }
+        Err(e) => warn!("Skipping the sprites of {:?}: {}", texture, e),
+      }
+      start = end;
+    }
+
+    // keeps the allocation for the next frame
+    sprites.clear();
+    self.sprites = sprites;
+    draws
+  }
+
+  /// Frees the descriptor set made for `texture`, call it before destroying a drawn texture.
+  pub fn release_texture(&mut self, device: &mut dyn GraphicsDevice, texture: TextureHandle) {
+    if let Some(set) = self.texture_sets.remove(&texture) {
+      device.destroy_descriptor_set(set);
+    }
+  }
+
+  /// Frees the gpu resources, the textures are left alone.
+  pub fn destroy(self, device: &mut dyn GraphicsDevice) {
+    for set in self.texture_sets.into_values() {
+      device.destroy_descriptor_set(set);
+    }
+    device.destroy_descriptor_set(self.camera_set);
+    device.destroy_buffer(self.camera_buffer);
+    device.destroy_sampler(self.sampler);
+    device.destroy_buffer(self.vertex_buffer);
+    device.destroy_buffer(self.index_buffer);
+  }
+
+  fn texture_set(
+    &mut self,
+    device: &mut dyn GraphicsDevice,
+    texture: TextureHandle,
+  ) -> Result<DescriptorSetHandle, GraphicsError> {
+    if let Some(set) = self.texture_sets.get(&texture) {
+      return Ok(*set);
+    }
+
+    let set = device.create_descriptor_set(&DescriptorSetDesc::texture(texture, self.sampler))?;
+    self.texture_sets.insert(texture, set);
+    Ok(set)
+  }
+
+  /// writes the vertices from the sprite at `first`, the buffers are replaced if they don't fit
+  fn upload(&mut self, graphics: &mut GraphicsContext, first: usize) -> Result<(), GraphicsError> {
+    let sprites = self.vertices.len() / 4;
+    if sprites <= self.capacity {
+      let offset = (first * 4 * std::mem::size_of::<SpriteVertex>()) as u64;
+      return graphics.device().write_buffer(
+        self.vertex_buffer,
+        offset,
+        bytemuck::cast_slice(&self.vertices[first * 4..]),
+      );
+    }
+
+    let capacity = sprites.next_power_of_two();
+    let (vertex_buffer, index_buffer) = create_buffers(graphics.device(), capacity)?;
+    // the sprites rendered earlier this frame are drawn from the new buffers too
+    let encoder = graphics.encoder();
+    encoder.replace_buffer(self.vertex_buffer, vertex_buffer);
+    encoder.replace_buffer(self.index_buffer, index_buffer);
+
+    let device = graphics.device();
+    device.destroy_buffer(self.vertex_buffer);
+    device.destroy_buffer(self.index_buffer);
+    self.vertex_buffer = vertex_buffer;
+    self.index_buffer = index_buffer;
+    self.capacity = capacity;
+    debug!("Sprite buffers grown to {} sprites", capacity);
+
+    device.write_buffer(self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices))
+  }
+}
+
+/// a vertex buffer for `capacity` sprites and the indices of their quads
+fn create_buffers(
+  device: &mut dyn GraphicsDevice,
+  capacity: usize,
+) -> Result<(BufferHandle, BufferHandle), GraphicsError> {
+  let indices: Vec<u32> = (0..capacity as u32)
+    .flat_map(|sprite| [0, 1, 2, 2, 3, 0].map(|corner| sprite * 4 + corner))
+    .collect();
+  let index_buffer = device.create_buffer(
+    &BufferDesc {
+      size: (indices.len() * std::mem::size_of::<u32>()) as u64,
+      usage: BufferUsage::Index(IndexFormat::U32),
+    },
+    Some(bytemuck::cast_slice(&indices)),
+  )?;
+
+  let vertex_buffer = device.create_buffer(
+    &BufferDesc {
+      size: (capacity * 4 * std::mem::size_of::<SpriteVertex>()) as u64,
+      usage: BufferUsage::Vertex,
+    },
+    None,
+  );
+  match vertex_buffer {
+    Ok(vertex_buffer) => Ok((vertex_buffer, index_buffer)),
+    Err(e) => {
+      device.destroy_buffer(index_buffer);
+      Err(e)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::null::GraphicsCall;
+  use crate::backend::{GegBackend, GraphicsOptions};
+  use crate::traits::{PassTarget, TextureDesc, TextureFormat};
+  use glam::{Mat4, Vec3};
+
+  fn graphics(width: u32, height: u32) -> GraphicsContext {
+    GraphicsContext::new(
+      GegBackend::Null,
+      None,
+      [width, height],
+      GraphicsOptions::default(),
+    )
+  }
+
+  fn texture(graphics: &mut GraphicsContext, width: u32, height: u32) -> Texture {
+    let desc = TextureDesc {
+      width,
+      height,
+      format: TextureFormat::Rgba8Unorm,
+    };
+    let pixels = vec![255; (width * height * 4) as usize];
+    Texture::new(graphics.device(), &desc, &pixels).unwrap()
+  }
+
+  /// renders the queued sprites in a screen pass and submits the frame
+  fn render(batch: &mut SpriteBatch, graphics: &mut GraphicsContext) -> usize {
+    graphics.encoder().begin_pass(PassTarget::Screen, [0.0; 4]);
+    let draws = batch.render(graphics);
+    graphics.encoder().end_pass();
+    graphics.update();
+    draws
+  }
+
+  /// the view projection last written to the camera buffer of `batch`
+  fn uploaded_camera(batch: &SpriteBatch, graphics: &GraphicsContext) -> Mat4 {
+    let data = graphics
+      .log()
+      .unwrap()
+      .calls()
+      .into_iter()
+      .rev()
+      .find_map(|call| match call {
+        GraphicsCall::WriteBuffer { handle, data, .. } if handle == batch.camera_buffer => {
+          Some(data)
+        }
+        _ => None,
+      })
+      .unwrap();
+    let uniform: CameraUniform = bytemuck::pod_read_unaligned(&data);
+    Mat4::from_cols_array_2d(&uniform.view_projection)
+  }
+
+  #[test]
+  fn sprites_keep_their_order_within_a_layer() {
+    let mut graphics = graphics(64, 64);
+    let mut batch = SpriteBatch::new(&mut graphics, &SamplerDesc::default()).unwrap();
+    let a = texture(&mut graphics, 1, 1);
+    let b = texture(&mut graphics, 2, 2);
+
+    batch.draw(Sprite::new(a).with_layer(1.0));
+    batch.draw(Sprite::new(b));
+    batch.draw(Sprite::new(a));
+    batch.draw(Sprite::new(a));
+    batch.draw(Sprite::new(b).with_layer(1.0));
+    batch.draw(Sprite::new(b).with_layer(-1.0));
+    assert_eq!(batch.len(), 6);
+    assert_eq!(render(&mut batch, &mut graphics), 3);
+    assert!(batch.is_empty());
+
+    // the texture bound for each draw and how many sprites it has
+    let texture_of = |set| batch.texture_sets.iter().find(|(_, s)| **s == set).unwrap().0;
+    let draws: Vec<(TextureHandle, u32)> = graphics
+      .log()
+      .unwrap()
+      .last_frame_draws()
+      .into_iter()
+      .map(|draw| {
+        let set = draw.descriptor_sets.iter().find(|(index, _)| *index == 1).unwrap().1;
+        (*texture_of(set), draw.elements.len() as u32 / 6)
+      })
+      .collect();
+    let [a, b] = [a.handle(), b.handle()];
+    // runs across layers are drawn together too, the order stays the same
+    assert_eq!(draws, [(b, 2), (a, 3), (b, 1)]);
+  }
+
+  #[test]
+  fn the_camera_follows_the_surface_size() {
+    let mut graphics = graphics(200, 100);
+    let mut batch = SpriteBatch::new(&mut graphics, &SamplerDesc::default()).unwrap();
+    let dot = texture(&mut graphics, 1, 1);
+
+    batch.draw(Sprite::new(dot));
+    render(&mut batch, &mut graphics);
+    let corner = uploaded_camera(&batch, &graphics).project_point3(Vec3::new(100.0, 50.0, 0.0));
+    assert!(corner.abs_diff_eq(Vec3::new(1.0, 1.0, 0.5), 1e-5));
+
+    // a pixel stays a pixel after a resize
+    graphics.resize(400, 300);
+    batch.draw(Sprite::new(dot));
+    render(&mut batch, &mut graphics);
+    let camera = uploaded_camera(&batch, &graphics);
+    let corner = camera.project_point3(Vec3::new(200.0, 150.0, 0.0));
+    assert!(corner.abs_diff_eq(Vec3::new(1.0, 1.0, 0.5), 1e-5));
+
+    // a minimized window keeps the last size
+    graphics.resize(0, 0);
+    batch.draw(Sprite::new(dot));
+    render(&mut batch, &mut graphics);
+    assert_eq!(uploaded_camera(&batch, &graphics), camera);
+  }
+}
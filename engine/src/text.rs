@@ -0,0 +1,750 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use fontdue::FontSettings;
+use glam::{Vec2, Vec4};
+use spdlog::prelude::*;
+
+use crate::backend::GraphicsContext;
+use crate::camera::Camera;
+use crate::sprite::{Region, Sprite, SpriteBatch};
+use crate::texture::Texture;
+use crate::traits::{GraphicsDevice, GraphicsError, SamplerDesc, TextureDesc, TextureFormat};
+
+/// The atlas starts this wide and tall and doubles when it is full.
+const ATLAS_SIZE: u32 = 256;
+const MAX_ATLAS_SIZE: u32 = 4096;
+/// Empty pixels around each glyph so linear filtering doesn't pick up its neighbours.
+const GLYPH_PADDING: u32 = 1;
+
+/// A TrueType or OpenType font, its glyphs are rasterized when text first uses them.
+pub struct Font {
+  font: fontdue::Font,
+}
+
+impl Font {
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, GraphicsError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| {
+      GraphicsError::InvalidFont(format!("failed to read {}: {}", path.display(), e))
+    })?;
+    Self::from_bytes(&bytes)
+      .map_err(|e| GraphicsError::InvalidFont(format!("{}: {}", path.display(), e)))
+  }
+
+  /// Parses a font file that is already in memory.
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, GraphicsError> {
+    let font = fontdue::Font::from_bytes(bytes, FontSettings::default())
+      .map_err(|e| GraphicsError::InvalidFont(e.to_string()))?;
+    Ok(Self { font })
+  }
+
+  /// The distance between the baselines of two lines at `size` pixels per em.
+  pub fn line_advance(&self, size: f32) -> f32 {
+    self
+      .font
+      .horizontal_line_metrics(size)
+      .map_or(size * 1.2, |metrics| metrics.new_line_size)
+  }
+
+  /// How far the tallest glyphs reach above the baseline at `size` pixels per em.
+  pub fn ascent(&self, size: f32) -> f32 {
+    self
+      .font
+      .horizontal_line_metrics(size)
+      .map_or(size, |metrics| metrics.ascent)
+  }
+}
+
+/// A font added to a `TextRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// Where the lines are placed relative to the text position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+  /// Lines start at the position.
+  Left,
+  /// Lines are centered on the position.
+  Center,
+  /// Lines end at the position.
+  Right,
+}
+
+/// Which camera the text is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextSpace {
+  /// The orthographic pixel camera of the renderer, like a `SpriteBatch`.
+  Screen,
+  /// The world camera of the renderer, it isn't synced with the scene camera so it has to be
+  /// set through `TextRenderer::world_camera_mut`.
+  World,
+}
+
+/// A string drawn by a `TextRenderer`.
+///
+/// Sizes and widths are in pixels at a scale of 1, in world space the scale turns them
+/// into world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Text<'a> {
+  pub font: FontId,
+  pub content: &'a str,
+  /// Where the top of the first line is placed, see `Align` for the horizontal side.
+  pub position: Vec2,
+  /// Pixels per em, glyphs are rasterized at this size.
+  pub size: f32,
+  pub color: Vec4,
+  pub align: Align,
+  /// Lines are wrapped at whitespace to fit, words wider than this are broken anywhere.
+  pub max_width: Option<f32>,
+  /// Multiplies the font's distance between lines.
+  pub line_height: f32,
+  pub space: TextSpace,
+  /// Multiplies the size of the laid out text.
+  pub scale: f32,
+  /// Drawn over sprites and text on lower layers in the same space.
+  pub layer: f32,
+}
+
+impl<'a> Text<'a> {
+  /// White left aligned screen text of 16 pixels.
+  pub fn new(font: FontId, content: &'a str) -> Self {
+    Self {
+      font,
+      content,
+      position: Vec2::ZERO,
+      size: 16.0,
+      color: Vec4::ONE,
+      align: Align::Left,
+      max_width: None,
+      line_height: 1.0,
+      space: TextSpace::Screen,
+      scale: 1.0,
+      layer: 0.0,
+    }
+  }
+
+  pub fn with_position(self, position: Vec2) -> Self {
+    Self { position, ..self }
+  }
+
+  pub fn with_size(self, size: f32) -> Self {
+    Self { size, ..self }
+  }
+
+  pub fn with_color(self, color: Vec4) -> Self {
+    Self { color, ..self }
+  }
+
+  pub fn with_align(self, align: Align) -> Self {
+    Self { align, ..self }
+  }
+
+  pub fn with_max_width(self, max_width: f32) -> Self {
+    Self {
+      max_width: Some(max_width),
+      ..self
+    }
+  }
+
+  pub fn with_line_height(self, line_height: f32) -> Self {
+    Self {
+      line_height,
+      ..self
+    }
+  }
+
+  pub fn with_space(self, space: TextSpace) -> Self {
+    Self { space, ..self }
+  }
+
+  pub fn with_scale(self, scale: f32) -> Self {
+    Self { scale, ..self }
+  }
+
+  pub fn with_layer(self, layer: f32) -> Self {
+    Self { layer, ..self }
+  }
+}
+
+/// Draws text with the glyphs of every font and size packed into one atlas texture.
+///
+/// Text is laid out and its new glyphs are rasterized by `TextRenderer::draw`, the atlas is
+/// uploaded and the glyphs are recorded into the current pass by `TextRenderer::render`.
+pub struct TextRenderer {
+  fonts: Vec<Font>,
+  atlas: GlyphAtlas,
+  texture: Option<Texture>,
+  // atlases replaced this frame, the recorded draws may still use them
+  retired: Vec<Texture>,
+  frame: u64,
+  glyphs: Vec<QueuedGlyph>,
+  screen: SpriteBatch,
+  world: SpriteBatch,
+}
+
+impl TextRenderer {
+  pub fn new(graphics: &mut GraphicsContext) -> Result<Self, GraphicsError> {
+    let screen = SpriteBatch::new(graphics, &SamplerDesc::linear())?;
+    let mut world = match SpriteBatch::new(graphics, &SamplerDesc::linear()) {
+      Ok(world) => world,
+      Err(e) => {
+        screen.destroy(graphics.device());
+        return Err(e);
+      }
+    };
+    // it's a copy of the scene camera
+    world.set_fit_camera(false);
+
+    Ok(Self {
+      fonts: Vec::new(),
+      atlas: GlyphAtlas::new(),
+      texture: None,
+      retired: Vec::new(),
+      frame: graphics.frame_count(),
+      glyphs: Vec::new(),
+      screen,
+      world,
+    })
+  }
+
+  pub fn add_font(&mut self, font: Font) -> FontId {
+    self.fonts.push(font);
+    FontId(self.fonts.len() - 1)
+  }
+
+  /// `None` if the id was given by another renderer.
+  pub fn font(&self, font: FontId) -> Option<&Font> {
+    self.fonts.get(font.0)
+  }
+
+  /// The camera of world space text, copy the scene camera into it before rendering.
+  pub fn world_camera_mut(&mut self) -> &mut Camera {
+    self.world.camera_mut()
+  }
+
+  /// The camera of screen space text, in pixels around the center of the surface.
+  pub fn screen_camera(&self) -> &Camera {
+    self.screen.camera()
+  }
+
+  /// The atlas texture, `None` until the first glyph is rendered.
+  pub fn atlas(&self) -> Option<Texture> {
+    self.texture
+  }
+
+  /// The width of the widest line and the height of all the lines, scaled.
+  ///
+  /// Text of an unknown font has no size.
+  pub fn measure(&self, text: &Text) -> Vec2 {
+    let font = match self.font(text.font) {
+      Some(font) => font,
+      None => return Vec2::ZERO,
+    };
+    let lines = layout(&font.font, text);
+    let width = lines.iter().map(Line::width).fold(0.0, f32::max);
+    let height = lines.len() as f32 * font.line_advance(text.size) * text.line_height;
+    Vec2::new(width, height) * text.scale
+  }
+
+  /// Lays out the text and queues its glyphs for the next render.
+  pub fn draw(&mut self, text: &Text) {
+    let font = match self.fonts.get(text.font.0) {
+      Some(font) => font,
+      None => {
+        warn!("Skipping text of an unknown font {:?}", text.font);
+        return;
+      }
+    };
+    let line_advance = font.line_advance(text.size) * text.line_height;
+    let mut baseline = -font.ascent(text.size);
+
+    for line in layout(&font.font, text) {
+      let start = match text.align {
+        Align::Left => 0.0,
+        Align::Center => -line.width() / 2.0,
+        Align::Right => -line.width(),
+      };
+
+      for glyph in line.glyphs.iter().filter(|glyph| !glyph.whitespace) {
+        let key = GlyphKey {
+          font: text.font,
+          index: glyph.index,
+          size: text.size.to_bits(),
+        };
+        let metrics = font.font.metrics_indexed(glyph.index, text.size);
+        let region = self
+          .atlas
+          .glyph(key, || font.font.rasterize_indexed(glyph.index, text.size));
+
+        if let Some(region) = region {
+          let corner = Vec2::new(
+            start + glyph.x + metrics.xmin as f32,
+            baseline + metrics.ymin as f32,
+          );
+          self.glyphs.push(QueuedGlyph {
+            region,
+            position: text.position + corner * text.scale,
+            scale: text.scale,
+            color: text.color,
+            space: text.space,
+            layer: text.layer,
+          });
+        }
+      }
+      baseline -= line_advance;
+    }
+  }
+
+  /// Uploads the new glyphs and records the queued text into the current pass, world space
+  /// text first so the screen space text is drawn over it.
+  ///
+  /// Returns the number of draw calls.
+  pub fn render(&mut self, graphics: &mut GraphicsContext) -> usize {
+    // the frames using the old atlases were submitted
+    if self.frame != graphics.frame_count() {
+      self.frame = graphics.frame_count();
+      for texture in self.retired.drain(..) {
+        self.screen.release_texture(graphics.device(), texture.handle());
+        self.world.release_texture(graphics.device(), texture.handle());
+        texture.destroy(graphics.device());
+      }
+    }
+
+    if self.glyphs.is_empty() {
+      return 0;
+    }
+
+    if let Err(e) = self.upload_atlas(graphics.device()) {
+      error!("Failed to upload the glyph atlas: {}", e);
+    }
+    let texture = match self.texture {
+      Some(texture) => texture,
+      None => {
+        self.glyphs.clear();
+        return 0;
+      }
+    };
+
+    for glyph in self.glyphs.drain(..) {
+      let sprite = Sprite::new(texture)
+        .with_region(glyph.region)
+        .with_pivot(Vec2::ZERO)
+        .with_position(glyph.position)
+        .with_scale(Vec2::splat(glyph.scale))
+        .with_tint(glyph.color)
+        .with_layer(glyph.layer);
+      match glyph.space {
+        TextSpace::Screen => self.screen.draw(sprite),
+        TextSpace::World => self.world.draw(sprite),
+      }
+    }
+
+    self.world.render(graphics) + self.screen.render(graphics)
+  }
+
+  /// writes the new glyphs into the atlas texture, it's only re-created when the atlas grew
+  fn upload_atlas(&mut self, device: &mut dyn GraphicsDevice) -> Result<(), GraphicsError> {
+    let [left, top, right, bottom] = match self.atlas.dirty {
+      Some(dirty) => dirty,
+      None => return Ok(()),
+    };
+
+    match self.texture {
+      Some(texture) if texture.width() == self.atlas.size => {
+        let pixels = self.atlas.pixels(left..right, top..bottom);
+        texture.write(device, [left, top], [right - left, bottom - top], &pixels)?;
+      }
+      _ => {
+        let texture = self.atlas.create_texture(device)?;
+        self.retired.extend(self.texture.replace(texture));
+      }
+    }
+    self.atlas.dirty = None;
+    Ok(())
+  }
+
+  /// Frees the atlases and the gpu resources of the batches.
+  pub fn destroy(self, device: &mut dyn GraphicsDevice) {
+    self.screen.destroy(device);
+    self.world.destroy(device);
+    for texture in self.retired.into_iter().chain(self.texture) {
+      texture.destroy(device);
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+  font: FontId,
+  index: u16,
+  // the bits of the size in pixels, so fractional sizes get their own glyphs
+  size: u32,
+}
+
+/// a glyph waiting for the atlas to be uploaded
+struct QueuedGlyph {
+  region: Region,
+  // of its bottom left corner
+  position: Vec2,
+  scale: f32,
+  color: Vec4,
+  space: TextSpace,
+  layer: f32,
+}
+
+/// a glyph placed on a line, `x` is where its pen position is
+struct LaidGlyph {
+  index: u16,
+  x: f32,
+  advance: f32,
+  whitespace: bool,
+}
+
+#[derive(Default)]
+struct Line {
+  glyphs: Vec<LaidGlyph>,
+  // the pen position after the last glyph
+  pen: f32,
+}
+
+impl Line {
+  /// trailing whitespace doesn't count
+  fn width(&self) -> f32 {
+    self
+      .glyphs
+      .iter()
+      .rev()
+      .find(|glyph| !glyph.whitespace)
+      .map_or(0.0, |glyph| glyph.x + glyph.advance)
+  }
+}
+
+/// splits the text at line breaks and wraps the lines wider than `max_width`
+fn layout(font: &fontdue::Font, text: &Text) -> Vec<Line> {
+  let mut lines = Vec::new();
+  for paragraph in text.content.lines() {
+    let mut line = Line::default();
+    let mut previous = None;
+    // the glyph after the last whitespace, where the line can be wrapped
+    let mut wrap_at = None;
+
+    for c in paragraph.chars() {
+      let index = font.lookup_glyph_index(c);
+      let kern = previous
+        .and_then(|previous| font.horizontal_kern_indexed(previous, index, text.size))
+        .unwrap_or(0.0);
+      let advance = font.metrics_indexed(index, text.size).advance_width;
+      let whitespace = c.is_whitespace();
+      previous = Some(index);
+
+      let overflows = text
+        .max_width
+        .is_some_and(|max_width| line.pen + kern + advance > max_width);
+      if overflows && !whitespace && !line.glyphs.is_empty() {
+        // moves the last word to a new line, or breaks the word if it fills the line
+        let rest = line.glyphs.split_off(wrap_at.take().unwrap_or(line.glyphs.len()));
+        let shift = rest.first().map_or(line.pen, |glyph| glyph.x);
+        let pen = line.pen - shift;
+        lines.push(std::mem::take(&mut line));
+        line.glyphs = rest
+          .into_iter()
+          .map(|glyph| LaidGlyph {
+            x: glyph.x - shift,
+            ..glyph
+          })
+          .collect();
+        line.pen = pen;
+      }
+
+      let x = line.pen + kern;
+      line.glyphs.push(LaidGlyph {
+        index,
+        x,
+        advance,
+        whitespace,
+      });
+      line.pen = x + advance;
+      if whitespace {
+        wrap_at = Some(line.glyphs.len());
+      }
+    }
+    lines.push(line);
+  }
+  lines
+}
+
+/// glyph coverage packed into rows of shelves, kept on the cpu and uploaded when it changes
+struct GlyphAtlas {
+  size: u32,
+  coverage: Vec<u8>,
+  shelves: Vec<Shelf>,
+  // `None` for glyphs without pixels and the ones that didn't fit
+  glyphs: HashMap<GlyphKey, Option<Region>>,
+  // the left, top, right and bottom edges of the pixels changed since the last upload
+  dirty: Option<[u32; 4]>,
+}
+
+struct Shelf {
+  y: u32,
+  height: u32,
+  // the first free column
+  x: u32,
+}
+
+impl GlyphAtlas {
+  fn new() -> Self {
+    Self {
+      size: ATLAS_SIZE,
+      coverage: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+      shelves: Vec::new(),
+      glyphs: HashMap::new(),
+      dirty: None,
+    }
+  }
+
+  /// the region of a glyph, `rasterize` is called the first time it's asked for
+  fn glyph(
+    &mut self,
+    key: GlyphKey,
+    rasterize: impl FnOnce() -> (fontdue::Metrics, Vec<u8>),
+  ) -> Option<Region> {
+    if let Some(region) = self.glyphs.get(&key) {
+      return *region;
+    }
+
+    let (metrics, coverage) = rasterize();
+    let (width, height) = (metrics.width as u32, metrics.height as u32);
+    let region = match (width, height) {
+      (0, _) | (_, 0) => None,
+      _ => match self.allocate(width, height) {
+        Some([x, y]) => {
+          for row in 0..height {
+            let source = (row * width) as usize;
+            let target = ((y + row) * self.size + x) as usize;
+            self.coverage[target..target + width as usize]
+              .copy_from_slice(&coverage[source..source + width as usize]);
+          }
+          self.mark_dirty([x, y, x + width, y + height]);
+          Some(Region::new(x as f32, y as f32, width as f32, height as f32))
+        }
+        None => {
+          warn!("The glyph atlas is full, skipping a glyph of {} pixels", f32::from_bits(key.size));
+          None
+        }
+      },
+    };
+    self.glyphs.insert(key, region);
+    region
+  }
+
+  /// the top left corner of a free area, the atlas grows if there is none
+  fn allocate(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+    let (width, height) = (width + GLYPH_PADDING, height + GLYPH_PADDING);
+    loop {
+      // the shortest shelf with room, so small glyphs don't waste tall shelves
+      let size = self.size;
+      let shelf = self
+        .shelves
+        .iter_mut()
+        .filter(|shelf| shelf.height >= height && size - shelf.x >= width)
+        .min_by_key(|shelf| shelf.height);
+      if let Some(shelf) = shelf {
+        let corner = [shelf.x, shelf.y];
+        shelf.x += width;
+        return Some(corner);
+      }
+
+      let y = self
+        .shelves
+        .last()
+        .map_or(GLYPH_PADDING, |shelf| shelf.y + shelf.height);
+      if y + height <= self.size && GLYPH_PADDING + width <= self.size {
+        self.shelves.push(Shelf {
+          y,
+          height,
+          x: GLYPH_PADDING + width,
+        });
+        return Some([GLYPH_PADDING, y]);
+      }
+
+      if !self.grow() {
+        return None;
+      }
+    }
+  }
+
+  /// doubles the size, the glyphs keep their place so only their uvs change
+  fn grow(&mut self) -> bool {
+    if self.size >= MAX_ATLAS_SIZE {
+      return false;
+    }
+
+    let size = self.size * 2;
+    let mut coverage = vec![0; (size * size) as usize];
+    for (row, pixels) in self.coverage.chunks_exact(self.size as usize).enumerate() {
+      let start = row * size as usize;
+      coverage[start..start + pixels.len()].copy_from_slice(pixels);
+    }
+    self.size = size;
+    self.coverage = coverage;
+    self.dirty = Some([0, 0, size, size]);
+    debug!("Glyph atlas grown to {}x{}", size, size);
+    true
+  }
+
+  fn mark_dirty(&mut self, [left, top, right, bottom]: [u32; 4]) {
+    self.dirty = Some(match self.dirty {
+      Some(dirty) => [
+        dirty[0].min(left),
+        dirty[1].min(top),
+        dirty[2].max(right),
+        dirty[3].max(bottom),
+      ],
+      None => [left, top, right, bottom],
+    });
+  }
+
+  /// white pixels with the coverage as alpha, to be drawn by the sprite pipeline
+  fn pixels(&self, columns: Range<u32>, rows: Range<u32>) -> Vec<u8> {
+    rows
+      .flat_map(|row| {
+        let start = (row * self.size) as usize;
+        &self.coverage[start + columns.start as usize..start + columns.end as usize]
+      })
+      .flat_map(|&alpha| [255, 255, 255, alpha])
+      .collect()
+  }
+
+  /// a texture of the whole atlas
+  fn create_texture(&self, device: &mut dyn GraphicsDevice) -> Result<Texture, GraphicsError> {
+    Texture::new(
+      device,
+      &TextureDesc {
+        width: self.size,
+        height: self.size,
+        format: TextureFormat::Rgba8Unorm,
+      },
+      &self.pixels(0..self.size, 0..self.size),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // the layout tests are skipped where this font isn't installed
+  const MONOSPACE: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf";
+
+  fn key(index: u16) -> GlyphKey {
+    GlyphKey {
+      font: FontId(0),
+      index,
+      size: 16f32.to_bits(),
+    }
+  }
+
+  fn add(atlas: &mut GlyphAtlas, index: u16, width: usize, height: usize) -> Option<Region> {
+    atlas.glyph(key(index), || {
+      let metrics = fontdue::Metrics {
+        width,
+        height,
+        ..Default::default()
+      };
+      (metrics, vec![255; width * height])
+    })
+  }
+
+  fn cached(atlas: &mut GlyphAtlas, index: u16) -> Option<Region> {
+    atlas.glyph(key(index), || panic!("glyph {} rasterized twice", index))
+  }
+
+  fn monospace() -> Option<fontdue::Font> {
+    Path::new(MONOSPACE)
+      .exists()
+      .then(|| Font::load(MONOSPACE).unwrap().font)
+  }
+
+  #[test]
+  fn glyphs_go_on_the_shortest_shelf_with_room() {
+    let mut atlas = GlyphAtlas::new();
+    assert_eq!(add(&mut atlas, 0, 240, 20), Some(Region::new(1.0, 1.0, 240.0, 20.0)));
+    // too wide for what is left of the first shelf
+    assert_eq!(add(&mut atlas, 1, 20, 8), Some(Region::new(1.0, 22.0, 20.0, 8.0)));
+    // both shelves have room, the second one is shorter
+    assert_eq!(add(&mut atlas, 2, 5, 4), Some(Region::new(22.0, 22.0, 5.0, 4.0)));
+    // only the first shelf is tall enough
+    assert_eq!(add(&mut atlas, 3, 5, 15), Some(Region::new(242.0, 1.0, 5.0, 15.0)));
+    assert_eq!(atlas.shelves.len(), 2);
+    assert_eq!(atlas.dirty, Some([1, 1, 247, 30]));
+
+    assert_eq!(cached(&mut atlas, 2), Some(Region::new(22.0, 22.0, 5.0, 4.0)));
+    assert_eq!(add(&mut atlas, 4, 0, 0), None);
+    assert_eq!(cached(&mut atlas, 4), None);
+  }
+
+  #[test]
+  fn a_full_atlas_grows_and_keeps_its_glyphs() {
+    let mut atlas = GlyphAtlas::new();
+    let first = add(&mut atlas, 0, 200, 200);
+    atlas.dirty = None;
+
+    assert_eq!(add(&mut atlas, 1, 100, 100), Some(Region::new(202.0, 1.0, 100.0, 100.0)));
+    assert_eq!(atlas.size, 512);
+    assert_eq!(atlas.coverage.len(), 512 * 512);
+    assert_eq!(atlas.dirty, Some([0, 0, 512, 512]));
+    assert_eq!(cached(&mut atlas, 0), first);
+    assert_eq!(atlas.coverage[512 + 1], 255);
+    assert_eq!(atlas.coverage[512 + 200], 255);
+    assert_eq!(atlas.coverage[512 + 201], 0);
+    assert_eq!(atlas.coverage[201 * 512 + 1], 0);
+  }
+
+  #[test]
+  fn glyphs_wider_than_the_largest_atlas_are_skipped() {
+    let mut atlas = GlyphAtlas::new();
+    assert_eq!(add(&mut atlas, 0, MAX_ATLAS_SIZE as usize, 1), None);
+    assert_eq!(atlas.size, MAX_ATLAS_SIZE);
+    assert_eq!(cached(&mut atlas, 0), None);
+    assert!(add(&mut atlas, 1, 8, 8).is_some());
+  }
+
+  #[test]
+  fn lines_wrap_at_whitespace() {
+    let Some(font) = monospace() else { return };
+    let advance = font.metrics('a', 16.0).advance_width;
+    let text = Text::new(FontId(0), "aaa bbb cc").with_max_width(advance * 6.5);
+
+    let lines = layout(&font, &text);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].glyphs.len(), 4);
+    assert_eq!(lines[0].width(), advance * 3.0);
+    assert_eq!(lines[1].glyphs.len(), 6);
+    assert_eq!(lines[1].glyphs[0].x, 0.0);
+    assert_eq!(lines[1].width(), advance * 6.0);
+  }
+
+  #[test]
+  fn words_wider_than_a_line_are_broken() {
+    let Some(font) = monospace() else { return };
+    let advance = font.metrics('a', 16.0).advance_width;
+    let text = Text::new(FontId(0), "aaaaaaaa").with_max_width(advance * 5.5);
+
+    let lines = layout(&font, &text);
+    let lengths: Vec<_> = lines.iter().map(|line| line.glyphs.len()).collect();
+    assert_eq!(lengths, [5, 3]);
+    assert_eq!(lines[1].glyphs[0].x, 0.0);
+    assert_eq!(lines[1].pen, advance * 3.0);
+  }
+
+  #[test]
+  fn line_breaks_start_new_lines() {
+    let Some(font) = monospace() else { return };
+    let lines = layout(&font, &Text::new(FontId(0), "ab\n\ncd ef"));
+    let lengths: Vec<_> = lines.iter().map(|line| line.glyphs.len()).collect();
+    assert_eq!(lengths, [2, 0, 5]);
+  }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::Path;
+
+use half::f16;
+use image::codecs::hdr::HdrDecoder;
+use image::{ColorType, DynamicImage, ImageFormat, ImageResult, Rgb32FImage};
+
+use crate::traits::{GraphicsDevice, GraphicsError, TextureDesc, TextureFormat, TextureHandle};
+
+/// A texture on the gpu, bound to pipelines through a descriptor set with a sampler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Texture {
+  handle: TextureHandle,
+  width: u32,
+  height: u32,
+  format: TextureFormat,
+}
+
+impl Texture {
+  /// Creates a texture from tightly packed rows of pixels.
+  pub fn new(
+    device: &mut dyn GraphicsDevice,
+    desc: &TextureDesc,
+    pixels: &[u8],
+  ) -> Result<Self, GraphicsError> {
+    let handle = device.create_texture(desc, Some(pixels))?;
+    Ok(Self {
+      handle,
+      width: desc.width,
+      height: desc.height,
+      format: desc.format,
+    })
+  }
+
+  /// Decodes a PNG, JPEG or HDR file, the format is picked from its content.
+  ///
+  /// `srgb` is for color images, data like normal maps should be read as is.
+  /// HDR images are always linear and stored as half floats.
+  pub fn load(
+    device: &mut dyn GraphicsDevice,
+    path: impl AsRef<Path>,
+    srgb: bool,
+  ) -> Result<Self, GraphicsError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| {
+      GraphicsError::InvalidImage(format!("failed to read {}: {}", path.display(), e))
+    })?;
+    let image = decode(&bytes)
+      .map_err(|e| GraphicsError::InvalidImage(format!("{}: {}", path.display(), e)))?;
+
+    Self::from_image(device, &image, srgb)
+  }
+
+  /// Decodes an image file that is already in memory, like `Texture::load`.
+  pub fn from_bytes(
+    device: &mut dyn GraphicsDevice,
+    bytes: &[u8],
+    srgb: bool,
+  ) -> Result<Self, GraphicsError> {
+    let image = decode(bytes).map_err(|e| GraphicsError::InvalidImage(e.to_string()))?;
+    Self::from_image(device, &image, srgb)
+  }
+
+  /// Uploads a decoded image, other pixel types are converted to 8 bit RGBA or half floats.
+  pub fn from_image(
+    device: &mut dyn GraphicsDevice,
+    image: &DynamicImage,
+    srgb: bool,
+  ) -> Result<Self, GraphicsError> {
+    let (format, pixels) = match image.color() {
+      ColorType::Rgb32F | ColorType::Rgba32F => {
+        let pixels = image
+          .to_rgba32f()
+          .into_raw()
+          .into_iter()
+          .flat_map(|channel| f16::from_f32(channel).to_ne_bytes())
+          .collect();
+        (TextureFormat::Rgba16Float, pixels)
+      }
+      _ if srgb => (TextureFormat::Rgba8Srgb, image.to_rgba8().into_raw()),
+      _ => (TextureFormat::Rgba8Unorm, image.to_rgba8().into_raw()),
+    };
+
+    Self::new(
+      device,
+      &TextureDesc {
+        width: image.width(),
+        height: image.height(),
+        format,
+      },
+      &pixels,
+    )
+  }
+
+  /// Overwrites the `size` pixels at `origin` with tightly packed rows of pixels.
+  pub fn write(
+    &self,
+    device: &mut dyn GraphicsDevice,
+    origin: [u32; 2],
+    size: [u32; 2],
+    pixels: &[u8],
+  ) -> Result<(), GraphicsError> {
+    device.write_texture(self.handle, origin, size, pixels)
+  }
+
+  /// Frees the gpu texture, descriptor sets using it must be destroyed first.
+  pub fn destroy(self, device: &mut dyn GraphicsDevice) {
+    device.destroy_texture(self.handle);
+  }
+
+  pub fn handle(&self) -> TextureHandle {
+    self.handle
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// The width and height in pixels.
+  pub fn size(&self) -> [u32; 2] {
+    [self.width, self.height]
+  }
+
+  pub fn format(&self) -> TextureFormat {
+    self.format
+  }
+}
+
+/// `image` turns HDR images into 8 bit ones, so they are read with their own decoder
+fn decode(bytes: &[u8]) -> ImageResult<DynamicImage> {
+  if image::guess_format(bytes)? != ImageFormat::Hdr {
+    return image::load_from_memory(bytes);
+  }
+
+  let decoder = HdrDecoder::new(bytes)?;
+  let metadata = decoder.metadata();
+  let pixels = decoder
+    .read_image_hdr()?
+    .into_iter()
+    .flat_map(|pixel| pixel.0)
+    .collect();
+  let image = Rgb32FImage::from_raw(metadata.width, metadata.height, pixels)
+    .expect("the decoder returns a pixel per texel");
+  Ok(DynamicImage::ImageRgb32F(image))
+}
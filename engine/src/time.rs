@@ -0,0 +1,120 @@
+/// Frame timing information.
+#[derive(Debug, Clone, Copy)]
+pub struct Time {
+  pub(crate) delta: f32,
+  pub(crate) elapsed: f64,
+  pub(crate) frame: u64,
+  fixed_delta: f32,
+  max_fixed_steps: u32,
+  accumulator: f64,
+  fixed_ticks: u64,
+}
+
+impl Default for Time {
+  fn default() -> Self {
+    Self::new(60, 5)
+  }
+}
+
+impl Time {
+  /// # Arguments
+  /// * `tick_rate` - How many fixed updates run per second.
+  /// * `max_fixed_steps` - The most fixed updates that can run in a single frame.
+  pub(crate) fn new(tick_rate: u32, max_fixed_steps: u32) -> Self {
+    Self {
+      delta: 0.0,
+      elapsed: 0.0,
+      frame: 0,
+      fixed_delta: 1.0 / tick_rate.max(1) as f32,
+      max_fixed_steps,
+      accumulator: 0.0,
+      fixed_ticks: 0,
+    }
+  }
+
+  /// The time in seconds since the last frame.
+  pub fn delta(&self) -> f32 {
+    self.delta
+  }
+
+  /// The time in seconds since the application started running.
+  pub fn elapsed(&self) -> f64 {
+    self.elapsed
+  }
+
+  /// The number of frames since the application started running, including the current one.
+  pub fn frame(&self) -> u64 {
+    self.frame
+  }
+
+  /// The time in seconds between two fixed updates.
+  pub fn fixed_delta(&self) -> f32 {
+    self.fixed_delta
+  }
+
+  /// The number of fixed updates since the application started running.
+  pub fn fixed_ticks(&self) -> u64 {
+    self.fixed_ticks
+  }
+
+  /// How far the current frame is between the last fixed update and the next one, in `[0, 1)`.
+  /// Used to interpolate between the previous and the current simulation state when rendering.
+  pub fn alpha(&self) -> f32 {
+    (self.accumulator / self.fixed_delta as f64) as f32
+  }
+
+  /// Starts a new frame.
+  ///
+  /// # Returns
+  /// The number of fixed updates that should run this frame.
+  pub(crate) fn advance(&mut self, delta: f32) -> u32 {
+    self.delta = delta;
+    self.elapsed += delta as f64;
+    self.frame += 1;
+
+    let fixed_delta = self.fixed_delta as f64;
+    self.accumulator += delta as f64;
+
+    let mut steps = (self.accumulator / fixed_delta) as u32;
+    if steps > self.max_fixed_steps {
+      // the simulation can't keep up, drop the time it's behind instead of
+      // running more and more steps each frame
+      steps = self.max_fixed_steps;
+      self.accumulator %= fixed_delta;
+    } else {
+      self.accumulator -= steps as f64 * fixed_delta;
+    }
+
+    steps
+  }
+
+  pub(crate) fn fixed_tick(&mut self) {
+    self.fixed_ticks += 1;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn advance_runs_the_fixed_steps_that_fit() {
+    let mut time = Time::new(4, 5);
+    assert_eq!(time.advance(0.1), 0);
+    assert_eq!(time.advance(0.4), 2);
+    assert!((time.alpha() - 0.0).abs() < 1e-5);
+    assert_eq!(time.frame(), 2);
+    assert!((time.elapsed() - 0.5).abs() < 1e-6);
+  }
+
+  #[test]
+  fn advance_clamps_a_long_frame() {
+    let mut time = Time::new(4, 5);
+    assert_eq!(time.advance(10.1), 5);
+    assert!((0.0..1.0).contains(&time.alpha()));
+
+    // the dropped time isn't caught up with on the next frames
+    assert_eq!(time.advance(0.25), 1);
+    assert_eq!(time.advance(0.25), 1);
+  }
+}
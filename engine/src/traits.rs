@@ -0,0 +1,876 @@
+//! The backend agnostic rendering interface.
+//!
+//! Resources are created through a `GraphicsDevice` and referred to by handles,
+//! a frame is recorded into a `CommandList` through the `CommandEncoder` trait
+//! and submitted to the device which replays it on its backend.
+//!
+//! Clip space is the same on every backend, y points up and depth goes from 0 to 1.
+
+use crate::backend::GegBackend;
+
+use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferHandle(pub(crate) u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(crate) u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerHandle(pub(crate) u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineHandle(pub(crate) u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderTargetHandle(pub(crate) u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DescriptorSetHandle(pub(crate) u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+  U16,
+  U32,
+}
+
+impl IndexFormat {
+  pub fn size(&self) -> u64 {
+    match self {
+      IndexFormat::U16 => 2,
+      IndexFormat::U32 => 4,
+    }
+  }
+}
+
+/// what a buffer is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+  Vertex,
+  Index(IndexFormat),
+  /// read by shaders through a descriptor set
+  Uniform,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferDesc {
+  /// size in bytes
+  pub size: u64,
+  pub usage: BufferUsage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+  Rgba8Unorm,
+  Rgba8Srgb,
+  R8Unorm,
+  /// half floats, for hdr images
+  Rgba16Float,
+}
+
+impl TextureFormat {
+  /// size of a single pixel in bytes
+  pub fn pixel_size(&self) -> u32 {
+    match self {
+      TextureFormat::Rgba8Unorm | TextureFormat::Rgba8Srgb => 4,
+      TextureFormat::R8Unorm => 1,
+      TextureFormat::Rgba16Float => 8,
+    }
+  }
+}
+
+/// checks that the `size` pixels at `origin` are inside of a texture of `dimensions` and
+/// that `data` holds all of them
+pub(crate) fn check_texture_write(
+  dimensions: [u32; 2],
+  format: TextureFormat,
+  origin: [u32; 2],
+  size: [u32; 2],
+  data: &[u8],
+) -> Result<(), GraphicsError> {
+  let inside =
+    (0..2).all(|i| matches!(origin[i].checked_add(size[i]), Some(end) if end <= dimensions[i]));
+  if !inside {
+    return Err(GraphicsError::InvalidUsage(format!(
+      "the {}x{} pixels at {:?} aren't inside of the {}x{} texture",
+      size[0], size[1], origin, dimensions[0], dimensions[1]
+    )));
+  }
+
+  let expected = size[0] as u64 * size[1] as u64 * format.pixel_size() as u64;
+  if data.len() as u64 != expected {
+    return Err(GraphicsError::InvalidSize {
+      expected,
+      actual: data.len() as u64,
+    });
+  }
+  Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureDesc {
+  pub width: u32,
+  pub height: u32,
+  pub format: TextureFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+  Nearest,
+  Linear,
+}
+
+/// what a sampler reads outside of the 0 to 1 texture coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressMode {
+  ClampToEdge,
+  Repeat,
+  MirrorRepeat,
+}
+
+/// how a texture is read by shaders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerDesc {
+  /// used when the texture is magnified
+  pub mag_filter: FilterMode,
+  /// used when the texture is minified
+  pub min_filter: FilterMode,
+  /// for the u and v coordinates
+  pub address_mode: [AddressMode; 2],
+  /// the max samples of anisotropic filtering, 1 turns it off
+  /// it's clamped to what the device supports and needs linear filtering
+  pub max_anisotropy: u8,
+}
+
+impl SamplerDesc {
+  /// smooth filtering, clamped to the edges
+  pub fn linear() -> Self {
+    Self {
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      address_mode: [AddressMode::ClampToEdge; 2],
+      max_anisotropy: 1,
+    }
+  }
+
+  /// sharp pixels, clamped to the edges
+  pub fn nearest() -> Self {
+    Self {
+      mag_filter: FilterMode::Nearest,
+      min_filter: FilterMode::Nearest,
+      ..Self::linear()
+    }
+  }
+
+  pub fn with_address_mode(self, address_mode: AddressMode) -> Self {
+    Self {
+      address_mode: [address_mode; 2],
+      ..self
+    }
+  }
+
+  pub fn with_anisotropy(self, max_anisotropy: u8) -> Self {
+    Self {
+      max_anisotropy,
+      ..self
+    }
+  }
+
+  /// `max_anisotropy` rounded down to 1, 2, 4, 8 or 16, or 1 without linear filtering
+  pub fn anisotropy(&self) -> u8 {
+    if self.mag_filter != FilterMode::Linear || self.min_filter != FilterMode::Linear {
+      return 1;
+    }
+    match self.max_anisotropy {
+      0..=1 => 1,
+      2..=3 => 2,
+      4..=7 => 4,
+      8..=15 => 8,
+      _ => 16,
+    }
+  }
+}
+
+impl Default for SamplerDesc {
+  fn default() -> Self {
+    Self::linear()
+  }
+}
+
+/// the color format of a render target, every pipeline can draw into both
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TargetFormat {
+  /// the format of the screen
+  #[default]
+  Screen,
+  /// 16 bit floats per channel, for colors brighter than 1 before they are tonemapped
+  Hdr,
+}
+
+/// an offscreen image that can be rendered into and then sampled as a texture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderTargetDesc {
+  pub width: u32,
+  pub height: u32,
+  pub format: TargetFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexFormat {
+  Float32,
+  Float32x2,
+  Float32x3,
+  Float32x4,
+  Uint32,
+  Unorm8x4,
+}
+
+impl VertexFormat {
+  pub fn size(&self) -> u32 {
+    match self {
+      VertexFormat::Float32 | VertexFormat::Uint32 | VertexFormat::Unorm8x4 => 4,
+      VertexFormat::Float32x2 => 8,
+      VertexFormat::Float32x3 => 12,
+      VertexFormat::Float32x4 => 16,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+  /// the `layout(location = ..)` in the shader
+  pub location: u32,
+  pub format: VertexFormat,
+  /// offset in bytes from the start of the vertex
+  pub offset: u32,
+}
+
+/// how often the shader moves to the next element of a vertex buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexStepMode {
+  Vertex,
+  Instance,
+}
+
+/// the layout of the vertices in a single vertex buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexLayout {
+  /// size of a vertex in bytes
+  pub stride: u32,
+  pub attributes: Vec<VertexAttribute>,
+  pub step_mode: VertexStepMode,
+}
+
+impl VertexLayout {
+  /// a layout with the attributes packed one after the other,
+  /// the locations follow the order of `formats`
+  pub fn packed(formats: &[VertexFormat]) -> Self {
+    let mut offset = 0;
+    let attributes = formats
+      .iter()
+      .enumerate()
+      .map(|(location, format)| {
+        let attribute = VertexAttribute {
+          location: location as u32,
+          format: *format,
+          offset,
+        };
+        offset += format.size();
+        attribute
+      })
+      .collect();
+
+    Self {
+      stride: offset,
+      attributes,
+      step_mode: VertexStepMode::Vertex,
+    }
+  }
+
+  /// the same layout read once per instance
+  pub fn per_instance(self) -> Self {
+    Self {
+      step_mode: VertexStepMode::Instance,
+      ..self
+    }
+  }
+
+  /// moves every attribute location by `offset`
+  pub fn with_location_offset(mut self, offset: u32) -> Self {
+    for attribute in &mut self.attributes {
+      attribute.location += offset;
+    }
+    self
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderSource {
+  SpirV(Vec<u32>),
+  Wgsl(String),
+  /// the stage comes from the pipeline slot the shader is used in
+  Glsl(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+  Vertex,
+  Fragment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderDesc {
+  pub source: ShaderSource,
+  pub entry_point: String,
+  /// the file the source was read from, pipelines created through
+  /// `GraphicsContext::create_pipeline` are rebuilt when it changes
+  pub path: Option<PathBuf>,
+}
+
+impl ShaderDesc {
+  pub fn spirv(words: Vec<u32>) -> Self {
+    Self {
+      source: ShaderSource::SpirV(words),
+      entry_point: "main".to_string(),
+      path: None,
+    }
+  }
+
+  pub fn wgsl(source: &str, entry_point: &str) -> Self {
+    Self {
+      source: ShaderSource::Wgsl(source.to_string()),
+      entry_point: entry_point.to_string(),
+      path: None,
+    }
+  }
+
+  pub fn glsl(source: &str) -> Self {
+    Self {
+      source: ShaderSource::Glsl(source.to_string()),
+      entry_point: "main".to_string(),
+      path: None,
+    }
+  }
+}
+
+/// what a descriptor set binding holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DescriptorType {
+  UniformBuffer,
+  /// a 2d float texture, `texture2D` in glsl and `texture_2d<f32>` in wgsl
+  Texture,
+  /// `sampler` in glsl and wgsl
+  Sampler,
+}
+
+/// a binding of a descriptor set, visible to every shader stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DescriptorBinding {
+  /// the `binding` in the shader
+  pub binding: u32,
+  pub ty: DescriptorType,
+}
+
+/// the bindings of a descriptor set, `set = n` in glsl and `@group(n)` in wgsl
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DescriptorSetLayout {
+  pub bindings: Vec<DescriptorBinding>,
+}
+
+impl DescriptorSetLayout {
+  /// a layout with a uniform buffer at each of the first `count` bindings
+  pub fn uniform_buffers(count: u32) -> Self {
+    Self {
+      bindings: (0..count)
+        .map(|binding| DescriptorBinding {
+          binding,
+          ty: DescriptorType::UniformBuffer,
+        })
+        .collect(),
+    }
+  }
+
+  /// a texture at binding 0 and its sampler at binding 1
+  pub fn texture() -> Self {
+    Self {
+      bindings: vec![
+        DescriptorBinding {
+          binding: 0,
+          ty: DescriptorType::Texture,
+        },
+        DescriptorBinding {
+          binding: 1,
+          ty: DescriptorType::Sampler,
+        },
+      ],
+    }
+  }
+}
+
+/// a resource bound to a descriptor set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorResource {
+  /// a whole buffer created with `BufferUsage::Uniform`
+  UniformBuffer(BufferHandle),
+  /// a texture or the texture of a render target
+  Texture(TextureHandle),
+  Sampler(SamplerHandle),
+}
+
+impl DescriptorResource {
+  pub fn ty(&self) -> DescriptorType {
+    match self {
+      DescriptorResource::UniformBuffer(_) => DescriptorType::UniformBuffer,
+      DescriptorResource::Texture(_) => DescriptorType::Texture,
+      DescriptorResource::Sampler(_) => DescriptorType::Sampler,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorEntry {
+  pub binding: u32,
+  pub resource: DescriptorResource,
+}
+
+/// the resources of a descriptor set, its layout follows from them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DescriptorSetDesc {
+  pub entries: Vec<DescriptorEntry>,
+}
+
+impl DescriptorSetDesc {
+  /// a set with `buffers` at the bindings following their order
+  pub fn uniform_buffers(buffers: &[BufferHandle]) -> Self {
+    Self {
+      entries: buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| DescriptorEntry {
+          binding: binding as u32,
+          resource: DescriptorResource::UniformBuffer(*buffer),
+        })
+        .collect(),
+    }
+  }
+
+  /// a set matching `DescriptorSetLayout::texture`
+  pub fn texture(texture: TextureHandle, sampler: SamplerHandle) -> Self {
+    Self {
+      entries: vec![
+        DescriptorEntry {
+          binding: 0,
+          resource: DescriptorResource::Texture(texture),
+        },
+        DescriptorEntry {
+          binding: 1,
+          resource: DescriptorResource::Sampler(sampler),
+        },
+      ],
+    }
+  }
+
+  pub fn layout(&self) -> DescriptorSetLayout {
+    DescriptorSetLayout {
+      bindings: self
+        .entries
+        .iter()
+        .map(|entry| DescriptorBinding {
+          binding: entry.binding,
+          ty: entry.resource.ty(),
+        })
+        .collect(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveTopology {
+  TriangleList,
+  TriangleStrip,
+  LineList,
+  PointList,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+  Opaque,
+  Alpha,
+  Additive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+  None,
+  Front,
+  Back,
+}
+
+/// how a pipeline uses the depth attachment of the pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+  /// draws over whatever is already there
+  Disabled,
+  /// keeps the closest fragments and writes their depth
+  ReadWrite,
+  /// hides what's behind the written depth without writing it, e.g. for transparent draws
+  ReadOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineDesc {
+  pub vertex_shader: ShaderDesc,
+  pub fragment_shader: ShaderDesc,
+  /// one layout per vertex buffer slot
+  pub vertex_layouts: Vec<VertexLayout>,
+  /// one layout per descriptor set index
+  pub descriptor_set_layouts: Vec<DescriptorSetLayout>,
+  pub topology: PrimitiveTopology,
+  pub blend: BlendMode,
+  pub cull_mode: CullMode,
+  pub depth: DepthMode,
+}
+
+/// pipelines every backend provides with its own shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinPipeline {
+  /// draws `ColorVertex` triangles without any transform
+  VertexColor,
+  /// draws `ColorVertex` meshes moved by a `MeshInstance` transform
+  /// and seen through the `CameraUniform` bound at set 0
+  Mesh,
+  /// draws alpha blended `SpriteVertex` quads without depth, seen through the
+  /// `CameraUniform` at set 0 and textured by the `DescriptorSetLayout::texture` at set 1
+  Sprite,
+  /// draws `ColorVertex` line lists seen through the `CameraUniform` at set 0,
+  /// hidden by the depth of the pass without writing it
+  Lines,
+  /// like `Lines` but drawn over everything
+  LinesOnTop,
+}
+
+/// the vertex used by `BuiltinPipeline::VertexColor`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Zeroable, Pod)]
+pub struct ColorVertex {
+  pub position: [f32; 3],
+  pub color: [f32; 3],
+}
+
+impl ColorVertex {
+  pub fn layout() -> VertexLayout {
+    VertexLayout::packed(&[VertexFormat::Float32x3, VertexFormat::Float32x3])
+  }
+}
+
+/// where a pass draws, the color is cleared to the pass clear color and the depth to 1
+/// every target has a depth attachment and the multisampling of the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassTarget {
+  /// the window, or the offscreen image when running headless
+  Screen,
+  Offscreen(RenderTargetHandle),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderCommand {
+  BeginPass {
+    target: PassTarget,
+    clear_color: [f32; 4],
+  },
+  EndPass,
+  SetPipeline(PipelineHandle),
+  SetVertexBuffer {
+    slot: u32,
+    buffer: BufferHandle,
+  },
+  SetIndexBuffer(BufferHandle),
+  SetDescriptorSet {
+    index: u32,
+    set: DescriptorSetHandle,
+  },
+  Draw {
+    vertices: Range<u32>,
+    instances: Range<u32>,
+  },
+  DrawIndexed {
+    indices: Range<u32>,
+    base_vertex: i32,
+    instances: Range<u32>,
+  },
+}
+
+/// records the draws of a frame
+pub trait CommandEncoder {
+  /// starts drawing into `target`, clearing it first
+  fn begin_pass(&mut self, target: PassTarget, clear_color: [f32; 4]);
+  fn end_pass(&mut self);
+  fn set_pipeline(&mut self, pipeline: PipelineHandle);
+  fn set_vertex_buffer(&mut self, slot: u32, buffer: BufferHandle);
+  fn set_index_buffer(&mut self, buffer: BufferHandle);
+  /// binds `set` at `index` of the pipeline's descriptor set layouts
+  fn set_descriptor_set(&mut self, index: u32, set: DescriptorSetHandle);
+  fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>);
+  fn draw_indexed(&mut self, indices: Range<u32>, base_vertex: i32, instances: Range<u32>);
+}
+
+/// a list of recorded commands, backends replay it when it's submitted
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandList {
+  commands: Vec<RenderCommand>,
+}
+
+impl CommandList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn commands(&self) -> &[RenderCommand] {
+    &self.commands
+  }
+
+  pub fn push(&mut self, command: RenderCommand) {
+    self.commands.push(command);
+  }
+
+  pub fn clear(&mut self) {
+    self.commands.clear();
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.commands.is_empty()
+  }
+
+  /// points the commands using `old` to `new`
+  pub(crate) fn replace_buffer(&mut self, old: BufferHandle, new: BufferHandle) {
+    for command in &mut self.commands {
+      match command {
+        RenderCommand::SetVertexBuffer { buffer, .. } | RenderCommand::SetIndexBuffer(buffer)
+          if *buffer == old =>
+        {
+          *buffer = new;
+        }
+        _ => (),
+      }
+    }
+  }
+
+  /// takes the end of the last screen pass and the commands after it off the list,
+  /// so more draws can be added to the pass before putting them back
+  /// `None` if no pass draws into the screen
+  pub(crate) fn reopen_screen_pass(&mut self) -> Option<Vec<RenderCommand>> {
+    let begin = self.commands.iter().rposition(|command| {
+      matches!(
+        command,
+        RenderCommand::BeginPass {
+          target: PassTarget::Screen,
+          ..
+        }
+      )
+    })?;
+    let end = self.commands[begin..]
+      .iter()
+      .position(|command| matches!(command, RenderCommand::EndPass))
+      .map_or(self.commands.len(), |end| begin + end);
+    Some(self.commands.split_off(end))
+  }
+
+  /// `true` if a pass draws into the screen
+  pub fn has_screen_pass(&self) -> bool {
+    self.commands.iter().any(|command| {
+      matches!(
+        command,
+        RenderCommand::BeginPass {
+          target: PassTarget::Screen,
+          ..
+        }
+      )
+    })
+  }
+}
+
+impl CommandEncoder for CommandList {
+  fn begin_pass(&mut self, target: PassTarget, clear_color: [f32; 4]) {
+    self.push(RenderCommand::BeginPass {
+      target,
+      clear_color,
+    });
+  }
+
+  fn end_pass(&mut self) {
+    self.push(RenderCommand::EndPass);
+  }
+
+  fn set_pipeline(&mut self, pipeline: PipelineHandle) {
+    self.push(RenderCommand::SetPipeline(pipeline));
+  }
+
+  fn set_vertex_buffer(&mut self, slot: u32, buffer: BufferHandle) {
+    self.push(RenderCommand::SetVertexBuffer { slot, buffer });
+  }
+
+  fn set_index_buffer(&mut self, buffer: BufferHandle) {
+    self.push(RenderCommand::SetIndexBuffer(buffer));
+  }
+
+  fn set_descriptor_set(&mut self, index: u32, set: DescriptorSetHandle) {
+    self.push(RenderCommand::SetDescriptorSet { index, set });
+  }
+
+  fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+    self.push(RenderCommand::Draw {
+      vertices,
+      instances,
+    });
+  }
+
+  fn draw_indexed(&mut self, indices: Range<u32>, base_vertex: i32, instances: Range<u32>) {
+    self.push(RenderCommand::DrawIndexed {
+      indices,
+      base_vertex,
+      instances,
+    });
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphicsError {
+  /// the shader couldn't be turned into a shader module
+  InvalidShader(String),
+  /// the backend can't create the pipeline
+  InvalidPipeline(String),
+  /// the handle was destroyed or belongs to another device
+  InvalidHandle,
+  /// the resource wasn't created for the way it's used
+  InvalidUsage(String),
+  /// the image couldn't be read or decoded
+  InvalidImage(String),
+  /// the font couldn't be read or parsed
+  InvalidFont(String),
+  /// the data doesn't match the size of the resource
+  InvalidSize { expected: u64, actual: u64 },
+  /// the frame couldn't be copied back to the cpu
+  Readback(String),
+  /// the backend failed to allocate or upload the resource
+  Device(String),
+}
+
+impl fmt::Display for GraphicsError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      GraphicsError::InvalidShader(e) => write!(f, "invalid shader: {}", e),
+      GraphicsError::InvalidPipeline(e) => write!(f, "invalid pipeline: {}", e),
+      GraphicsError::InvalidHandle => write!(f, "invalid handle"),
+      GraphicsError::InvalidUsage(e) => write!(f, "invalid usage: {}", e),
+      GraphicsError::InvalidImage(e) => write!(f, "invalid image: {}", e),
+      GraphicsError::InvalidFont(e) => write!(f, "invalid font: {}", e),
+      GraphicsError::InvalidSize { expected, actual } => {
+        write!(f, "expected {} bytes but got {}", expected, actual)
+      }
+      GraphicsError::Readback(e) => write!(f, "failed to read the frame back: {}", e),
+      GraphicsError::Device(e) => write!(f, "device error: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for GraphicsError {}
+
+/// a rendering backend, it owns the gpu resources and draws the submitted frames
+pub trait GraphicsDevice {
+  fn backend(&self) -> GegBackend;
+
+  /// the size of the screen target in pixels
+  fn surface_size(&self) -> [u32; 2];
+
+  /// the samples per pixel of every pass, 1 without msaa
+  fn msaa_samples(&self) -> u32;
+
+  /// called when the window is resized
+  fn resize(&mut self, width: u32, height: u32);
+
+  /// creates a buffer of `desc.size` bytes, filled with `data` if given
+  fn create_buffer(
+    &mut self,
+    desc: &BufferDesc,
+    data: Option<&[u8]>,
+  ) -> Result<BufferHandle, GraphicsError>;
+
+  /// overwrites the buffer content starting at `offset` bytes
+  fn write_buffer(
+    &mut self,
+    buffer: BufferHandle,
+    offset: u64,
+    data: &[u8],
+  ) -> Result<(), GraphicsError>;
+
+  fn destroy_buffer(&mut self, buffer: BufferHandle);
+
+  /// creates a texture, `data` is tightly packed rows of pixels, zeroed if not given
+  fn create_texture(
+    &mut self,
+    desc: &TextureDesc,
+    data: Option<&[u8]>,
+  ) -> Result<TextureHandle, GraphicsError>;
+
+  /// overwrites the `size` pixels at `origin`, `data` is tightly packed rows of pixels
+  /// the textures of render targets can't be written
+  fn write_texture(
+    &mut self,
+    texture: TextureHandle,
+    origin: [u32; 2],
+    size: [u32; 2],
+    data: &[u8],
+  ) -> Result<(), GraphicsError>;
+
+  /// the size of a texture in pixels
+  fn texture_size(&self, texture: TextureHandle) -> Option<[u32; 2]>;
+
+  fn destroy_texture(&mut self, texture: TextureHandle);
+
+  fn create_sampler(&mut self, desc: &SamplerDesc) -> Result<SamplerHandle, GraphicsError>;
+
+  fn destroy_sampler(&mut self, sampler: SamplerHandle);
+
+  fn create_pipeline(&mut self, desc: &PipelineDesc) -> Result<PipelineHandle, GraphicsError>;
+
+  /// the backend's own version of a builtin pipeline, created on first use
+  fn builtin_pipeline(&mut self, pipeline: BuiltinPipeline) -> PipelineHandle;
+
+  /// rebuilds a pipeline in place, the old one is kept if the new one can't be created
+  fn recreate_pipeline(
+    &mut self,
+    pipeline: PipelineHandle,
+    desc: &PipelineDesc,
+  ) -> Result<(), GraphicsError>;
+
+  fn destroy_pipeline(&mut self, pipeline: PipelineHandle);
+
+  fn create_render_target(
+    &mut self,
+    desc: &RenderTargetDesc,
+  ) -> Result<RenderTargetHandle, GraphicsError>;
+
+  /// the texture a render target draws into
+  fn render_target_texture(&self, target: RenderTargetHandle) -> Option<TextureHandle>;
+
+  fn destroy_render_target(&mut self, target: RenderTargetHandle);
+
+  /// binds resources for the shaders, the resources must outlive the set
+  fn create_descriptor_set(
+    &mut self,
+    desc: &DescriptorSetDesc,
+  ) -> Result<DescriptorSetHandle, GraphicsError>;
+
+  fn destroy_descriptor_set(&mut self, set: DescriptorSetHandle);
+
+  /// draws a frame and presents it, the screen is cleared if no pass draws into it
+  fn submit(&mut self, commands: &CommandList);
+
+  /// like `submit` but also copies the drawn screen back, it waits until the gpu is done
+  /// with the frame so it's meant for screenshots and tests, not every frame
+  fn submit_and_capture(&mut self, commands: &CommandList) -> Result<RgbaImage, GraphicsError>;
+}
@@ -0,0 +1,89 @@
+use glam::{DVec2, UVec2};
+use std::sync::Arc;
+use winit::{
+  dpi::PhysicalPosition,
+  window::{CursorGrabMode, Window},
+};
+
+use spdlog::prelude::*;
+
+/// The application window.
+/// When running headless there is no window and the cursor functions do nothing.
+pub struct GegWindow {
+  win: Option<Arc<Window>>,
+  title: String,
+  size: UVec2,
+}
+
+impl GegWindow {
+  pub(crate) fn new(win: Option<Arc<Window>>, title: String, size: UVec2) -> Self {
+    Self { win, title, size }
+  }
+
+  pub(crate) fn set_size(&mut self, size: UVec2) {
+    self.size = size;
+  }
+
+  pub(crate) fn winit_window(&self) -> Option<Arc<Window>> {
+    self.win.clone()
+  }
+
+  pub fn is_headless(&self) -> bool {
+    self.win.is_none()
+  }
+
+  pub fn title(&self) -> &str {
+    &self.title
+  }
+
+  pub fn set_title(&mut self, title: &str) {
+    self.title = title.to_string();
+    if let Some(win) = &self.win {
+      win.set_title(title);
+    }
+  }
+
+  /// The size of the drawable area in physical pixels.
+  pub fn size(&self) -> UVec2 {
+    self.size
+  }
+
+  pub fn scale_factor(&self) -> f64 {
+    self.win.as_ref().map_or(1.0, |win| win.scale_factor())
+  }
+
+  pub fn set_cursor_visible(&self, visible: bool) {
+    if let Some(win) = &self.win {
+      win.set_cursor_visible(visible);
+    }
+  }
+
+  /// Locks the cursor inside the window, useful for camera controls.
+  pub fn set_cursor_grab(&self, grab: bool) {
+    let win = match &self.win {
+      Some(win) => win,
+      None => return,
+    };
+
+    let result = if grab {
+      // not every platform supports both modes
+      win
+        .set_cursor_grab(CursorGrabMode::Locked)
+        .or_else(|_| win.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+      win.set_cursor_grab(CursorGrabMode::None)
+    };
+
+    if let Err(e) = result {
+      warn!("Failed to set cursor grab: {}", e);
+    }
+  }
+
+  pub fn set_cursor_position(&self, position: DVec2) {
+    if let Some(win) = &self.win {
+      if let Err(e) = win.set_cursor_position(PhysicalPosition::new(position.x, position.y)) {
+        warn!("Failed to set cursor position: {}", e);
+      }
+    }
+  }
+}
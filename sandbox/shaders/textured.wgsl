@@ -0,0 +1,42 @@
+// a mesh with a texture bound at set 1, next to the camera at set 0
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+struct Camera {
+  view_projection: mat4x4<f32>,
+  view: mat4x4<f32>,
+  projection: mat4x4<f32>,
+  position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var color_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var color_sampler: sampler;
+
+@vertex
+fn vs_main(
+  @location(0) position: vec3<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(12) transform_0: vec4<f32>,
+  @location(13) transform_1: vec4<f32>,
+  @location(14) transform_2: vec4<f32>,
+  @location(15) transform_3: vec4<f32>,
+) -> VertexOutput {
+  let transform = mat4x4<f32>(transform_0, transform_1, transform_2, transform_3);
+  var out: VertexOutput;
+  out.position = camera.view_projection * transform * vec4<f32>(position, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  return textureSample(color_texture, color_sampler, in.uv);
+}
@@ -1,25 +1,459 @@
 use geg::app::{GegApp, GegAppOptions};
+use geg::backend::GraphicsContext;
+use geg::camera::{Camera, CameraUniform};
+use geg::context::GegContext;
 use geg::events::GegEvent;
+use geg::input_map::{AxisBinding, Binding, InputMap};
 use geg::io::{Key, MouseButton, ModifiersState};
 use geg::layer::Layer;
+use geg::mesh::{Indices, Mesh, MeshDesc, MeshInstance};
+use geg::post_process::{Effect, PostStack, Tonemap};
+use geg::render_graph::{RenderGraph, TargetPool, TargetSize};
+use geg::shader::load_shader;
+use geg::sprite::{Sprite, SpriteBatch};
+use geg::text::{Align, Font, FontId, Text, TextRenderer, TextSpace};
+use geg::texture::Texture;
+use geg::traits::{
+  AddressMode, BlendMode, BuiltinPipeline, ColorVertex, CommandEncoder, CullMode, DepthMode,
+  DescriptorSetDesc, DescriptorSetHandle, DescriptorSetLayout, GraphicsError,
+  PipelineDesc, PipelineHandle, PrimitiveTopology, SamplerDesc, SamplerHandle, TextureDesc,
+  TargetFormat, TextureFormat, VertexFormat, VertexLayout,
+};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::time::Duration;
+
+const BINDINGS_FILE: &str = "bindings.ron";
+const MESH_SHADER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/mesh.wgsl");
+const TEXTURED_SHADER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/textured.wgsl");
+// the sandbox doesn't ship a font, the first one found is used
+const FONTS: [&str; 4] = [
+  "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+  "/usr/share/fonts/TTF/DejaVuSans.ttf",
+  "/System/Library/Fonts/Supplemental/Arial.ttf",
+  "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+fn default_bindings() -> InputMap {
+  let mut bindings = InputMap::new();
+  bindings.bind_action("jump", Binding::new(Key::Space));
+  bindings.bind_action(
+    "super_jump",
+    Binding::with_modifiers(
+      Key::Space,
+      ModifiersState {
+        ctrl: true,
+        ..Default::default()
+      },
+    ),
+  );
+  bindings.bind_axis(
+    "move_x",
+    AxisBinding::Digital {
+      negative: Key::A.into(),
+      positive: Key::D.into(),
+    },
+  );
+  bindings
+}
+
+fn mesh_pipeline_desc() -> Result<PipelineDesc, GraphicsError> {
+  Ok(PipelineDesc {
+    vertex_shader: load_shader(MESH_SHADER, "vs_main")?,
+    fragment_shader: load_shader(MESH_SHADER, "fs_main")?,
+    vertex_layouts: vec![ColorVertex::layout(), MeshInstance::layout()],
+    descriptor_set_layouts: vec![CameraUniform::layout()],
+    topology: PrimitiveTopology::TriangleList,
+    blend: BlendMode::Opaque,
+    cull_mode: CullMode::None,
+    depth: DepthMode::ReadWrite,
+  })
+}
+
+fn textured_pipeline_desc() -> Result<PipelineDesc, GraphicsError> {
+  Ok(PipelineDesc {
+    vertex_shader: load_shader(TEXTURED_SHADER, "vs_main")?,
+    fragment_shader: load_shader(TEXTURED_SHADER, "fs_main")?,
+    vertex_layouts: vec![
+      VertexLayout::packed(&[VertexFormat::Float32x3, VertexFormat::Float32x2]),
+      MeshInstance::layout(),
+    ],
+    descriptor_set_layouts: vec![CameraUniform::layout(), DescriptorSetLayout::texture()],
+    topology: PrimitiveTopology::TriangleList,
+    blend: BlendMode::Opaque,
+    cull_mode: CullMode::None,
+    depth: DepthMode::ReadWrite,
+  })
+}
+
+/// a checkerboard floor under the triangle
+struct Floor {
+  mesh: Mesh,
+  texture: Texture,
+  sampler: SamplerHandle,
+  set: DescriptorSetHandle,
+  pipeline: PipelineHandle,
+}
+
+impl Floor {
+  fn new(graphics: &mut GraphicsContext) -> Result<Self, GraphicsError> {
+    let pipeline = textured_pipeline_desc().and_then(|desc| graphics.create_pipeline(&desc))?;
+    let device = graphics.device();
+
+    let pixels: Vec<u8> = (0..8 * 8)
+      .flat_map(|i| match (i % 8 + i / 8) % 2 {
+        0 => [230, 230, 230, 255],
+        _ => [40, 40, 40, 255],
+      })
+      .collect();
+    let texture = Texture::new(
+      device,
+      &TextureDesc {
+        width: 8,
+        height: 8,
+        format: TextureFormat::Rgba8Srgb,
+      },
+      &pixels,
+    )?;
+    let sampler =
+      device.create_sampler(&SamplerDesc::nearest().with_address_mode(AddressMode::Repeat))?;
+    let set =
+      device.create_descriptor_set(&DescriptorSetDesc::texture(texture.handle(), sampler))?;
+
+    // position and uv, the texture repeats 4 times along each side
+    let vertices: [[f32; 5]; 4] = [
+      [-2.0, 0.0, -2.0, 0.0, 0.0],
+      [2.0, 0.0, -2.0, 4.0, 0.0],
+      [2.0, 0.0, 2.0, 4.0, 4.0],
+      [-2.0, 0.0, 2.0, 0.0, 4.0],
+    ];
+    let mesh = Mesh::new(
+      device,
+      &MeshDesc {
+        layout: VertexLayout::packed(&[VertexFormat::Float32x3, VertexFormat::Float32x2]),
+        vertices: bytemuck::cast_slice(&vertices),
+        indices: Some(Indices::U16(&[0, 1, 2, 0, 2, 3])),
+        submeshes: Vec::new(),
+      },
+    )?;
+
+    Ok(Self {
+      mesh,
+      texture,
+      sampler,
+      set,
+      pipeline,
+    })
+  }
+
+  fn draw(&self, graphics: &mut GraphicsContext) {
+    graphics.encoder().set_descriptor_set(1, self.set);
+    let transform = Mat4::from_translation(Vec3::new(0.0, -0.75, 0.0));
+    graphics.draw_mesh(&self.mesh, self.pipeline, transform);
+  }
+
+  fn destroy(self, graphics: &mut GraphicsContext) {
+    graphics.destroy_pipeline(self.pipeline);
+    let device = graphics.device();
+    device.destroy_descriptor_set(self.set);
+    device.destroy_sampler(self.sampler);
+    self.texture.destroy(device);
+    self.mesh.destroy(device);
+  }
+}
+
+/// a white disc fading out at the edges, tinted by the sprites
+fn create_dot(graphics: &mut GraphicsContext) -> Result<Texture, GraphicsError> {
+  let size = 16;
+  let pixels: Vec<u8> = (0..size * size)
+    .flat_map(|i| {
+      let offset = Vec2::new((i % size) as f32, (i / size) as f32) - (size as f32 - 1.0) / 2.0;
+      let alpha = (1.0 - offset.length() / (size as f32 / 2.0)).clamp(0.0, 1.0);
+      [255, 255, 255, (alpha * 255.0) as u8]
+    })
+    .collect();
+
+  Texture::new(
+    graphics.device(),
+    &TextureDesc {
+      width: size,
+      height: size,
+      format: TextureFormat::Rgba8Srgb,
+    },
+    &pixels,
+  )
+}
+
+fn load_font() -> Result<Font, GraphicsError> {
+  FONTS
+    .iter()
+    .find(|path| std::path::Path::new(path).exists())
+    .map_or_else(
+      || Err(GraphicsError::InvalidFont("no system font found".to_string())),
+      Font::load,
+    )
+}
+
+#[derive(Default)]
+struct ExampleLayer {
+  triangle: Option<(Mesh, PipelineHandle)>,
+  floor: Option<Floor>,
+  dots: Option<(SpriteBatch, Texture)>,
+  text: Option<(TextRenderer, FontId)>,
+  post: Option<PostStack>,
+  targets: TargetPool,
+}
+
+impl ExampleLayer {
+  /// Turns the post effect at `index` on or off.
+  fn toggle_effect(&mut self, index: usize) {
+    if let Some(effect) = self.post.as_mut().and_then(|post| post.effects_mut().get_mut(index)) {
+      effect.enabled = !effect.enabled;
+      geg::info!("{} {}", effect.effect.name(), if effect.enabled { "on" } else { "off" });
+    }
+  }
+
+  /// Switches between the ACES and Reinhard tonemapping.
+  fn switch_tonemap(&mut self) {
+    let effects = self.post.iter_mut().flat_map(|post| post.effects_mut());
+    for effect in effects {
+      if let Effect::Tonemap(tonemap) = &mut effect.effect {
+        *tonemap = match tonemap {
+          Tonemap::Aces => Tonemap::Reinhard,
+          Tonemap::Reinhard => Tonemap::Aces,
+        };
+        geg::info!("Tonemapping with {}", effect.effect.name());
+      }
+    }
+  }
+}
 
-struct ExampleLayer;
 impl Layer for ExampleLayer {
-  fn on_update(&mut self, _dt: f32) {}
+  fn on_attach(&mut self, ctx: &mut GegContext) {
+    let bindings = InputMap::load(BINDINGS_FILE).unwrap_or_else(|e| {
+      geg::warn!("Using the default bindings: {}", e);
+      default_bindings()
+    });
+    *ctx.input_map_mut() = bindings;
+
+    // brighter than the screen can show, the bloom spreads it around the triangle
+    let vertices = [
+      ColorVertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [4.0, 0.0, 0.0],
+      },
+      ColorVertex {
+        position: [0.5, -0.5, 0.0],
+        color: [0.0, 4.0, 0.0],
+      },
+      ColorVertex {
+        position: [0.0, 0.25, 0.0],
+        color: [0.0, 0.0, 4.0],
+      },
+    ];
+
+    let graphics = ctx.graphics();
+    graphics.set_camera(Camera::perspective(
+      std::f32::consts::FRAC_PI_3,
+      1.0,
+      0.1,
+      100.0,
+    ));
 
-  fn on_event(&mut self, event: GegEvent, modifiers: ModifiersState) -> bool {
+    let mesh = Mesh::new(
+      graphics.device(),
+      &MeshDesc {
+        layout: ColorVertex::layout(),
+        vertices: bytemuck::cast_slice(&vertices),
+        indices: Some(Indices::U16(&[0, 1, 2])),
+        submeshes: Vec::new(),
+      },
+    )
+    .expect("failed to create the triangle");
+    // the shader is reloaded on save, the builtin one is used if it doesn't compile
+    let pipeline = mesh_pipeline_desc()
+      .and_then(|desc| graphics.create_pipeline(&desc))
+      .unwrap_or_else(|e| {
+        geg::error!("Failed to create the mesh pipeline: {}", e);
+        graphics.device().builtin_pipeline(BuiltinPipeline::Mesh)
+      });
+    self.triangle = Some((mesh, pipeline));
+
+    self.floor = Floor::new(graphics)
+      .map_err(|e| geg::error!("Failed to create the floor: {}", e))
+      .ok();
+
+    self.dots = create_dot(graphics)
+      .and_then(|dot| Ok((SpriteBatch::new(graphics, &SamplerDesc::linear())?, dot)))
+      .map_err(|e| geg::error!("Failed to create the sprites: {}", e))
+      .ok();
+
+    self.text = load_font()
+      .and_then(|font| {
+        let mut text = TextRenderer::new(graphics)?;
+        let font = text.add_font(font);
+        Ok((text, font))
+      })
+      .map_err(|e| geg::warn!("Drawing without text: {}", e))
+      .ok();
+    if let Ok(font) = load_font() {
+      graphics.debug_draw().set_font(font);
+    }
+
+    self.post = PostStack::new(graphics)
+      .map_err(|e| geg::error!("Failed to create the post effects: {}", e))
+      .ok();
+  }
+
+  fn on_detach(&mut self, ctx: &mut GegContext) {
+    if let Some((mesh, pipeline)) = self.triangle.take() {
+      let graphics = ctx.graphics();
+      mesh.destroy(graphics.device());
+      graphics.destroy_pipeline(pipeline);
+    }
+    if let Some(floor) = self.floor.take() {
+      floor.destroy(ctx.graphics());
+    }
+    if let Some((batch, dot)) = self.dots.take() {
+      let device = ctx.graphics().device();
+      batch.destroy(device);
+      dot.destroy(device);
+    }
+    if let Some((text, _)) = self.text.take() {
+      text.destroy(ctx.graphics().device());
+    }
+    if let Some(post) = self.post.take() {
+      post.destroy(ctx.graphics().device());
+    }
+    std::mem::take(&mut self.targets).destroy(ctx.graphics().device());
+  }
+
+  fn on_update(&mut self, ctx: &mut GegContext, dt: f32) {
+    if ctx.was_action_pressed("super_jump") {
+      geg::info!("Super jump");
+    } else if ctx.was_action_pressed("jump") {
+      geg::info!("Jump");
+      ctx
+        .debug_draw()
+        .arrow(Vec3::ZERO, Vec3::Y, Vec3::new(1.0, 1.0, 0.0))
+        .lasting(Duration::from_secs(1));
+    }
+
+    let move_x = ctx.axis("move_x");
+    if move_x != 0.0 {
+      geg::trace!("Moving: {}", move_x);
+    }
+
+    if let Some((mesh, pipeline)) = &self.triangle {
+      let elapsed = ctx.time().elapsed() as f32;
+      let transform = Mat4::from_rotation_z(elapsed);
+      let graphics = ctx.graphics();
+
+      // orbits around the triangle
+      let eye = Vec3::new((elapsed * 0.5).sin() * 1.5, 0.5, (elapsed * 0.5).cos() * 1.5);
+      graphics.camera_mut().look_at(eye, Vec3::ZERO, Vec3::Y);
+
+      // queued before the scene pass records them
+      let debug = graphics.debug_draw();
+      debug.grid(Vec3::new(0.0, -0.75, 0.0), 4.0, 8, Vec3::splat(0.35));
+      debug.axes(transform, 0.4).on_top();
+      debug.text_3d(Vec3::new(0.0, -0.75, 0.0), "floor", Vec3::new(0.6, 0.9, 1.0));
+
+      // the scene is drawn in hdr and brought to the screen by the post effects
+      let mut graph = RenderGraph::new();
+      let screen = graph.screen();
+      let scene = match self.post {
+        Some(_) => graph.create_target_with_format("scene", TargetSize::Screen, TargetFormat::Hdr),
+        None => screen,
+      };
+      let floor = &self.floor;
+      let dots = &mut self.dots;
+      graph.add_pass("scene", scene).write_depth().record(move |graphics, _| {
+        graphics.draw_mesh(mesh, *pipeline, transform);
+        if let Some(floor) = floor {
+          floor.draw(graphics);
+        }
+
+        // a ring of dots over the scene, in pixels
+        if let Some((batch, dot)) = dots {
+          for i in 0..64 {
+            let angle = i as f32 / 64.0 * std::f32::consts::TAU + elapsed * 0.25;
+            let position = Vec2::from_angle(angle) * 200.0;
+            let tint = Vec4::new(angle.sin() * 0.5 + 0.5, 0.6, angle.cos() * 0.5 + 0.5, 0.8);
+            batch.draw(
+              Sprite::new(*dot)
+                .with_position(position)
+                .with_scale(Vec2::splat(1.5))
+                .with_tint(tint),
+            );
+          }
+          batch.render(graphics);
+        }
+      });
+      // tested against the depth of the scene, the post effects only copy its color
+      graph
+        .add_pass("debug lines", scene)
+        .read_depth()
+        .record(|graphics, _| graphics.render_debug_lines());
+
+      if let Some(post) = &mut self.post {
+        if let Err(e) = post.add_passes(graphics, &mut graph, scene, screen) {
+          geg::error!("Failed to add the post effects: {}", e);
+        }
+      }
+
+      // drawn over the post effects in the same pass
+      let text = &mut self.text;
+      graph.add_pass("ui", screen).record(move |graphics, _| {
+        if let Some((text, font)) = text {
+          *text.world_camera_mut() = graphics.camera().clone();
+          text.draw(
+            &Text::new(*font, "triangle")
+              .with_position(Vec2::new(0.0, 0.75))
+              .with_size(32.0)
+              .with_scale(0.005)
+              .with_align(Align::Center)
+              .with_space(TextSpace::World),
+          );
+
+          // a hud in the top left corner
+          let [width, height] = graphics.device().surface_size();
+          let corner = Vec2::new(width as f32 * -0.5 + 12.0, height as f32 * 0.5 - 12.0);
+          let hud = format!("{:.0} fps\n{:.1} s", 1.0 / dt.max(f32::EPSILON), elapsed);
+          text.draw(&Text::new(*font, &hud).with_position(corner).with_size(18.0));
+          text.draw(
+            &Text::new(
+              *font,
+              "Escape to exit, space to jump, the axis bindings to move, \
+               1 to 5 to toggle the effects and T to switch the tonemapping",
+            )
+              .with_position(Vec2::new(0.0, height as f32 * -0.5 + 60.0))
+              .with_max_width(260.0)
+              .with_align(Align::Center)
+              .with_color(Vec4::new(1.0, 0.85, 0.4, 1.0)),
+          );
+          text.render(graphics);
+        }
+      });
+
+      if let Err(e) = graph.execute(graphics, &mut self.targets) {
+        geg::error!("Failed to render the frame: {}", e);
+      }
+    }
+  }
+
+  fn on_event(&mut self, ctx: &mut GegContext, event: GegEvent, modifiers: ModifiersState) -> bool {
     match event {
       GegEvent::KeyDown(key) => match key {
         Key::Escape => {
-          geg::info!("Escape pressed");
-        }
-        Key::Space => {
-          if modifiers.ctrl {
-            geg::info!("Ctrl + Space pressed");
-          } else {
-            geg::info!("Space pressed");
-          }
+          geg::info!("Escape pressed, exiting");
+          ctx.request_exit();
         }
+        Key::Key1 => self.toggle_effect(0),
+        Key::Key2 => self.toggle_effect(1),
+        Key::Key3 => self.toggle_effect(2),
+        Key::Key4 => self.toggle_effect(3),
+        Key::Key5 => self.toggle_effect(4),
+        Key::T => self.switch_tonemap(),
         _ => (),
       },
 
@@ -57,11 +491,17 @@ impl Layer for ExampleLayer {
 fn main() {
   geg::init();
 
-  let opts = GegAppOptions::default();
+  let mut opts = GegAppOptions::default();
+  #[cfg(feature = "wgpu")]
+  if std::env::args().any(|arg| arg == "--wgpu") {
+    opts.backend = geg::backend::GegBackend::Wgpu;
+  }
+  opts.graphics.msaa_samples = 4;
+  opts.screenshot_key = Some(Key::F12);
   let mut app = GegApp::new(opts);
 
-  let layer = Box::new(ExampleLayer);
-  app.add_layer(layer);
+  let layer = Box::new(ExampleLayer::default());
+  app.push_layer(layer);
 
   geg::info!("Starting app");
   app.run();